@@ -1,5 +1,5 @@
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write, Seek, SeekFrom};
+use std::io::{self, Read, Write, Seek, SeekFrom};
 use crate::layout::BLOCK_SIZE;
 
 pub struct BlockDevice {
@@ -7,14 +7,19 @@ pub struct BlockDevice {
 }
 
 impl BlockDevice {
-    pub fn open(path: &str) -> Self {
+    pub fn open(path: &str) -> io::Result<Self> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(path)
-            .unwrap();
-        BlockDevice { file }
+            .truncate(false)
+            .open(path)?;
+        Ok(BlockDevice { file })
+    }
+
+    // 镜像文件当前包含的完整块数
+    pub fn len_blocks(&self) -> u64 {
+        self.file.metadata().map(|m| m.len() / BLOCK_SIZE as u64).unwrap_or(0)
     }
 
     pub fn read_block(&mut self, block_idx: u32, buf: &mut [u8; BLOCK_SIZE]) {
@@ -26,4 +31,9 @@ impl BlockDevice {
         self.file.seek(SeekFrom::Start(block_idx as u64 * BLOCK_SIZE as u64)).unwrap();
         self.file.write_all(buf).unwrap();
     }
+
+    // 把镜像文件调整为 blocks 个块的大小
+    pub fn set_len(&mut self, blocks: u32) {
+        self.file.set_len(blocks as u64 * BLOCK_SIZE as u64).unwrap();
+    }
 }
\ No newline at end of file
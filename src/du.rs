@@ -0,0 +1,115 @@
+use crate::fs::{Metadata, MyFileSystem};
+use crate::json::{self, Json};
+use crate::layout::{BLOCK_SIZE, InodeType};
+use crate::{EXIT_FAILURE, fail, human_size, to_absolute_path, usage};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+// du [-s] [-h] [--apparent] [path...]
+// 默认统计实际分配的块：Inode 中的直接块、间接块指向的数据块，以及间接块本身，
+// 文件中的空洞不占块；--apparent 改为累加逻辑大小 size。
+// 每个目录输出一行（后序，子目录在前），-s 只输出每个参数的合计；
+// 有多个硬链接的 Inode 只计一次，符号链接不跟随
+// JSON 模式下输出 [{"path":..,"bytes":..}]，bytes 总是字节数
+
+const DU_USAGE: &str = "du [-s] [-h] [--apparent] [path...]";
+
+pub fn run_du(fs: &mut MyFileSystem, args: &[&str], out: &mut Vec<u8>) -> i32 {
+    let mut summarize = false;
+    let mut human = false;
+    let mut apparent = false;
+    let mut paths = Vec::new();
+    for &arg in args {
+        match arg {
+            "-s" => summarize = true,
+            "-h" => human = true,
+            "--apparent" | "--apparent-size" => apparent = true,
+            "-sh" | "-hs" => {
+                summarize = true;
+                human = true;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => return usage(DU_USAGE),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(".");
+    }
+
+    // 一个条目计入的字节数
+    let usage_of = |meta: &Metadata| {
+        if apparent {
+            meta.size as u64
+        } else {
+            (meta.allocated_blocks() * BLOCK_SIZE) as u64
+        }
+    };
+    // 默认以 KiB 为单位（向上取整），-h 自动选择单位
+    let format = |bytes: u64| {
+        if human {
+            human_size(bytes)
+        } else {
+            bytes.div_ceil(1024).to_string()
+        }
+    };
+
+    // 输出一行；JSON 模式下先收集起来
+    let mut results = Vec::new();
+    let mut report = |out: &mut Vec<u8>, bytes: u64, path: &str| {
+        if json::enabled() {
+            results.push(Json::object([
+                ("path", path.into()),
+                ("bytes", bytes.into()),
+            ]));
+        } else {
+            writeln!(out, "{}\t{}", format(bytes), path).unwrap();
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let mut status = 0;
+    for raw_path in paths {
+        let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+        // 后序遍历：目录在它的全部子条目之后出现，这时它的合计已经完整
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        let mut root_total = None;
+        for entry in fs.walk(&abs_path).contents_first(true) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    status = fail("du", raw_path, e);
+                    continue;
+                }
+            };
+            let own = if entry.meta.nlinks > 1 && entry.meta.kind != InodeType::Directory {
+                if seen.insert(entry.meta.ino) {
+                    usage_of(&entry.meta)
+                } else {
+                    0
+                }
+            } else {
+                usage_of(&entry.meta)
+            };
+            let total = own + totals.remove(&entry.path).unwrap_or(0);
+
+            if entry.path == abs_path {
+                root_total = Some(total);
+                continue;
+            }
+            if entry.meta.kind == InodeType::Directory && !summarize {
+                let rel = entry.path[abs_path.len()..].trim_start_matches('/');
+                let shown = format!("{}/{}", raw_path.trim_end_matches('/'), rel);
+                report(out, total, &shown);
+            }
+            let parent = MyFileSystem::split_path(&entry.path).0.to_string();
+            *totals.entry(parent).or_insert(0) += total;
+        }
+        if let Some(total) = root_total {
+            report(out, total, raw_path);
+        }
+    }
+    if json::enabled() {
+        writeln!(out, "{}", Json::Array(results)).unwrap();
+    }
+    if status != 0 { EXIT_FAILURE } else { 0 }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::io;
+
+// 文件系统 API 的错误类型，Display 输出与命令行提示保持一致
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyExists,
+    NotEmpty,
+    InvalidArgument,
+    Busy,
+    NoSpace,
+    FileTooLarge,
+    TooManyLinks,
+    ReadOnly,
+    // 磁盘上的数据结构损坏（如无法解压的压缩簇）
+    Corrupted,
+    // 宿主机文件系统上的 I/O 错误（import/export 等）
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            FsError::NotFound => "No such file or directory",
+            FsError::NotADirectory => "Not a directory",
+            FsError::IsADirectory => "Is a directory",
+            FsError::AlreadyExists => "File exists",
+            FsError::NotEmpty => "Directory not empty",
+            FsError::InvalidArgument => "Invalid argument",
+            FsError::Busy => "Device or resource busy",
+            FsError::NoSpace => "No space left on device",
+            FsError::FileTooLarge => "File too large",
+            FsError::TooManyLinks => "Too many levels of symbolic links",
+            FsError::ReadOnly => "Read-only file system",
+            FsError::Corrupted => "Structure needs cleaning",
+            FsError::Io(kind) => return write!(f, "Host I/O error: {}", kind),
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for FsError {}
+
+impl From<io::Error> for FsError {
+    fn from(e: io::Error) -> Self {
+        FsError::Io(e.kind())
+    }
+}
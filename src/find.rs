@@ -0,0 +1,416 @@
+use crate::fs::{Metadata, MyFileSystem};
+use crate::glob::fnmatch;
+use crate::json::{self, Json};
+use crate::layout::InodeType;
+use crate::walk::WalkEntry;
+use crate::{EXIT_FAILURE, EXIT_USAGE, fail, report, to_absolute_path};
+use std::io::Write;
+
+// find [path...] [expression]
+// 表达式由测试（-name -iname -type -size -mtime -inum -empty）、动作（-print -delete -exec）
+// 和运算符（! -not、-a -and 或直接相邻、-o -or、括号）组成；没有动作时对匹配的条目执行 -print
+// 先用 walk 收集所有条目再逐个求值，这样 -delete 和 -exec 修改目录树不会影响遍历
+// JSON 模式下 -print 不逐行输出，最后输出 [{"path":..,"ino":..,"type":..,"size":..}]
+
+// -size / -mtime / -inum 的数值比较：+N 大于，-N 小于，N 等于
+#[derive(Debug, Clone, Copy)]
+enum Cmp {
+    Less(u64),
+    More(u64),
+    Exactly(u64),
+}
+
+impl Cmp {
+    fn parse(s: &str) -> Option<Cmp> {
+        if let Some(n) = s.strip_prefix('+') {
+            n.parse().ok().map(Cmp::More)
+        } else if let Some(n) = s.strip_prefix('-') {
+            n.parse().ok().map(Cmp::Less)
+        } else {
+            s.parse().ok().map(Cmp::Exactly)
+        }
+    }
+
+    fn test(self, value: u64) -> bool {
+        match self {
+            Cmp::Less(n) => value < n,
+            Cmp::More(n) => value > n,
+            Cmp::Exactly(n) => value == n,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    True,
+    Name {
+        pattern: String,
+        ignore_case: bool,
+    },
+    Type(InodeType),
+    Size {
+        cmp: Cmp,
+        unit: u64,
+    },
+    Mtime(Cmp),
+    Inum(Cmp),
+    Empty,
+    Print,
+    Delete,
+    // batch 为 Some 时是 -exec ... {} + 形式，路径先收集起来最后一次执行
+    Exec {
+        argv: Vec<String>,
+        batch: Option<usize>,
+    },
+}
+
+struct Parser<'a> {
+    args: &'a [String],
+    pos: usize,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    has_action: bool,
+    delete: bool,
+    batches: Vec<Vec<String>>, // 每个 -exec ... + 的命令模板
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.pos).map(String::as_str)
+    }
+
+    fn value(&mut self, opt: &str) -> Result<&'a str, String> {
+        let value = self
+            .args
+            .get(self.pos)
+            .ok_or_else(|| format!("missing argument to '{}'", opt))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_and()?;
+        while matches!(self.peek(), Some("-o" | "-or")) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                None | Some("-o" | "-or" | ")") => return Ok(lhs),
+                Some("-a" | "-and") => self.pos += 1,
+                _ => {}
+            }
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.peek() else {
+            return Err("expected an expression".to_string());
+        };
+        self.pos += 1;
+        match token {
+            "!" | "-not" => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            "(" => {
+                let expr = self.parse_or()?;
+                if self.peek() != Some(")") {
+                    return Err("missing ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            _ => self.parse_primary(token),
+        }
+    }
+
+    fn parse_primary(&mut self, token: &str) -> Result<Expr, String> {
+        let expr = match token {
+            "-name" | "-iname" => Expr::Name {
+                pattern: self.value(token)?.to_string(),
+                ignore_case: token == "-iname",
+            },
+            "-type" => Expr::Type(match self.value(token)? {
+                "f" => InodeType::File,
+                "d" => InodeType::Directory,
+                "l" => InodeType::Symlink,
+                t => return Err(format!("unknown type '{}' (expected f, d or l)", t)),
+            }),
+            "-size" => {
+                // 单位：c 字节，k KiB，M MiB，G GiB，省略时为 512 字节的块；大小向上取整到单位
+                let value = self.value(token)?;
+                let (number, unit) = match value.char_indices().last() {
+                    Some((i, 'c')) => (&value[..i], 1),
+                    Some((i, 'k')) => (&value[..i], 1 << 10),
+                    Some((i, 'M')) => (&value[..i], 1 << 20),
+                    Some((i, 'G')) => (&value[..i], 1 << 30),
+                    _ => (value, 512),
+                };
+                let cmp = Cmp::parse(number).ok_or_else(|| format!("invalid size '{}'", value))?;
+                Expr::Size { cmp, unit }
+            }
+            "-mtime" | "-inum" => {
+                let value = self.value(token)?;
+                let cmp = Cmp::parse(value)
+                    .ok_or_else(|| format!("invalid argument '{}' to '{}'", value, token))?;
+                if token == "-mtime" {
+                    Expr::Mtime(cmp)
+                } else {
+                    Expr::Inum(cmp)
+                }
+            }
+            "-maxdepth" | "-mindepth" => {
+                let value = self.value(token)?;
+                let depth = value
+                    .parse()
+                    .map_err(|_| format!("invalid depth '{}'", value))?;
+                if token == "-maxdepth" {
+                    self.max_depth = Some(depth);
+                } else {
+                    self.min_depth = depth;
+                }
+                Expr::True
+            }
+            "-empty" => Expr::Empty,
+            "-true" => Expr::True,
+            "-false" => Expr::Not(Box::new(Expr::True)),
+            "-print" => {
+                self.has_action = true;
+                Expr::Print
+            }
+            "-delete" => {
+                self.has_action = true;
+                self.delete = true;
+                Expr::Delete
+            }
+            "-exec" => {
+                self.has_action = true;
+                let start = self.pos;
+                let end = self.args[start..]
+                    .iter()
+                    .position(|a| a == ";" || a == "+")
+                    .map(|i| start + i)
+                    .ok_or("missing ';' or '+' after -exec")?;
+                self.pos = end + 1;
+                let argv = self.args[start..end].to_vec();
+                if argv.is_empty() {
+                    return Err("-exec requires a command".to_string());
+                }
+                let batch = if self.args[end] == "+" {
+                    // {} + 形式中 {} 必须是最后一个参数
+                    if argv.last().map(String::as_str) != Some("{}") {
+                        return Err("'{}' must come right before '+' in -exec".to_string());
+                    }
+                    self.batches.push(argv.clone());
+                    Some(self.batches.len() - 1)
+                } else {
+                    None
+                };
+                Expr::Exec { argv, batch }
+            }
+            _ => return Err(format!("unknown predicate '{}'", token)),
+        };
+        Ok(expr)
+    }
+}
+
+// 执行 -exec 的命令：参数中的 {} 替换为路径，输出写入 out，返回退出状态
+pub type ExecFn<'a> = dyn FnMut(&mut MyFileSystem, &[String], &mut Vec<u8>) -> i32 + 'a;
+
+struct Found {
+    path: String, // 输出中的写法，以命令行上给出的路径开头
+    abs_path: String,
+    meta: Metadata,
+}
+
+struct Eval<'a, 'b> {
+    fs: &'a mut MyFileSystem,
+    out: &'a mut Vec<u8>,
+    exec: &'a mut ExecFn<'b>,
+    now: u64,
+    pending: Vec<Vec<String>>, // 每个 -exec ... + 收集到的路径
+    printed: Vec<Json>,        // JSON 模式下 -print 的条目
+    status: i32,
+}
+
+impl Eval<'_, '_> {
+    fn eval(&mut self, expr: &Expr, found: &Found) -> bool {
+        match expr {
+            Expr::And(a, b) => self.eval(a, found) && self.eval(b, found),
+            Expr::Or(a, b) => self.eval(a, found) || self.eval(b, found),
+            Expr::Not(e) => !self.eval(e, found),
+            Expr::True => true,
+            Expr::Name {
+                pattern,
+                ignore_case,
+            } => {
+                let name = match found.path.trim_end_matches('/').rsplit('/').next() {
+                    Some("") | None => "/",
+                    Some(name) => name,
+                };
+                if *ignore_case {
+                    fnmatch(&pattern.to_lowercase(), &name.to_lowercase())
+                } else {
+                    fnmatch(pattern, name)
+                }
+            }
+            Expr::Type(kind) => found.meta.kind == *kind,
+            Expr::Size { cmp, unit } => cmp.test((found.meta.size as u64).div_ceil(*unit)),
+            Expr::Mtime(cmp) => cmp.test(self.now.saturating_sub(found.meta.mtime) / 86400),
+            Expr::Inum(cmp) => cmp.test(found.meta.ino as u64),
+            Expr::Empty => match found.meta.kind {
+                InodeType::Directory => self
+                    .fs
+                    .read_dir(&found.abs_path)
+                    .is_ok_and(|entries| entries.is_empty()),
+                InodeType::File => found.meta.size == 0,
+                _ => false,
+            },
+            Expr::Print => {
+                if json::enabled() {
+                    self.printed.push(Json::object([
+                        ("path", found.path.as_str().into()),
+                        ("ino", found.meta.ino.into()),
+                        ("type", json::kind_name(found.meta.kind).into()),
+                        ("size", found.meta.size.into()),
+                    ]));
+                } else {
+                    writeln!(self.out, "{}", found.path).unwrap();
+                }
+                true
+            }
+            Expr::Delete => match self.fs.remove(&found.abs_path, false) {
+                Ok(()) => true,
+                Err(e) => {
+                    self.status = fail("find", &found.path, e);
+                    false
+                }
+            },
+            Expr::Exec { argv, batch } => {
+                if let Some(id) = batch {
+                    self.pending[*id].push(found.path.clone());
+                    return true;
+                }
+                let argv: Vec<String> = argv.iter().map(|a| a.replace("{}", &found.path)).collect();
+                (self.exec)(self.fs, &argv, self.out) == 0
+            }
+        }
+    }
+}
+
+// 输出中的路径：起点按命令行上的写法，其下的条目接上相对部分
+fn display_path(raw_root: &str, abs_root: &str, abs_path: &str) -> String {
+    let rel = abs_path[abs_root.len()..].trim_start_matches('/');
+    if rel.is_empty() {
+        raw_root.to_string()
+    } else {
+        format!("{}/{}", raw_root.trim_end_matches('/'), rel)
+    }
+}
+
+pub fn run_find(
+    fs: &mut MyFileSystem,
+    args: &[String],
+    out: &mut Vec<u8>,
+    exec: &mut ExecFn,
+) -> i32 {
+    // 第一个以 - 开头的参数、! 或 ( 之前的都是起点
+    let split = args
+        .iter()
+        .position(|a| (a.starts_with('-') && a.len() > 1) || a == "!" || a == "(")
+        .unwrap_or(args.len());
+    let roots: Vec<String> = if split == 0 {
+        vec![".".to_string()]
+    } else {
+        args[..split].to_vec()
+    };
+
+    let mut parser = Parser {
+        args: &args[split..],
+        pos: 0,
+        min_depth: 0,
+        max_depth: None,
+        has_action: false,
+        delete: false,
+        batches: Vec::new(),
+    };
+    let parsed = if parser.args.is_empty() {
+        Ok(Expr::True)
+    } else {
+        parser.parse_or().and_then(|expr| match parser.peek() {
+            Some(token) => Err(format!("unexpected '{}'", token)),
+            None => Ok(expr),
+        })
+    };
+    let mut expr = match parsed {
+        Ok(expr) => expr,
+        Err(msg) => {
+            report("find", None, &msg);
+            return EXIT_USAGE;
+        }
+    };
+    if !parser.has_action {
+        expr = Expr::And(Box::new(expr), Box::new(Expr::Print));
+    }
+
+    let now = fs.now();
+    let mut eval = Eval {
+        fs,
+        out,
+        exec,
+        now,
+        pending: vec![Vec::new(); parser.batches.len()],
+        printed: Vec::new(),
+        status: 0,
+    };
+    for raw_root in &roots {
+        let abs_root = to_absolute_path(&eval.fs.cwd_path, raw_root);
+        let mut walk = eval
+            .fs
+            .walk(&abs_root)
+            .min_depth(parser.min_depth)
+            .contents_first(parser.delete);
+        if let Some(depth) = parser.max_depth {
+            walk = walk.max_depth(depth);
+        }
+        let entries: Vec<_> = walk.collect();
+        for entry in entries {
+            match entry {
+                Ok(WalkEntry { path, meta }) => {
+                    let found = Found {
+                        path: display_path(raw_root, &abs_root, &path),
+                        abs_path: path,
+                        meta,
+                    };
+                    eval.eval(&expr, &found);
+                }
+                Err(e) => eval.status = fail("find", raw_root, e),
+            }
+        }
+    }
+
+    // -exec ... {} + 把收集到的所有路径放在一条命令中执行
+    for (template, paths) in parser.batches.iter().zip(std::mem::take(&mut eval.pending)) {
+        if paths.is_empty() {
+            continue;
+        }
+        let mut argv = template[..template.len() - 1].to_vec();
+        argv.extend(paths);
+        if (eval.exec)(eval.fs, &argv, eval.out) != 0 {
+            eval.status = EXIT_FAILURE;
+        }
+    }
+    if json::enabled() {
+        writeln!(eval.out, "{}", Json::Array(eval.printed)).unwrap();
+    }
+    eval.status
+}
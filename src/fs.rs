@@ -1,41 +1,287 @@
 use crate::device::BlockDevice;
+use crate::error::FsError;
 use crate::layout::*;
-use std::io::Read;
+use crate::lz;
+use crate::walk::Walk;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 把任意字符串种子展开成 16 字节的 UUID（FNV-1a + splitmix64），同一种子总是得到同一个 UUID
+pub fn uuid_from_seed(seed: &str) -> [u8; 16] {
+    let mut state = seed.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    let mut uuid = [0u8; 16];
+    uuid[..8].copy_from_slice(&next().to_le_bytes());
+    uuid[8..].copy_from_slice(&next().to_le_bytes());
+    // 按 RFC 4122 标记为版本 4、变体 1
+    uuid[6] = (uuid[6] & 0x0f) | 0x40;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    uuid
+}
+
+// 数据块内容的 FNV-1a 哈希，去重时用来找候选块，合并前仍然逐字节比较
+fn block_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325u64, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// 没有指定种子时，用系统随机的哈希键生成 UUID
+fn random_uuid() -> [u8; 16] {
+    let seed = format!(
+        "{:x}{:x}",
+        RandomState::new().build_hasher().finish(),
+        RandomState::new().build_hasher().finish()
+    );
+    uuid_from_seed(&seed)
+}
+
+// 快照中的 Inode 编号：高 16 位是快照槽位加一，低 16 位是快照内的 Inode 编号，
+// 这样同一个 Inode 在实时文件系统和各个快照中的编号互不相同（Inode 数不超过 32768）
+// 根目录下的 .snapshots 是一个虚拟目录，列出所有快照的根
+const SNAPSHOT_DIR_INO: u32 = u32::MAX;
+
+fn snapshot_ino(slot: usize, ino: u32) -> u32 {
+    ((slot as u32 + 1) << 16) | ino
+}
+
+// 拆分 Inode 编号：(快照槽位, 快照内的编号)，实时文件系统的槽位为 None
+fn split_ino(ino: u32) -> (Option<usize>, u32) {
+    match ino >> 16 {
+        0 => (None, ino),
+        slot => (Some(slot as usize - 1), ino & 0xffff),
+    }
+}
+
+// 快照（包括 .snapshots 目录本身）只能读取
+fn check_writable(ino: u32) -> Result<(), FsError> {
+    if ino >> 16 != 0 {
+        Err(FsError::ReadOnly)
+    } else {
+        Ok(())
+    }
+}
+
+// 引用计数表每块记录的块数
+const REFS_PER_BLOCK: usize = BLOCK_SIZE / 4;
+// 引用计数索引块最后 4 字节记录额外引用数不为 0 的块数，减到 0 时整套表都可以释放。
+// 块数不超过一个位图块能表示的范围，表块最多 32 个，不会用到索引块末尾
+const SHARED_COUNT_OFFSET: usize = BLOCK_SIZE - 4;
+
+// statfs 返回的空间统计
+pub struct StatFs {
+    pub block_size: u32,
+    pub total_blocks: u32,
+    pub free_blocks: u32,
+    pub total_inodes: u32,
+    pub free_inodes: u32,
+    pub uuid: [u8; 16],
+}
+
+// dedup 的结果：检查的数据块指针数、合并的指针数、回到空闲位图的块数
+pub struct DedupReport {
+    pub scanned: usize,
+    pub merged: usize,
+    pub reclaimed: u32,
+}
+
+// 文件数据块指针所在的位置
+#[derive(Clone, Copy)]
+enum BlockPointer {
+    Direct(u32, usize),   // (Inode 号, blocks 下标)
+    Indirect(u32, usize), // (间接块号, 块内下标)
+}
+
+// 去重索引：块内容哈希 -> 文件数据块，以及反向的块 -> 哈希，释放块时直接按块号移出
+#[derive(Default)]
+struct DedupIndex {
+    blocks: HashMap<u64, Vec<u32>>,
+    hashes: HashMap<u32, u64>,
+}
+
+impl DedupIndex {
+    // 记录块 block 的内容哈希；块内容变过时先从原来的哈希下移出
+    fn insert(&mut self, block: u32, hash: u64) {
+        match self.hashes.insert(block, hash) {
+            Some(old) if old == hash => return,
+            Some(old) => self.remove_from_bucket(old, block),
+            None => {}
+        }
+        self.blocks.entry(hash).or_default().push(block);
+    }
+
+    fn remove(&mut self, block: u32) {
+        if let Some(hash) = self.hashes.remove(&block) {
+            self.remove_from_bucket(hash, block);
+        }
+    }
+
+    fn remove_from_bucket(&mut self, hash: u64, block: u32) {
+        if let Some(bucket) = self.blocks.get_mut(&hash) {
+            bucket.retain(|&b| b != block);
+            if bucket.is_empty() {
+                self.blocks.remove(&hash);
+            }
+        }
+    }
+}
+
+// snapshot list 返回的一个快照
+pub struct SnapshotInfo {
+    pub name: String,
+    pub created: u64,
+}
+
+// fsck 的检查结果：超级块里记录的计数与位图实际统计的计数
+pub struct FsckReport {
+    pub recorded_free_blocks: u32,
+    pub actual_free_blocks: u32,
+    pub recorded_free_inodes: u32,
+    pub actual_free_inodes: u32,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.recorded_free_blocks == self.actual_free_blocks
+            && self.recorded_free_inodes == self.actual_free_inodes
+    }
+}
+
+// stat / lstat 返回的 Inode 元数据
+pub struct Metadata {
+    pub ino: u32,
+    pub kind: InodeType,
+    pub size: u32,
+    pub blocks: Vec<u32>,
+    pub indirect: u32,
+    pub nlinks: u32,
+    pub perm: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub compressed: bool, // 设置了压缩标志
+}
+
+impl Metadata {
+    // 实际占用的块数：数据块加上间接块本身，与逻辑大小 size 无关（空洞不占块）
+    pub fn allocated_blocks(&self) -> usize {
+        self.blocks.len() + if self.indirect != 0 { 1 } else { 0 }
+    }
+}
 
 pub struct MyFileSystem {
     device: BlockDevice,
-    pub cwd_ino: u32,     // 当前目录的 Inode 编号
-    pub cwd_path: String, // 当前路径的字符串表示
+    pub cwd_ino: u32,          // 当前目录的 Inode 编号
+    pub cwd_path: String,      // 当前路径的字符串表示
+    fixed_time: Option<u64>,   // 设置后所有时间戳都使用该时间（SOURCE_DATE_EPOCH）
+    uuid_seed: Option<String>, // 设置后 format 生成的 UUID 由种子决定
+    // 在线去重用的索引，第一次用到时扫描建立，块被释放时移出
+    dedup_index: Option<DedupIndex>,
+    // 最近解压的簇：(Inode 编号, 簇号, 内容)。按块顺序读取压缩文件时每簇只解压一次，
+    // 写入簇或释放块时清空
+    cluster_cache: Option<(u32, usize, Vec<u8>)>,
 }
 
 impl MyFileSystem {
-    pub fn new(disk_path: &str) -> Self {
-        MyFileSystem {
-            device: BlockDevice::open(disk_path),
+    pub fn new(disk_path: &str) -> Result<Self, FsError> {
+        Ok(MyFileSystem {
+            device: BlockDevice::open(disk_path)?,
             cwd_ino: 1, // 初始指向根目录
             cwd_path: "/".to_string(),
+            fixed_time: None,
+            uuid_seed: None,
+            dedup_index: None,
+            cluster_cache: None,
+        })
+    }
+
+    // 镜像是否已经格式化：文件至少包含超级块且魔数正确
+    pub fn is_formatted(&mut self) -> bool {
+        self.device.len_blocks() > 0 && self.read_superblock().magic == MAGIC
+    }
+
+    // 固定时间戳（类似 SOURCE_DATE_EPOCH），用于生成可重现的镜像
+    pub fn set_fixed_time(&mut self, epoch: Option<u64>) {
+        self.fixed_time = epoch;
+    }
+
+    pub fn fixed_time(&self) -> Option<u64> {
+        self.fixed_time
+    }
+
+    // 指定 format 时生成 UUID 的种子
+    pub fn set_uuid_seed(&mut self, seed: Option<String>) {
+        self.uuid_seed = seed;
+    }
+
+    // 当前时间（Unix 秒），用于 Inode 时间戳
+    pub fn now(&self) -> u64 {
+        self.fixed_time.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        })
+    }
+
+    // 设置了固定时间时，外部传入的时间戳不能晚于它
+    pub fn clamp_time(&self, t: u64) -> u64 {
+        match self.fixed_time {
+            Some(epoch) => t.min(epoch),
+            None => t,
         }
     }
 
     // 格式化
     pub fn format(&mut self) {
-        println!("Formatting disk...");
+        self.format_with(DEFAULT_TOTAL_BLOCKS, DEFAULT_INODE_COUNT);
+    }
+
+    // 按指定的块数和 Inode 数格式化；Inode 表紧跟在两个位图之后，其后是数据区
+    pub fn format_with(&mut self, total_blocks: u32, inode_count: u32) {
+        let table_blocks = inode_table_blocks(inode_count);
+        let data_area_start = INODE_TABLE_START + table_blocks;
+        self.device.set_len(total_blocks);
 
         // 1. 写入超级块 (Block 0)
         let sb = Superblock {
             magic: MAGIC,
-            total_blocks: 1024,
-            inode_count: 128,
-            data_area_start: 10,
+            total_blocks,
+            inode_count,
+            data_area_start,
+            // 数据区之前的元数据块和根目录的数据块已被占用；Inode 0 保留，Inode 1 为根目录
+            free_blocks: total_blocks - data_area_start - 1,
+            free_inodes: inode_count - 2,
+            uuid: match &self.uuid_seed {
+                Some(seed) => uuid_from_seed(seed),
+                None => random_uuid(),
+            },
+            refcount_index: 0,
+            snapshot_table: 0,
+            flags: 0,
         };
+        self.dedup_index = None;
+        self.cluster_cache = None;
         self.device.write_block(0, &sb.serialize());
 
-        // 2. 初始化数据块位图 (Block 2)
+        // 2. 初始化数据块位图 (Block 2)，Block 0..=data_area_start 标记为已用
         let mut data_bitmap = [0u8; BLOCK_SIZE];
-
-        data_bitmap[0] = 0xFF;
-        data_bitmap[1] = 0b00000111;
-
+        for idx in 0..=data_area_start as usize {
+            data_bitmap[idx / 8] |= 1 << (idx % 8);
+        }
         self.device.write_block(2, &data_bitmap);
 
         // 3. 初始化 Inode 位图 (Block 1)
@@ -43,19 +289,20 @@ impl MyFileSystem {
         inode_bitmap[0] = 0b00000011;
         self.device.write_block(1, &inode_bitmap);
 
-        // 4. 初始化根目录 Inode (Inode 1) 存放在 Block 3
-        let mut root_inode = Inode {
-            mode: InodeType::Directory,
-            size: BLOCK_SIZE as u32,
-            blocks: [0; 12],
-        };
-        root_inode.blocks[0] = 10;
+        // 4. 初始化根目录 Inode (Inode 1) 存放在 Inode 表的第一个块，其余表块清零
+        let mut root_inode = Inode::new(InodeType::Directory, self.now());
+        root_inode.size = BLOCK_SIZE as u32;
+        root_inode.blocks[0] = data_area_start;
 
         let mut inode_table_block = [0u8; BLOCK_SIZE];
         inode_table_block[INODE_SIZE..INODE_SIZE * 2].copy_from_slice(&root_inode.serialize());
-        self.device.write_block(3, &inode_table_block);
+        self.device
+            .write_block(INODE_TABLE_START, &inode_table_block);
+        for block_idx in INODE_TABLE_START + 1..data_area_start {
+            self.device.write_block(block_idx, &[0u8; BLOCK_SIZE]);
+        }
 
-        // 5. 初始化根目录的数据块 (Block 10)
+        // 5. 初始化根目录的数据块（数据区的第一个块）
         let mut data_block = [0u8; BLOCK_SIZE];
         let dot = DirEntry {
             inode_no: 1,
@@ -67,207 +314,259 @@ impl MyFileSystem {
         };
         data_block[0..64].copy_from_slice(&dot.serialize());
         data_block[64..128].copy_from_slice(&dotdot.serialize());
-        self.device.write_block(10, &data_block);
+        self.device.write_block(data_area_start, &data_block);
 
         // 6. 初始化当前目录
         self.cwd_ino = 1;
         self.cwd_path = "/".to_string();
-
-        println!("Done.");
     }
 
-    // ls 命令
-    pub fn list_dir(&mut self, path: &str) {
-        // 1. 获取路径对应的 Inode 编号
-        let ino = match self.resolve_path(path) {
-            Some(i) => i,
-            None => {
-                println!("ls: {}: No such file or directory", path);
-                return;
+    // Inode 所在的 Inode 表块和块内偏移；快照中的 Inode 在该快照的 Inode 表副本中
+    fn inode_location(&mut self, ino: u32) -> Option<(u32, usize)> {
+        let (slot, ino) = split_ino(ino);
+        let index = ino as usize * INODE_SIZE / BLOCK_SIZE;
+        let offset = ino as usize * INODE_SIZE % BLOCK_SIZE;
+        let block_idx = match slot {
+            None => INODE_TABLE_START + index as u32,
+            Some(slot) => {
+                let record = self.snapshot_record(slot)?;
+                *self.snapshot_blocks(record.descriptor).get(1 + index)?
             }
         };
-
-        // 2. 读取该 Inode
-        let inode = self.read_inode(ino);
-
-        // 3. 检查是否为目录
-        if inode.mode != InodeType::Directory {
-            println!("{}", path.split('/').last().unwrap_or(path));
-            return;
-        }
-
-        println!("Listing directory: {}", path);
-        let mut buf = [0u8; BLOCK_SIZE];
-
-        // 4. 遍历 Inode 指向的所有数据块
-        for &data_block_idx in inode.blocks.iter() {
-            if data_block_idx == 0 {
-                continue;
-            }
-
-            self.device.read_block(data_block_idx, &mut buf);
-
-            // 5. 在每个块中按 DIR_ENTRY_SIZE (64字节) 长度切分并解析
-            for i in 0..(BLOCK_SIZE / DIR_ENTRY_SIZE) {
-                let start = i * DIR_ENTRY_SIZE;
-                let end = start + DIR_ENTRY_SIZE;
-
-                let entry = DirEntry::deserialize(&buf[start..end]);
-
-                if entry.inode_no != 0 {
-                    let child_inode = self.read_inode(entry.inode_no);
-                    let suffix = match child_inode.mode {
-                        InodeType::Directory => "/",
-                        _ => "",
-                    };
-                    println!("  {}{}", entry.name, suffix);
-                }
-            }
-        }
+        Some((block_idx, offset))
     }
 
     fn read_inode(&mut self, ino: u32) -> Inode {
+        if ino == SNAPSHOT_DIR_INO {
+            let mut inode = Inode::new(InodeType::Directory, 0);
+            inode.perm = 0o555;
+            return inode;
+        }
+        let Some((block_idx, start)) = self.inode_location(ino) else {
+            return Inode::deserialize(&[0u8; INODE_SIZE]);
+        };
         let mut buf = [0u8; BLOCK_SIZE];
-        let block_idx = 3 + (ino * INODE_SIZE as u32) / BLOCK_SIZE as u32;
-        let offset_in_block = (ino * INODE_SIZE as u32) % BLOCK_SIZE as u32;
-
         self.device.read_block(block_idx, &mut buf);
-        let start = offset_in_block as usize;
         Inode::deserialize(&buf[start..start + INODE_SIZE])
     }
 
     fn find_in_dir(&mut self, dir_ino: u32, name: &str) -> Option<u32> {
-        let inode = self.read_inode(dir_ino);
-        if inode.mode != InodeType::Directory {
+        // 有快照时才出现 .snapshots，没有快照时这个名字可以照常使用
+        if dir_ino == 1 && name == SNAPSHOT_DIR && self.read_superblock().snapshot_table != 0 {
+            return Some(SNAPSHOT_DIR_INO);
+        }
+        if self.read_inode(dir_ino).mode != InodeType::Directory {
             return None;
         }
+        self.dir_entries(dir_ino)
+            .into_iter()
+            .find(|e| e.name == name)
+            .map(|e| e.inode_no)
+    }
 
-        let mut buf = [0u8; BLOCK_SIZE];
-        for &data_block_idx in inode.blocks.iter() {
-            if data_block_idx == 0 {
-                continue;
+    // 解析路径，路径中的符号链接（包括最后一个分量）都会被跟随
+    pub fn resolve_path(&mut self, path: &str) -> Option<u32> {
+        self.lookup(path, true).ok()
+    }
+
+    // follow_last 为 false 时不跟随最后一个分量的符号链接（lstat、rm、mv 操作的是链接本身）
+    fn lookup(&mut self, path: &str, follow_last: bool) -> Result<u32, FsError> {
+        // 待处理的分量按逆序压栈；stack 记录从根开始经过的目录，用于处理 ".."
+        let mut pending: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .rev()
+            .map(String::from)
+            .collect();
+        let mut stack = vec![1u32];
+        let mut links = 0;
+
+        while let Some(part) = pending.pop() {
+            let current_ino = *stack.last().unwrap();
+            match part.as_str() {
+                "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => {}
             }
-            self.device.read_block(data_block_idx, &mut buf);
 
-            for i in 0..(BLOCK_SIZE / DIR_ENTRY_SIZE) {
-                let start = i * DIR_ENTRY_SIZE;
-                let entry = DirEntry::deserialize(&buf[start..start + DIR_ENTRY_SIZE]);
-                if entry.inode_no != 0 && entry.name == name {
-                    return Some(entry.inode_no);
+            if self.read_inode(current_ino).mode != InodeType::Directory {
+                return Err(FsError::NotADirectory);
+            }
+            let next_ino = self
+                .find_in_dir(current_ino, &part)
+                .ok_or(FsError::NotFound)?;
+
+            let mut inode = self.read_inode(next_ino);
+            if inode.mode == InodeType::Symlink && (follow_last || !pending.is_empty()) {
+                links += 1;
+                if links > MAX_SYMLINK_DEPTH {
+                    return Err(FsError::TooManyLinks);
                 }
+                let size = inode.size as usize;
+                let target = self.read_inode_range(next_ino, &mut inode, 0, size)?;
+                let target = String::from_utf8_lossy(&target).into_owned();
+                // 绝对路径的链接从根重新开始，相对路径的链接相对于链接所在目录
+                if target.starts_with('/') {
+                    stack.truncate(1);
+                }
+                pending.extend(
+                    target
+                        .split('/')
+                        .filter(|s| !s.is_empty())
+                        .rev()
+                        .map(String::from),
+                );
+            } else {
+                stack.push(next_ino);
             }
         }
-        None
+        Ok(*stack.last().unwrap())
     }
 
-    pub fn resolve_path(&mut self, path: &str) -> Option<u32> {
-        if path == "/" {
-            return Some(1);
+    // cd 命令
+    // change_dir：切换当前工作目录，abs_path 必须是绝对路径
+    pub fn change_dir(&mut self, abs_path: &str) -> Result<(), FsError> {
+        let ino = self.lookup(abs_path, true)?;
+        if self.read_inode(ino).mode != InodeType::Directory {
+            return Err(FsError::NotADirectory);
         }
+        self.cwd_ino = ino;
+        self.cwd_path = abs_path.to_string();
+        Ok(())
+    }
 
-        let mut current_ino = 1;
-        let parts = path.split('/').filter(|s| !s.is_empty());
-
-        for part in parts {
-            match self.find_in_dir(current_ino, part) {
-                Some(next_ino) => current_ino = next_ino,
-                None => return None,
-            }
-        }
-        Some(current_ino)
+    fn read_superblock(&mut self) -> Superblock {
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(0, &mut buf);
+        Superblock::deserialize(&buf)
     }
 
-    // cd 命令
-    pub fn cd(&mut self, abs_path: &str) {
-        if let Some(ino) = self.resolve_path(abs_path) {
-            let inode = self.read_inode(ino);
-            if inode.mode == InodeType::Directory {
-                self.cwd_ino = ino;
-                self.cwd_path = abs_path.to_string();
-            } else {
-                println!("cd: not a directory: {}", abs_path);
-            }
-        } else {
-            println!("cd: no such directory: {}", abs_path);
-        }
+    fn write_superblock(&mut self, sb: &Superblock) {
+        self.device.write_block(0, &sb.serialize());
     }
 
-    fn allocate_bit(&mut self, bitmap_block_idx: u32) -> Option<u32> {
+    // 只在前 limit 个位中查找空闲位，避免分配出超过磁盘/Inode 表范围的编号
+    fn allocate_bit(&mut self, bitmap_block_idx: u32, limit: u32) -> Option<u32> {
         let mut buf = [0u8; BLOCK_SIZE];
         self.device.read_block(bitmap_block_idx, &mut buf);
 
-        for i in 0..BLOCK_SIZE {
-            if buf[i] != 0xFF {
-                for bit in 0..8 {
-                    if (buf[i] & (1 << bit)) == 0 {
-                        buf[i] |= 1 << bit;
-                        self.device.write_block(bitmap_block_idx, &buf);
-                        return Some((i * 8 + bit) as u32);
-                    }
-                }
+        let limit = (limit as usize).min(BLOCK_SIZE * 8);
+        for idx in 0..limit {
+            let (byte_pos, bit_pos) = (idx / 8, idx % 8);
+            if (buf[byte_pos] & (1 << bit_pos)) == 0 {
+                buf[byte_pos] |= 1 << bit_pos;
+                self.device.write_block(bitmap_block_idx, &buf);
+                return Some(idx as u32);
             }
         }
         None
     }
 
+    fn count_free_bits(&mut self, bitmap_block_idx: u32, limit: u32) -> u32 {
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(bitmap_block_idx, &mut buf);
+
+        let limit = (limit as usize).min(BLOCK_SIZE * 8);
+        (0..limit)
+            .filter(|&idx| (buf[idx / 8] & (1 << (idx % 8))) == 0)
+            .count() as u32
+    }
+
     fn alloc_inode(&mut self) -> Option<u32> {
-        self.allocate_bit(1)
+        let mut sb = self.read_superblock();
+        let ino = self.allocate_bit(1, sb.inode_count)?;
+        sb.free_inodes = sb.free_inodes.saturating_sub(1);
+        self.write_superblock(&sb);
+        Some(ino)
     }
 
     fn alloc_data_block(&mut self) -> Option<u32> {
-        self.allocate_bit(2)
+        let mut sb = self.read_superblock();
+        let block_idx = self.allocate_bit(2, sb.total_blocks)?;
+        sb.free_blocks = sb.free_blocks.saturating_sub(1);
+        self.write_superblock(&sb);
+        Some(block_idx)
+    }
+
+    // statfs：直接读取超级块中维护的计数，不扫描位图
+    pub fn statfs(&mut self) -> StatFs {
+        let sb = self.read_superblock();
+        StatFs {
+            block_size: BLOCK_SIZE as u32,
+            total_blocks: sb.total_blocks,
+            free_blocks: sb.free_blocks,
+            total_inodes: sb.inode_count,
+            free_inodes: sb.free_inodes,
+            uuid: sb.uuid,
+        }
+    }
+
+    // fsck 命令：按位图重新统计空闲块和空闲 Inode，并修正超级块中的计数
+    pub fn fsck(&mut self) -> FsckReport {
+        let mut sb = self.read_superblock();
+        let report = FsckReport {
+            recorded_free_blocks: sb.free_blocks,
+            actual_free_blocks: self.count_free_bits(2, sb.total_blocks),
+            recorded_free_inodes: sb.free_inodes,
+            actual_free_inodes: self.count_free_bits(1, sb.inode_count),
+        };
+
+        if !report.is_clean() {
+            sb.free_blocks = report.actual_free_blocks;
+            sb.free_inodes = report.actual_free_inodes;
+            self.write_superblock(&sb);
+        }
+        report
     }
 
-    // mkdir 命令
-    pub fn mkdir(&mut self, path: &str) {
-        // 1. 分离父路径和新目录名
-        let (parent_path, dir_name) = match path.rsplit_once('/') {
+    // 分离父路径和最后一个分量，如 "/a/b" -> ("/a", "b")，"/a" -> ("/", "a")
+    pub fn split_path(path: &str) -> (&str, &str) {
+        match path.rsplit_once('/') {
             Some(("", name)) => ("/", name),
             Some((p, name)) => (p, name),
-            None => {
-                println!("mkdir: Invalid path");
-                return;
-            }
-        };
+            None => ("/", path),
+        }
+    }
 
-        // 2. 找到父目录 Inode
-        let parent_ino = match self.resolve_path(parent_path) {
-            Some(ino) => ino,
-            None => {
-                println!("mkdir: Parent not found: {}", parent_path);
-                return;
-            }
-        };
+    // 找到新条目的父目录，并检查名字是否合法、是否已存在
+    fn prepare_new_entry<'a>(&mut self, path: &'a str) -> Result<(u32, &'a str), FsError> {
+        let (parent_path, name) = Self::split_path(path);
+        if name.is_empty() || name == "." || name == ".." || name.len() > DIR_ENTRY_SIZE - 4 {
+            return Err(FsError::InvalidArgument);
+        }
 
-        // 3. 检查是否已存在
-        if self.find_in_dir(parent_ino, dir_name).is_some() {
-            println!("mkdir: '{}' already exists", dir_name);
-            return;
+        let parent_ino = self.resolve_path(parent_path).ok_or(FsError::NotFound)?;
+        if self.read_inode(parent_ino).mode != InodeType::Directory {
+            return Err(FsError::NotADirectory);
         }
+        check_writable(parent_ino)?;
+        if self.find_in_dir(parent_ino, name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+        Ok((parent_ino, name))
+    }
 
-        // 4. 分配资源
-        let new_ino = self.alloc_inode().expect("No free Inodes");
-        let new_data_block = self.alloc_data_block().expect("No free Data Blocks");
+    // create_dir：创建一个目录，父目录必须存在
+    pub fn create_dir(&mut self, path: &str) -> Result<u32, FsError> {
+        // 1. 找到父目录 Inode 并检查是否已存在
+        let (parent_ino, dir_name) = self.prepare_new_entry(path)?;
 
-        // 5. 初始化并写入新 Inode
-        let new_inode = Inode {
-            mode: InodeType::Directory,
-            size: BLOCK_SIZE as u32,
-            blocks: {
-                let mut b = [0u32; 12];
-                b[0] = new_data_block;
-                b
-            },
+        // 2. 分配资源
+        let new_ino = self.alloc_inode().ok_or(FsError::NoSpace)?;
+        let new_data_block = match self.alloc_data_block() {
+            Some(b) => b,
+            None => {
+                self.free_inode(new_ino);
+                return Err(FsError::NoSpace);
+            }
         };
-        let mut table_buf = [0u8; BLOCK_SIZE];
-        let block_idx = 3 + (new_ino * INODE_SIZE as u32) / BLOCK_SIZE as u32;
-        let offset = (new_ino * INODE_SIZE as u32) % BLOCK_SIZE as u32;
-        self.device.read_block(block_idx, &mut table_buf);
-        table_buf[offset as usize..offset as usize + INODE_SIZE]
-            .copy_from_slice(&new_inode.serialize());
-        self.device.write_block(block_idx, &table_buf);
 
-        // 6. 初始化新目录的数据块
+        // 3. 初始化新目录的数据块
         let mut new_data = [0u8; BLOCK_SIZE];
         let dot = DirEntry {
             inode_no: new_ino,
@@ -281,75 +580,127 @@ impl MyFileSystem {
         new_data[64..128].copy_from_slice(&dotdot.serialize());
         self.device.write_block(new_data_block, &new_data);
 
-        // 7. 在父目录中增加条目
-        self.add_dir_entry(parent_ino, new_ino, dir_name);
+        // 4. 初始化并写入新 Inode
+        let mut new_inode = Inode::new(InodeType::Directory, self.now());
+        new_inode.size = BLOCK_SIZE as u32;
+        new_inode.blocks[0] = new_data_block;
+        new_inode.flags = self.inherited_flags(parent_ino);
+        self.write_inode_to_disk(new_ino, &new_inode);
 
-        println!("Directory '{}' created.", dir_name);
+        // 5. 在父目录中增加条目，新目录的 ".." 使父目录链接数加一
+        if let Err(e) = self.add_dir_entry(parent_ino, new_ino, dir_name) {
+            self.free_data_block(new_data_block);
+            self.free_inode(new_ino);
+            return Err(e);
+        }
+        self.adjust_nlinks(parent_ino, 1);
+        Ok(new_ino)
     }
 
-    fn add_dir_entry(&mut self, parent_ino: u32, child_ino: u32, name: &str) {
-        let mut parent_inode = self.read_inode(parent_ino);
-        let mut buf = [0u8; BLOCK_SIZE];
-
-        let block_idx = parent_inode.blocks[0];
-        self.device.read_block(block_idx, &mut buf);
-
-        for i in 0..(BLOCK_SIZE / DIR_ENTRY_SIZE) {
-            let start = i * DIR_ENTRY_SIZE;
-            let entry = DirEntry::deserialize(&buf[start..start + DIR_ENTRY_SIZE]);
-            if entry.inode_no == 0 {
-                let new_entry = DirEntry {
-                    inode_no: child_ino,
-                    name: name.to_string(),
-                };
-                buf[start..start + DIR_ENTRY_SIZE].copy_from_slice(&new_entry.serialize());
-                self.device.write_block(block_idx, &buf);
-                return;
+    // create_dir_all：逐级创建路径上缺失的目录，已存在的目录直接跳过
+    pub fn create_dir_all(&mut self, path: &str) -> Result<(), FsError> {
+        let mut current = String::new();
+        for part in path.split('/').filter(|s| !s.is_empty()) {
+            current.push('/');
+            current.push_str(part);
+            match self.resolve_path(&current) {
+                Some(ino) if self.read_inode(ino).mode == InodeType::Directory => {}
+                Some(_) => return Err(FsError::NotADirectory),
+                None => {
+                    self.create_dir(&current)?;
+                }
             }
         }
-        panic!("Parent directory is full!");
+        Ok(())
     }
 
-    // touch 命令
-    pub fn touch(&mut self, path: &str) {
-        // 1. 分离父路径和文件名
-        let (parent_path, file_name) = match path.rsplit_once('/') {
-            Some(("", name)) => ("/", name),
-            Some((p, name)) => (p, name),
-            None => {
-                println!("touch: Path must start with /");
-                return;
+    fn adjust_nlinks(&mut self, ino: u32, delta: i32) {
+        let mut inode = self.read_inode(ino);
+        inode.nlinks = inode.nlinks.saturating_add_signed(delta);
+        inode.ctime = self.now();
+        self.write_inode_to_disk(ino, &inode);
+    }
+
+    // 在目录中找一个空槽写入新条目；现有的块都满了就为目录再分配一个块
+    fn add_dir_entry(
+        &mut self,
+        parent_ino: u32,
+        child_ino: u32,
+        name: &str,
+    ) -> Result<(), FsError> {
+        let mut parent_inode = self.read_inode(parent_ino);
+        let mut buf = [0u8; BLOCK_SIZE];
+
+        for i in 0..DIRECT_BLOCKS {
+            if parent_inode.blocks[i] == 0 {
+                parent_inode.blocks[i] = self.alloc_zeroed_block()?;
+                parent_inode.size += BLOCK_SIZE as u32;
+                buf = [0u8; BLOCK_SIZE];
+            } else {
+                self.device.read_block(parent_inode.blocks[i], &mut buf);
             }
-        };
 
-        // 2. 找到父目录 Inode
-        let parent_ino = match self.resolve_path(parent_path) {
-            Some(ino) => ino,
-            None => {
-                println!("touch: Parent directory not found");
-                return;
+            for j in 0..(BLOCK_SIZE / DIR_ENTRY_SIZE) {
+                let start = j * DIR_ENTRY_SIZE;
+                let entry = DirEntry::deserialize(&buf[start..start + DIR_ENTRY_SIZE]);
+                if entry.inode_no == 0 {
+                    let new_entry = DirEntry {
+                        inode_no: child_ino,
+                        name: name.to_string(),
+                    };
+                    buf[start..start + DIR_ENTRY_SIZE].copy_from_slice(&new_entry.serialize());
+                    // 目录块被快照共享时先复制（内容与 buf 读到的相同）
+                    let block_idx = self.bmap(&mut parent_inode, i, true)?.unwrap();
+                    self.device.write_block(block_idx, &buf);
+                    parent_inode.mtime = self.now();
+                    self.write_inode_to_disk(parent_ino, &parent_inode);
+                    return Ok(());
+                }
             }
-        };
+        }
+        Err(FsError::NoSpace)
+    }
 
-        // 3. 分配一个新的 Inode
-        let new_ino = self.alloc_inode().expect("No free Inodes");
+    // create_file：创建一个空文件
+    pub fn create_file(&mut self, path: &str) -> Result<u32, FsError> {
+        // 1. 找到父目录 Inode 并检查是否已存在
+        let (parent_ino, file_name) = self.prepare_new_entry(path)?;
 
-        // 4. 初始化 Inode
-        let new_inode = Inode {
-            mode: InodeType::File,
-            size: 0,
-            blocks: [0; 12],
-        };
+        // 2. 分配并初始化一个新的 Inode
+        let new_ino = self.alloc_inode().ok_or(FsError::NoSpace)?;
+        let mut new_inode = Inode::new(InodeType::File, self.now());
+        new_inode.flags = self.inherited_flags(parent_ino);
         self.write_inode_to_disk(new_ino, &new_inode);
 
-        // 5. 在父目录中增加条目
-        self.add_dir_entry(parent_ino, new_ino, file_name);
-        println!("File '{}' created.", file_name);
+        // 3. 在父目录中增加条目
+        if let Err(e) = self.add_dir_entry(parent_ino, new_ino, file_name) {
+            self.free_inode(new_ino);
+            return Err(e);
+        }
+        Ok(new_ino)
+    }
+
+    // touch：文件不存在时创建，已存在时只更新时间戳；返回是否新建了文件
+    pub fn touch(&mut self, path: &str) -> Result<bool, FsError> {
+        if let Some(ino) = self.resolve_path(path) {
+            check_writable(ino)?;
+            let mut inode = self.read_inode(ino);
+            inode.atime = self.now();
+            inode.mtime = inode.atime;
+            self.write_inode_to_disk(ino, &inode);
+            return Ok(false);
+        }
+        self.create_file(path)?;
+        Ok(true)
     }
 
     fn write_inode_to_disk(&mut self, ino: u32, inode: &Inode) {
+        // 快照中的 Inode 不会被改写（例如读取快照中的文件时不更新 atime）
+        if check_writable(ino).is_err() {
+            return;
+        }
         let mut table_buf = [0u8; BLOCK_SIZE];
-        let block_idx = 3 + (ino * INODE_SIZE as u32) / BLOCK_SIZE as u32;
+        let block_idx = INODE_TABLE_START + (ino * INODE_SIZE as u32) / BLOCK_SIZE as u32;
         let offset = (ino * INODE_SIZE as u32) % BLOCK_SIZE as u32;
         self.device.read_block(block_idx, &mut table_buf);
         table_buf[offset as usize..offset as usize + INODE_SIZE]
@@ -357,100 +708,531 @@ impl MyFileSystem {
         self.device.write_block(block_idx, &table_buf);
     }
 
-    // write 命令
-    pub fn write_file(&mut self, path: &str, content: &str) {
-        // 1. 找到文件的 Inode
-        let ino = match self.resolve_path(path) {
-            Some(i) => i,
-            None => {
-                println!("write: File not found");
-                return;
-            }
-        };
+    // 分配一个数据块并清零，避免读到之前被释放的旧数据
+    fn alloc_zeroed_block(&mut self) -> Result<u32, FsError> {
+        let block_idx = self.alloc_data_block().ok_or(FsError::NoSpace)?;
+        self.device.write_block(block_idx, &[0u8; BLOCK_SIZE]);
+        Ok(block_idx)
+    }
 
-        let mut inode = self.read_inode(ino);
-        if inode.mode != InodeType::File {
-            println!("write: '{}' is not a file", path);
-            return;
+    // 把文件内第 file_block 个逻辑块映射为磁盘块号；alloc 为 true 表示要写入该块：
+    // 按需分配（包括间接块），被快照或其他文件共享的块先复制一份私有的副本（写时复制）
+    // 返回 None 表示该位置是未分配的空洞，读取时视为全 0
+    fn bmap(
+        &mut self,
+        inode: &mut Inode,
+        file_block: usize,
+        alloc: bool,
+    ) -> Result<Option<u32>, FsError> {
+        if file_block < DIRECT_BLOCKS {
+            let b = inode.blocks[file_block];
+            if alloc {
+                inode.blocks[file_block] = match b {
+                    0 => self.alloc_zeroed_block()?,
+                    b => self.unshare_block(b)?,
+                };
+            }
+            let b = inode.blocks[file_block];
+            return Ok(if b == 0 { None } else { Some(b) });
         }
 
-        // 2. 分配数据块
-        if inode.blocks[0] == 0 {
-            let new_block = self.alloc_data_block().expect("No free Data Blocks");
-            inode.blocks[0] = new_block;
+        let idx = file_block - DIRECT_BLOCKS;
+        if idx >= PTRS_PER_BLOCK {
+            return Err(FsError::FileTooLarge);
+        }
+        if inode.indirect == 0 {
+            if !alloc {
+                return Ok(None);
+            }
+            inode.indirect = self.alloc_zeroed_block()?;
+        }
+        if alloc {
+            // 共享的间接块中的数据块也是共享的，要修改其中的指针先复制间接块
+            self.unshare_indirect(inode)?;
         }
 
-        // 3. 准备数据
-        let bytes = content.as_bytes();
-        let write_len = bytes.len().min(BLOCK_SIZE);
-        let mut data_buf = [0u8; BLOCK_SIZE];
-        data_buf[..write_len].copy_from_slice(&bytes[..write_len]);
-
-        // 4. 写入数据块
-        self.device.write_block(inode.blocks[0], &data_buf);
-
-        // 5. 更新 Inode 大小并写回
-        inode.size = write_len as u32;
-        self.write_inode_to_disk(ino, &inode);
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(inode.indirect, &mut buf);
+        let mut b = u32::from_le_bytes(buf[idx * 4..idx * 4 + 4].try_into().unwrap());
+        if alloc {
+            let new_b = match b {
+                0 => self.alloc_zeroed_block()?,
+                b => self.unshare_block(b)?,
+            };
+            if new_b != b {
+                b = new_b;
+                buf[idx * 4..idx * 4 + 4].copy_from_slice(&b.to_le_bytes());
+                self.device.write_block(inode.indirect, &buf);
+            }
+        }
+        Ok(if b == 0 { None } else { Some(b) })
+    }
 
-        println!("Wrote {} bytes to '{}'.", write_len, path);
+    // 引用计数表中 block_idx 对应的 (表块, 块内偏移)；还没有建立引用计数表时返回 None
+    fn refcount_slot(&mut self, block_idx: u32) -> Option<(u32, usize)> {
+        let sb = self.read_superblock();
+        if sb.refcount_index == 0 {
+            return None;
+        }
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(sb.refcount_index, &mut buf);
+        let i = block_idx as usize / REFS_PER_BLOCK * 4;
+        let table = u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        (table != 0).then_some((table, block_idx as usize % REFS_PER_BLOCK * 4))
     }
 
-    // cat 命令
-    pub fn cat(&mut self, path: &str) {
-        let ino = match self.resolve_path(path) {
-            Some(i) => i,
-            None => {
-                println!("cat: File not found");
-                return;
-            }
+    // 块的额外引用数：0 表示只有一个所有者，每多一个快照、reflink 副本或去重引用就加一
+    fn extra_refs(&mut self, block_idx: u32) -> u32 {
+        let Some((table, offset)) = self.refcount_slot(block_idx) else {
+            return 0;
         };
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(table, &mut buf);
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
 
-        let inode = self.read_inode(ino);
-        if inode.mode != InodeType::File {
-            println!("cat: '{}' is not a file", path);
+    // 设置块的额外引用数；所有块都不再共享时释放引用计数表
+    fn set_extra_refs(&mut self, block_idx: u32, refs: u32) {
+        let Some((table, offset)) = self.refcount_slot(block_idx) else {
             return;
-        }
-
-        if inode.size == 0 {
+        };
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(table, &mut buf);
+        let old = u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+        buf[offset..offset + 4].copy_from_slice(&refs.to_le_bytes());
+        self.device.write_block(table, &buf);
+        if (old == 0) == (refs == 0) {
             return;
         }
 
-        // 读取第一个块
-        let mut buf = [0u8; BLOCK_SIZE];
-        self.device.read_block(inode.blocks[0], &mut buf);
-
-        // 只打印 size 长度的内容
-        let content = String::from_utf8_lossy(&buf[..inode.size as usize]);
-        println!("{}", content);
+        let index = self.read_superblock().refcount_index;
+        self.device.read_block(index, &mut buf);
+        let range = SHARED_COUNT_OFFSET..SHARED_COUNT_OFFSET + 4;
+        let shared = u32::from_le_bytes(buf[range.clone()].try_into().unwrap());
+        let shared = if refs == 0 {
+            shared.saturating_sub(1)
+        } else {
+            shared + 1
+        };
+        buf[range].copy_from_slice(&shared.to_le_bytes());
+        self.device.write_block(index, &buf);
+        self.release_unused_refcounts();
     }
 
-    fn set_bit(&mut self, bitmap_block_idx: u32, bit_idx: u32, val: bool) {
+    // 没有任何块被共享时释放引用计数索引块和表块，超级块中的索引清零
+    fn release_unused_refcounts(&mut self) {
+        let mut sb = self.read_superblock();
+        if sb.refcount_index == 0 {
+            return;
+        }
         let mut buf = [0u8; BLOCK_SIZE];
-        self.device.read_block(bitmap_block_idx, &mut buf);
-        let byte_pos = (bit_idx / 8) as usize;
-        let bit_pos = (bit_idx % 8) as usize;
-        if val {
-            buf[byte_pos] |= 1 << bit_pos;
-        } else {
-            buf[byte_pos] &= !(1 << bit_pos);
+        self.device.read_block(sb.refcount_index, &mut buf);
+        let shared = &buf[SHARED_COUNT_OFFSET..SHARED_COUNT_OFFSET + 4];
+        if u32::from_le_bytes(shared.try_into().unwrap()) != 0 {
+            return;
         }
-        self.device.write_block(bitmap_block_idx, &buf);
+        // 先清除超级块中的索引，释放表块时它们自己就不会被当作共享块
+        let index = std::mem::take(&mut sb.refcount_index);
+        self.write_superblock(&sb);
+        for chunk in buf[..SHARED_COUNT_OFFSET].chunks_exact(4) {
+            let b = u32::from_le_bytes(chunk.try_into().unwrap());
+            if b != 0 {
+                self.free_data_block(b);
+            }
+        }
+        self.free_data_block(index);
     }
 
-    fn free_inode(&mut self, ino: u32) {
-        self.set_bit(1, ino, false);
+    // 增加一个引用；调用前必须已经用 ensure_refcounts 建立了引用计数表。
+    // 引用数减到 0 时表可能被释放，所以同一操作中要先增加引用再放弃旧的引用
+    fn add_ref(&mut self, block_idx: u32) {
+        let refs = self.extra_refs(block_idx);
+        self.set_extra_refs(block_idx, refs + 1);
     }
 
-    fn free_data_block(&mut self, block_idx: u32) {
-        self.set_bit(2, block_idx, false);
+    // 第一次共享块时建立引用计数表：一个索引块加上覆盖所有块的表块，一次分配好，
+    // 之后增减引用计数都不会再分配块
+    fn ensure_refcounts(&mut self) -> Result<(), FsError> {
+        let sb = self.read_superblock();
+        if sb.refcount_index != 0 {
+            return Ok(());
+        }
+        let tables = (sb.total_blocks as usize).div_ceil(REFS_PER_BLOCK);
+        let blocks = self.alloc_blocks(1 + tables)?;
+        let mut index = [0u8; BLOCK_SIZE];
+        for (i, &b) in blocks[1..].iter().enumerate() {
+            self.device.write_block(b, &[0u8; BLOCK_SIZE]);
+            index[i * 4..i * 4 + 4].copy_from_slice(&b.to_le_bytes());
+        }
+        self.device.write_block(blocks[0], &index);
+
+        let mut sb = self.read_superblock();
+        sb.refcount_index = blocks[0];
+        self.write_superblock(&sb);
+        Ok(())
     }
 
-    fn remove_dir_entry(&mut self, parent_ino: u32, name: &str) {
-        let mut parent_inode = self.read_inode(parent_ino);
+    // 一次分配 count 个块，空间不足时一个也不分配
+    fn alloc_blocks(&mut self, count: usize) -> Result<Vec<u32>, FsError> {
+        let mut blocks = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.alloc_data_block() {
+                Some(b) => blocks.push(b),
+                None => {
+                    for b in blocks {
+                        self.free_data_block(b);
+                    }
+                    return Err(FsError::NoSpace);
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    // 写时复制：块被共享时复制到一个新块并放弃对原块的引用，返回可以写入的块号
+    fn unshare_block(&mut self, block_idx: u32) -> Result<u32, FsError> {
+        if self.extra_refs(block_idx) == 0 {
+            return Ok(block_idx);
+        }
+        let new_block = self.alloc_data_block().ok_or(FsError::NoSpace)?;
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(block_idx, &mut buf);
+        self.device.write_block(new_block, &buf);
+        self.free_data_block(block_idx);
+        Ok(new_block)
+    }
+
+    // 复制共享的间接块；副本和原块指向同样的数据块，所以这些数据块各增加一个引用
+    fn unshare_indirect(&mut self, inode: &mut Inode) -> Result<(), FsError> {
+        if inode.indirect == 0 || self.extra_refs(inode.indirect) == 0 {
+            return Ok(());
+        }
+        let new_indirect = self.alloc_data_block().ok_or(FsError::NoSpace)?;
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(inode.indirect, &mut buf);
+        self.device.write_block(new_indirect, &buf);
+        for chunk in buf.chunks_exact(4) {
+            let b = u32::from_le_bytes(chunk.try_into().unwrap());
+            if b != 0 {
+                self.add_ref(b);
+            }
+        }
+        self.free_data_block(inode.indirect);
+        inode.indirect = new_indirect;
+        Ok(())
+    }
+
+    // 文件实际占用的全部数据块（不含间接块本身），按逻辑顺序排列
+    fn data_blocks(&mut self, inode: &Inode) -> Vec<u32> {
+        let mut result: Vec<u32> = inode.blocks.iter().copied().filter(|&b| b != 0).collect();
+        if inode.indirect != 0 {
+            let mut buf = [0u8; BLOCK_SIZE];
+            self.device.read_block(inode.indirect, &mut buf);
+            for chunk in buf.chunks_exact(4) {
+                let b = u32::from_le_bytes(chunk.try_into().unwrap());
+                if b != 0 {
+                    result.push(b);
+                }
+            }
+        }
+        result
+    }
+
+    // 释放逻辑块号 >= first_block 的所有数据块；不再需要间接块时一并释放
+    // 共享的块只放弃本文件的引用。从 0 开始释放不会失败；只截掉一部分时
+    // 可能要先复制共享的间接块，空间不足时返回 NoSpace
+    fn free_blocks_from(&mut self, inode: &mut Inode, first_block: usize) -> Result<(), FsError> {
+        self.cluster_cache = None;
+        for i in first_block.min(DIRECT_BLOCKS)..DIRECT_BLOCKS {
+            if inode.blocks[i] != 0 {
+                self.free_data_block(inode.blocks[i]);
+                inode.blocks[i] = 0;
+            }
+        }
+
+        if inode.indirect == 0 {
+            return Ok(());
+        }
+        let first_idx = first_block.saturating_sub(DIRECT_BLOCKS);
+        if first_idx == 0 && self.extra_refs(inode.indirect) > 0 {
+            // 整个间接块都不要了，其中的数据块仍然由共享它的其他所有者引用
+            self.free_data_block(inode.indirect);
+            inode.indirect = 0;
+            return Ok(());
+        }
+        if first_idx > 0 {
+            self.unshare_indirect(inode)?;
+        }
+
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(inode.indirect, &mut buf);
+        for idx in first_idx..PTRS_PER_BLOCK {
+            let b = u32::from_le_bytes(buf[idx * 4..idx * 4 + 4].try_into().unwrap());
+            if b != 0 {
+                self.free_data_block(b);
+                buf[idx * 4..idx * 4 + 4].copy_from_slice(&[0; 4]);
+            }
+        }
+
+        if first_idx == 0 {
+            self.free_data_block(inode.indirect);
+            inode.indirect = 0;
+        } else {
+            self.device.write_block(inode.indirect, &buf);
+        }
+        Ok(())
+    }
+
+    fn open_file(&mut self, path: &str) -> Result<(u32, Inode), FsError> {
+        let ino = self.resolve_path(path).ok_or(FsError::NotFound)?;
+        let inode = self.read_inode(ino);
+        if inode.mode == InodeType::Directory {
+            return Err(FsError::IsADirectory);
+        }
+        Ok((ino, inode))
+    }
+
+    // 打开文件准备修改，快照中的文件只读
+    fn open_file_mut(&mut self, path: &str) -> Result<(u32, Inode), FsError> {
+        let (ino, inode) = self.open_file(path)?;
+        check_writable(ino)?;
+        Ok((ino, inode))
+    }
+
+    // 读取整个文件的原始字节
+    pub fn read_bytes(&mut self, path: &str) -> Result<Vec<u8>, FsError> {
+        let (ino, mut inode) = self.open_file(path)?;
+        let size = inode.size as usize;
+        let data = self.read_inode_range(ino, &mut inode, 0, size)?;
+
+        inode.atime = self.now();
+        self.write_inode_to_disk(ino, &inode);
+        Ok(data)
+    }
+
+    // read_at：读取 [offset, offset + len) 范围内的字节，超出文件末尾的部分被截掉
+    // 只读取覆盖该范围的数据块；为了避免频繁写回 Inode，这里不更新 atime
+    pub fn read_at(&mut self, ino: u32, offset: usize, len: usize) -> Result<Vec<u8>, FsError> {
+        let mut inode = self.read_inode(ino);
+        match inode.mode {
+            InodeType::Directory => return Err(FsError::IsADirectory),
+            InodeType::Unused => return Err(FsError::NotFound),
+            _ => {}
+        }
+        self.read_inode_range(ino, &mut inode, offset, len)
+    }
+
+    fn read_inode_range(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, FsError> {
+        let size = inode.size as usize;
+        let end = offset.saturating_add(len).min(size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        if inode.flags & INODE_FLAG_COMPRESSED != 0 {
+            return self.read_compressed_range(ino, inode, offset, end);
+        }
+
+        let mut data = Vec::with_capacity(end - offset);
+        let mut buf = [0u8; BLOCK_SIZE];
+        for file_block in offset / BLOCK_SIZE..end.div_ceil(BLOCK_SIZE) {
+            match self.bmap(inode, file_block, false)? {
+                Some(b) => self.device.read_block(b, &mut buf),
+                None => buf = [0u8; BLOCK_SIZE],
+            }
+            let block_start = file_block * BLOCK_SIZE;
+            let from = offset.max(block_start) - block_start;
+            let to = end.min(block_start + BLOCK_SIZE) - block_start;
+            data.extend_from_slice(&buf[from..to]);
+        }
+        Ok(data)
+    }
+
+    // 从 offset 开始写入 data，必要时扩展文件大小；不足一块的部分先读出原块再修改
+    // 返回实际写入的字节数；出错（如空间不足）时已写入的部分仍然保留
+    fn write_at_inode(
+        &mut self,
+        inode: &mut Inode,
+        offset: usize,
+        data: &[u8],
+    ) -> (usize, Result<(), FsError>) {
+        let Some(end) = offset.checked_add(data.len()) else {
+            return (0, Err(FsError::FileTooLarge));
+        };
+        if end.div_ceil(BLOCK_SIZE) > MAX_FILE_BLOCKS {
+            return (0, Err(FsError::FileTooLarge));
+        }
+        if inode.flags & INODE_FLAG_COMPRESSED != 0 {
+            return self.write_compressed(inode, offset, data);
+        }
+
+        let inline_dedup =
+            inode.mode == InodeType::File && self.read_superblock().flags & FLAG_INLINE_DEDUP != 0;
+        let mut written = 0;
+        let mut result = Ok(());
+        let mut buf = [0u8; BLOCK_SIZE];
+        while written < data.len() {
+            let pos = offset + written;
+            let (file_block, in_block) = (pos / BLOCK_SIZE, pos % BLOCK_SIZE);
+            let len = (BLOCK_SIZE - in_block).min(data.len() - written);
+
+            let b = match self.bmap(inode, file_block, true) {
+                Ok(b) => b.unwrap(),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            if len < BLOCK_SIZE {
+                self.device.read_block(b, &mut buf);
+            }
+            buf[in_block..in_block + len].copy_from_slice(&data[written..written + len]);
+            self.device.write_block(b, &buf);
+            // 只对写到块末尾的块去重，追加中途的块很快又会被改写
+            if inline_dedup && in_block + len == BLOCK_SIZE {
+                self.dedup_written_block(inode, file_block, b, &buf);
+            }
+            written += len;
+        }
+
+        if written > 0 {
+            inode.size = inode.size.max((offset + written) as u32);
+            inode.mtime = self.now();
+            inode.ctime = inode.mtime;
+        }
+        (written, result)
+    }
+
+    // 把文件大小改为 size：缩小时释放多余的块并清零最后一块的尾部，扩大时补齐清零的新块
+    fn resize_inode(&mut self, inode: &mut Inode, size: usize) -> Result<(), FsError> {
+        let old_size = inode.size as usize;
+        let new_blocks = size.div_ceil(BLOCK_SIZE);
+        if new_blocks > MAX_FILE_BLOCKS {
+            return Err(FsError::FileTooLarge);
+        }
+        if inode.flags & INODE_FLAG_COMPRESSED != 0 {
+            return self.resize_compressed(inode, size);
+        }
+
+        if size < old_size {
+            self.free_blocks_from(inode, new_blocks)?;
+            if !size.is_multiple_of(BLOCK_SIZE)
+                && self.bmap(inode, size / BLOCK_SIZE, false)?.is_some()
+                && let Some(b) = self.bmap(inode, size / BLOCK_SIZE, true)?
+            {
+                let mut buf = [0u8; BLOCK_SIZE];
+                self.device.read_block(b, &mut buf);
+                buf[size % BLOCK_SIZE..].fill(0);
+                self.device.write_block(b, &buf);
+            }
+            inode.size = size as u32;
+        } else if size > old_size {
+            // 新分配的块已经清零，只需要按块补齐；中途失败时大小停在已补齐的位置
+            for file_block in old_size / BLOCK_SIZE..new_blocks {
+                self.bmap(inode, file_block, true)?;
+                inode.size = ((file_block + 1) * BLOCK_SIZE).min(size) as u32;
+            }
+        }
+
+        inode.mtime = self.now();
+        inode.ctime = inode.mtime;
+        Ok(())
+    }
+
+    // 用 data 替换文件的全部内容，多余的旧数据块会被释放
+    pub fn write_bytes(&mut self, path: &str, data: &[u8]) -> Result<usize, FsError> {
+        let (ino, mut inode) = self.open_file_mut(path)?;
+        let (written, result) = self.write_at_inode(&mut inode, 0, data);
+        let truncated = self.resize_inode(&mut inode, written);
+        self.write_inode_to_disk(ino, &inode);
+        result.and(truncated).map(|_| written)
+    }
+
+    // pwrite：从 offset 处覆盖写入，不改变 offset 之前和写入范围之后的内容
+    pub fn pwrite(&mut self, path: &str, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        let (ino, mut inode) = self.open_file_mut(path)?;
+        let (written, result) = self.write_at_inode(&mut inode, offset, data);
+        self.write_inode_to_disk(ino, &inode);
+        result.map(|_| written)
+    }
+
+    // 追加写入到文件末尾
+    pub fn append(&mut self, path: &str, data: &[u8]) -> Result<usize, FsError> {
+        let (ino, mut inode) = self.open_file_mut(path)?;
+        let offset = inode.size as usize;
+        let (written, result) = self.write_at_inode(&mut inode, offset, data);
+        self.write_inode_to_disk(ino, &inode);
+        result.map(|_| written)
+    }
+
+    // set_len：截断或用 0 扩展文件到 size 字节
+    pub fn set_len(&mut self, path: &str, size: usize) -> Result<(), FsError> {
+        let (ino, mut inode) = self.open_file_mut(path)?;
+        let result = self.resize_inode(&mut inode, size);
+        self.write_inode_to_disk(ino, &inode);
+        result
+    }
+
+    // 返回修改前该位的值
+    fn set_bit(&mut self, bitmap_block_idx: u32, bit_idx: u32, val: bool) -> bool {
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(bitmap_block_idx, &mut buf);
+        let byte_pos = (bit_idx / 8) as usize;
+        let bit_pos = (bit_idx % 8) as usize;
+        let old = (buf[byte_pos] & (1 << bit_pos)) != 0;
+        if val {
+            buf[byte_pos] |= 1 << bit_pos;
+        } else {
+            buf[byte_pos] &= !(1 << bit_pos);
+        }
+        self.device.write_block(bitmap_block_idx, &buf);
+        old
+    }
+
+    fn free_inode(&mut self, ino: u32) {
+        // 清空 Inode 表中的旧内容，保证空闲 Inode 全为 0
+        self.write_inode_to_disk(ino, &Inode::deserialize(&[0u8; INODE_SIZE]));
+        // 重复释放不应让计数继续增长
+        if self.set_bit(1, ino, false) {
+            let mut sb = self.read_superblock();
+            sb.free_inodes = (sb.free_inodes + 1).min(sb.inode_count);
+            self.write_superblock(&sb);
+        }
+    }
+
+    // 共享的块只减少一个引用，最后一个所有者释放时才真正回到空闲位图
+    fn free_data_block(&mut self, block_idx: u32) {
+        let refs = self.extra_refs(block_idx);
+        if refs > 0 {
+            self.set_extra_refs(block_idx, refs - 1);
+            return;
+        }
+        if self.set_bit(2, block_idx, false) {
+            if let Some(index) = &mut self.dedup_index {
+                index.remove(block_idx);
+            }
+            let mut sb = self.read_superblock();
+            sb.free_blocks = (sb.free_blocks + 1).min(sb.total_blocks);
+            self.write_superblock(&sb);
+        }
+    }
+
+    fn remove_dir_entry(&mut self, parent_ino: u32, name: &str) -> Result<(), FsError> {
+        self.set_dir_entry(parent_ino, name, 0)
+    }
+
+    // 把目录中名为 name 的条目改为指向 new_ino；new_ino 为 0 时整条抹除
+    // 目录块被快照共享时要先复制，空间不足时返回 NoSpace
+    fn set_dir_entry(&mut self, parent_ino: u32, name: &str, new_ino: u32) -> Result<(), FsError> {
+        let mut parent_inode = self.read_inode(parent_ino);
         let mut buf = [0u8; BLOCK_SIZE];
 
-        for i in 0..12 {
+        for i in 0..DIRECT_BLOCKS {
             let block_idx = parent_inode.blocks[i];
             if block_idx == 0 {
                 continue;
@@ -461,146 +1243,1768 @@ impl MyFileSystem {
                 let start = j * DIR_ENTRY_SIZE;
                 let entry = DirEntry::deserialize(&buf[start..start + DIR_ENTRY_SIZE]);
                 if entry.inode_no != 0 && entry.name == name {
-                    // 找到后抹除，写入一个全0的无效Entry
-                    let empty_entry = [0u8; DIR_ENTRY_SIZE];
-                    buf[start..start + DIR_ENTRY_SIZE].copy_from_slice(&empty_entry);
+                    let new_entry = if new_ino == 0 {
+                        // 抹除时写入一个全0的无效Entry
+                        [0u8; DIR_ENTRY_SIZE]
+                    } else {
+                        DirEntry {
+                            inode_no: new_ino,
+                            name: name.to_string(),
+                        }
+                        .serialize()
+                    };
+                    buf[start..start + DIR_ENTRY_SIZE].copy_from_slice(&new_entry);
+                    let block_idx = self.bmap(&mut parent_inode, i, true)?.unwrap();
                     self.device.write_block(block_idx, &buf);
-                    return;
+                    parent_inode.mtime = self.now();
+                    self.write_inode_to_disk(parent_ino, &parent_inode);
+                    return Ok(());
                 }
             }
         }
+        Ok(())
     }
 
-    // rm 命令
-    pub fn rm(&mut self, path: &str) {
-        if path == "/" {
-            println!("rm: Cannot remove root");
-            return;
+    // 目录中的全部有效条目（包括 "." 和 ".."）；快照中的目录项换算成快照中的 Inode 编号
+    fn dir_entries(&mut self, dir_ino: u32) -> Vec<DirEntry> {
+        if dir_ino == SNAPSHOT_DIR_INO {
+            let mut entries = vec![
+                DirEntry {
+                    inode_no: SNAPSHOT_DIR_INO,
+                    name: ".".to_string(),
+                },
+                DirEntry {
+                    inode_no: 1,
+                    name: "..".to_string(),
+                },
+            ];
+            for (slot, record) in self.snapshot_records() {
+                entries.push(DirEntry {
+                    inode_no: snapshot_ino(slot, 1),
+                    name: record.name,
+                });
+            }
+            return entries;
         }
-
-        let ino = match self.resolve_path(path) {
-            Some(i) => i,
-            None => {
-                println!("rm: File not found");
-                return;
+        let slot = split_ino(dir_ino).0;
+        let inode = self.read_inode(dir_ino);
+        let mut entries = Vec::new();
+        let mut buf = [0u8; BLOCK_SIZE];
+        for &data_block_idx in inode.blocks.iter() {
+            if data_block_idx == 0 {
+                continue;
             }
-        };
-
-        let inode = self.read_inode(ino);
-
-        // 简单起见，只支持了删空目录
-        if inode.mode == InodeType::Directory {
-            let mut count = 0;
-            let mut buf = [0u8; BLOCK_SIZE];
-            self.device.read_block(inode.blocks[0], &mut buf);
+            self.device.read_block(data_block_idx, &mut buf);
             for i in 0..(BLOCK_SIZE / DIR_ENTRY_SIZE) {
-                let e = DirEntry::deserialize(&buf[i * DIR_ENTRY_SIZE..(i + 1) * DIR_ENTRY_SIZE]);
-                if e.inode_no != 0 {
-                    count += 1;
+                let start = i * DIR_ENTRY_SIZE;
+                let mut entry = DirEntry::deserialize(&buf[start..start + DIR_ENTRY_SIZE]);
+                if entry.inode_no != 0 {
+                    if let Some(slot) = slot {
+                        entry.inode_no = snapshot_ino(slot, entry.inode_no);
+                    }
+                    entries.push(entry);
                 }
             }
-            if count > 2 {
-                println!("rm: Directory not empty");
-                return;
-            }
         }
+        entries
+    }
 
-        // 1. 释放所有数据块
-        for &b in inode.blocks.iter() {
-            if b != 0 {
-                self.free_data_block(b);
+    // 目录中除 "." 和 ".." 以外的条目
+    fn child_entries(&mut self, dir_ino: u32) -> Vec<DirEntry> {
+        self.dir_entries(dir_ino)
+            .into_iter()
+            .filter(|e| e.name != "." && e.name != "..")
+            .collect()
+    }
+
+    pub fn join_path(dir: &str, name: &str) -> String {
+        if dir.ends_with('/') {
+            format!("{}{}", dir, name)
+        } else {
+            format!("{}/{}", dir, name)
+        }
+    }
+
+    // ancestor 是否等于 path 或是 path 的上级目录
+    fn is_same_or_ancestor(ancestor: &str, path: &str) -> bool {
+        ancestor == "/" || path == ancestor || path.starts_with(&format!("{}/", ancestor))
+    }
+
+    // 目录 dir_ino 是否就是 ancestor_ino 或位于它的子树中：沿 ".." 逐级向上直到根目录。
+    // 按 Inode 判断，经过符号链接的路径也能识别
+    fn is_within(&mut self, dir_ino: u32, ancestor_ino: u32) -> bool {
+        let mut ino = dir_ino;
+        for _ in 0..=self.read_superblock().inode_count {
+            if ino == ancestor_ino {
+                return true;
+            }
+            match self.find_in_dir(ino, "..") {
+                Some(parent) if parent != ino => ino = parent,
+                _ => return false,
             }
         }
+        false
+    }
 
-        // 2. 释放 Inode
+    // 释放 Inode 及其所有数据块；目录会先递归释放其下的所有条目
+    fn free_tree(&mut self, ino: u32) {
+        let mut inode = self.read_inode(ino);
+        // 还有其他硬链接指向该文件时只减少链接数
+        if inode.mode != InodeType::Directory && inode.nlinks > 1 {
+            self.adjust_nlinks(ino, -1);
+            return;
+        }
+        if inode.mode == InodeType::Directory {
+            for entry in self.child_entries(ino) {
+                self.free_tree(entry.inode_no);
+            }
+        }
+        // 从 0 开始释放不会失败
+        let _ = self.free_blocks_from(&mut inode, 0);
         self.free_inode(ino);
+    }
 
-        // 3. 删除父子关系
-        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("/", path));
-        let parent_ino = self
-            .resolve_path(if parent_path.is_empty() {
-                "/"
-            } else {
-                parent_path
-            })
-            .unwrap();
-        self.remove_dir_entry(parent_ino, name);
+    // remove：删除文件或空目录；recursive 为 true 时连同目录下的整棵子树一起删除
+    pub fn remove(&mut self, path: &str, recursive: bool) -> Result<(), FsError> {
+        let (parent_path, name) = Self::split_path(path);
+        if path == "/" || name.is_empty() || name == "." || name == ".." {
+            return Err(FsError::InvalidArgument);
+        }
+
+        let ino = self.lookup(path, false)?;
+        check_writable(ino)?;
+        let inode = self.read_inode(ino);
+
+        if inode.mode == InodeType::Directory {
+            // 不能删除当前工作目录或它的上级目录
+            if Self::is_same_or_ancestor(path, &self.cwd_path) {
+                return Err(FsError::Busy);
+            }
+            if !recursive && !self.child_entries(ino).is_empty() {
+                return Err(FsError::NotEmpty);
+            }
+        }
 
-        println!("Removed '{}'", path);
+        // 1. 删除父子关系（目录块可能要写时复制，先做可能失败的这一步）
+        let parent_ino = self.resolve_path(parent_path).unwrap();
+        self.remove_dir_entry(parent_ino, name)?;
+
+        // 2. 释放 Inode 及所有数据块（包括间接块和子树）
+        self.free_tree(ino);
+        if inode.mode == InodeType::Directory {
+            self.adjust_nlinks(parent_ino, -1);
+        }
+        Ok(())
     }
 
-    // cp 命令
-    pub fn cp(&mut self, src_path: &str, dst_path: &str) {
-        let src_ino = match self.resolve_path(src_path) {
-            Some(i) => i,
-            None => {
-                println!("cp: Source not found");
-                return;
+    // copy：复制文件；recursive 为 true 时可以复制整个目录，reflink 为 true 时
+    // 目标和源共享数据块（写时复制），不复制数据也不占用新的数据块
+    // 目标是已存在的目录时，复制到该目录下的同名条目
+    pub fn copy(
+        &mut self,
+        src_path: &str,
+        dst_path: &str,
+        recursive: bool,
+        reflink: bool,
+    ) -> Result<(), FsError> {
+        let src_ino = self.resolve_path(src_path).ok_or(FsError::NotFound)?;
+        let src_inode = self.read_inode(src_ino);
+
+        let dst_path = match self.resolve_path(dst_path) {
+            Some(ino) if self.read_inode(ino).mode == InodeType::Directory => {
+                Self::join_path(dst_path, Self::split_path(src_path).1)
             }
+            _ => dst_path.to_string(),
         };
-        let src_inode = self.read_inode(src_ino);
-        if src_inode.mode != InodeType::File {
-            println!("cp: Only file copying is supported");
-            return;
-        }
 
-        // 其实就是 touch + write
-        self.touch(dst_path);
+        if src_inode.mode == InodeType::Directory {
+            if !recursive {
+                return Err(FsError::IsADirectory);
+            }
+            // 不能把目录复制到它自己的子树中
+            let dst_parent = Self::split_path(&dst_path).0;
+            if let Some(dst_parent_ino) = self.resolve_path(dst_parent)
+                && self.is_within(dst_parent_ino, src_ino)
+            {
+                return Err(FsError::InvalidArgument);
+            }
+            self.copy_tree(src_ino, &dst_path, reflink)
+        } else {
+            self.copy_file(src_ino, &dst_path, reflink)
+        }
+    }
 
-        if src_inode.blocks[0] != 0 {
-            let mut buf = [0u8; BLOCK_SIZE];
-            self.device.read_block(src_inode.blocks[0], &mut buf);
-            let content = String::from_utf8_lossy(&buf[..src_inode.size as usize]).to_string();
-            self.write_file(dst_path, &content);
+    fn copy_file(&mut self, src_ino: u32, dst_path: &str, reflink: bool) -> Result<(), FsError> {
+        let dst_ino = match self.resolve_path(dst_path) {
+            Some(ino) if ino == src_ino => return Err(FsError::InvalidArgument),
+            Some(ino) if self.read_inode(ino).mode == InodeType::Directory => {
+                return Err(FsError::IsADirectory);
+            }
+            Some(ino) => {
+                check_writable(ino)?;
+                ino
+            }
+            None => self.create_file(dst_path)?,
+        };
+        if reflink {
+            self.reflink_file_data(src_ino, dst_ino)
+        } else {
+            self.copy_file_data(src_ino, dst_ino)
         }
     }
 
-    // mv 命令
-    pub fn mv(&mut self, src_path: &str, dst_path: &str) {
-        let src_ino = match self.resolve_path(src_path) {
-            Some(i) => i,
-            None => {
-                println!("mv: Source not found");
-                return;
+    fn copy_tree(&mut self, src_ino: u32, dst_path: &str, reflink: bool) -> Result<(), FsError> {
+        let dst_ino = match self.resolve_path(dst_path) {
+            Some(ino) if self.read_inode(ino).mode == InodeType::Directory => {
+                check_writable(ino)?;
+                ino
             }
+            Some(_) => return Err(FsError::NotADirectory),
+            None => self.create_dir(dst_path)?,
         };
+        let mut dst_inode = self.read_inode(dst_ino);
+        dst_inode.perm = self.read_inode(src_ino).perm;
+        self.write_inode_to_disk(dst_ino, &dst_inode);
+
+        for entry in self.child_entries(src_ino) {
+            let child_dst = Self::join_path(dst_path, &entry.name);
+            let mut child = self.read_inode(entry.inode_no);
+            match child.mode {
+                InodeType::Directory => self.copy_tree(entry.inode_no, &child_dst, reflink)?,
+                // 子树中的符号链接按链接本身复制
+                InodeType::Symlink => {
+                    let size = child.size as usize;
+                    let target = self.read_inode_range(entry.inode_no, &mut child, 0, size)?;
+                    self.symlink(&String::from_utf8_lossy(&target), &child_dst)?;
+                }
+                _ => self.copy_file(entry.inode_no, &child_dst, reflink)?,
+            }
+        }
+        Ok(())
+    }
 
-        let (dst_parent_path, dst_name) = dst_path.rsplit_once('/').unwrap_or(("/", dst_path));
-        let dst_parent_ino = match self.resolve_path(if dst_parent_path.is_empty() {
-            "/"
+    // 逐块复制原始数据，目标原有的数据块先全部释放
+    fn copy_file_data(&mut self, src_ino: u32, dst_ino: u32) -> Result<(), FsError> {
+        let mut src_inode = self.read_inode(src_ino);
+        let mut dst_inode = self.read_inode(dst_ino);
+        let _ = self.free_blocks_from(&mut dst_inode, 0);
+        dst_inode.size = 0;
+        dst_inode.perm = src_inode.perm;
+
+        let mut buf = [0u8; BLOCK_SIZE];
+        let mut result = Ok(());
+        if (src_inode.flags | dst_inode.flags) & INODE_FLAG_COMPRESSED != 0 {
+            // 两边的块布局可能不同（压缩簇或原样存放），按解压后的内容写入目标
+            let size = src_inode.size as usize;
+            result = match self.read_inode_range(src_ino, &mut src_inode, 0, size) {
+                Ok(data) => self.write_at_inode(&mut dst_inode, 0, &data).1,
+                Err(e) => Err(e),
+            };
         } else {
-            dst_parent_path
-        }) {
-            Some(i) => i,
-            None => {
-                println!("mv: Destination path invalid");
-                return;
+            for file_block in 0..(src_inode.size as usize).div_ceil(BLOCK_SIZE) {
+                // 源文件中的空洞不必复制；出错时也要在下面写回目标 Inode
+                let src_block = match self.bmap(&mut src_inode, file_block, false) {
+                    Ok(Some(block)) => block,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                };
+                match self.bmap(&mut dst_inode, file_block, true) {
+                    Ok(dst_block) => {
+                        self.device.read_block(src_block, &mut buf);
+                        self.device.write_block(dst_block.unwrap(), &buf);
+                    }
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
             }
+        }
+
+        if result.is_ok() {
+            dst_inode.size = src_inode.size;
+        }
+        dst_inode.mtime = self.now();
+        dst_inode.ctime = dst_inode.mtime;
+        self.write_inode_to_disk(dst_ino, &dst_inode);
+        result
+    }
+
+    // reflink 复制：目标直接使用源的块指针，直接块和间接块各增加一个引用
+    // （间接块中的数据块仍然只被这一个间接块引用），之后任何一方写入时才复制；
+    // 块的内容按源的压缩标志解释，所以目标也使用源的压缩标志
+    fn reflink_file_data(&mut self, src_ino: u32, dst_ino: u32) -> Result<(), FsError> {
+        self.ensure_refcounts()?;
+        let src_inode = self.read_inode(src_ino);
+        let mut dst_inode = self.read_inode(dst_ino);
+        self.share_inode_blocks(&src_inode);
+        let _ = self.free_blocks_from(&mut dst_inode, 0);
+        // 源文件没有数据块时没有共享任何块
+        self.release_unused_refcounts();
+
+        dst_inode.blocks = src_inode.blocks;
+        dst_inode.indirect = src_inode.indirect;
+        dst_inode.size = src_inode.size;
+        dst_inode.perm = src_inode.perm;
+        dst_inode.flags = src_inode.flags;
+        dst_inode.mtime = self.now();
+        dst_inode.ctime = dst_inode.mtime;
+        self.write_inode_to_disk(dst_ino, &dst_inode);
+        Ok(())
+    }
+
+    // mv：目标是已存在的目录时，移动到该目录下的同名条目；返回最终的目标路径
+    pub fn mv(&mut self, src_path: &str, dst_path: &str) -> Result<String, FsError> {
+        let dst_path = match self.resolve_path(dst_path) {
+            Some(ino) if self.read_inode(ino).mode == InodeType::Directory => {
+                Self::join_path(dst_path, Self::split_path(src_path).1)
+            }
+            _ => dst_path.to_string(),
         };
+        self.rename(src_path, &dst_path)?;
+        Ok(dst_path)
+    }
+
+    // rename：与 rename(2) 语义一致
+    // 目标已存在时原子地替换（目录只能替换空目录），不能把目录移动到它自己的子树中
+    pub fn rename(&mut self, src_path: &str, dst_path: &str) -> Result<(), FsError> {
+        let (src_parent_path, src_name) = Self::split_path(src_path);
+        let (dst_parent_path, dst_name) = Self::split_path(dst_path);
+        if src_path == "/" || dst_path == "/" {
+            return Err(FsError::Busy);
+        }
+        if src_name == "." || src_name == ".." {
+            return Err(FsError::InvalidArgument);
+        }
+        if dst_name == "." || dst_name == ".." || dst_name.len() > DIR_ENTRY_SIZE - 4 {
+            return Err(FsError::InvalidArgument);
+        }
+
+        let src_ino = self.lookup(src_path, false)?;
+        check_writable(src_ino)?;
+        if src_path == dst_path {
+            return Ok(());
+        }
+        let mut src_inode = self.read_inode(src_ino);
+        let is_dir = src_inode.mode == InodeType::Directory;
+
+        let dst_parent_ino = self
+            .resolve_path(dst_parent_path)
+            .ok_or(FsError::NotFound)?;
+        if self.read_inode(dst_parent_ino).mode != InodeType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        // 不能把目录移动到它自己的子树中
+        if is_dir && self.is_within(dst_parent_ino, src_ino) {
+            return Err(FsError::InvalidArgument);
+        }
+        check_writable(dst_parent_ino)?;
+        let src_parent_ino = self.resolve_path(src_parent_path).unwrap();
 
         // 其实也可以认为是 rm + write，但这样有不必要的开销，最好是直接更新相关索引而不动数据块
-        self.add_dir_entry(dst_parent_ino, src_ino, dst_name);
+        match self.find_in_dir(dst_parent_ino, dst_name) {
+            Some(dst_ino) if dst_ino == src_ino => return Ok(()),
+            Some(dst_ino) => {
+                let dst_is_dir = self.read_inode(dst_ino).mode == InodeType::Directory;
+                match (is_dir, dst_is_dir) {
+                    (false, true) => return Err(FsError::IsADirectory),
+                    (true, false) => return Err(FsError::NotADirectory),
+                    (true, true) if !self.child_entries(dst_ino).is_empty() => {
+                        return Err(FsError::NotEmpty);
+                    }
+                    _ => {}
+                }
+                if dst_is_dir && Self::is_same_or_ancestor(dst_path, &self.cwd_path) {
+                    return Err(FsError::Busy);
+                }
 
-        let (src_parent_path, src_name) = src_path.rsplit_once('/').unwrap_or(("/", src_path));
-        let src_parent_ino = self
-            .resolve_path(if src_parent_path.is_empty() {
-                "/"
-            } else {
-                src_parent_path
-            })
-            .unwrap();
-        self.remove_dir_entry(src_parent_ino, src_name);
+                // 直接改写目标条目指向的 Inode，替换是一次块写入完成的
+                self.set_dir_entry(dst_parent_ino, dst_name, src_ino)?;
+                self.free_tree(dst_ino);
+                if dst_is_dir {
+                    self.adjust_nlinks(dst_parent_ino, -1);
+                }
+            }
+            None => self.add_dir_entry(dst_parent_ino, src_ino, dst_name)?,
+        }
+        self.remove_dir_entry(src_parent_ino, src_name)?;
 
-        let mut inode = self.read_inode(src_ino);
-        if inode.mode == InodeType::Directory {
+        if is_dir && src_parent_ino != dst_parent_ino {
+            // 更新被移动目录的 ".." 以及新旧父目录的链接数
+            src_inode = self.read_inode(src_ino);
+            let block_idx = self.bmap(&mut src_inode, 0, true)?.unwrap();
             let mut buf = [0u8; BLOCK_SIZE];
-            self.device.read_block(inode.blocks[0], &mut buf);
+            self.device.read_block(block_idx, &mut buf);
             let mut dotdot = DirEntry::deserialize(&buf[64..128]);
             dotdot.inode_no = dst_parent_ino;
             buf[64..128].copy_from_slice(&dotdot.serialize());
-            self.device.write_block(inode.blocks[0], &buf);
+            self.device.write_block(block_idx, &buf);
+            self.write_inode_to_disk(src_ino, &src_inode);
+
+            self.adjust_nlinks(src_parent_ino, -1);
+            self.adjust_nlinks(dst_parent_ino, 1);
+        }
+        if is_dir && Self::is_same_or_ancestor(src_path, &self.cwd_path) {
+            // 当前目录随之移动
+            self.cwd_path = format!("{}{}", dst_path, &self.cwd_path[src_path.len()..]);
+        }
+
+        src_inode = self.read_inode(src_ino);
+        src_inode.ctime = self.now();
+        self.write_inode_to_disk(src_ino, &src_inode);
+        Ok(())
+    }
+
+    fn metadata_of(&mut self, ino: u32) -> Metadata {
+        let inode = self.read_inode(ino);
+        Metadata {
+            ino,
+            kind: inode.mode,
+            size: inode.size,
+            blocks: self.data_blocks(&inode),
+            indirect: inode.indirect,
+            nlinks: inode.nlinks,
+            perm: inode.perm,
+            uid: inode.uid,
+            gid: inode.gid,
+            atime: inode.atime,
+            mtime: inode.mtime,
+            ctime: inode.ctime,
+            compressed: inode.flags & INODE_FLAG_COMPRESSED != 0,
+        }
+    }
+
+    // stat：返回路径指向的 Inode 的元数据
+    pub fn stat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        let ino = self.lookup(path, true)?;
+        Ok(self.metadata_of(ino))
+    }
+
+    // lstat：不跟随路径最后一个分量的符号链接
+    pub fn lstat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        let ino = self.lookup(path, false)?;
+        Ok(self.metadata_of(ino))
+    }
+
+    // symlink：创建指向 target 的符号链接，target 原样保存，不检查是否存在
+    pub fn symlink(&mut self, target: &str, link_path: &str) -> Result<u32, FsError> {
+        let (parent_ino, link_name) = self.prepare_new_entry(link_path)?;
+        if target.is_empty() {
+            return Err(FsError::InvalidArgument);
+        }
+
+        let new_ino = self.alloc_inode().ok_or(FsError::NoSpace)?;
+        let mut new_inode = Inode::new(InodeType::Symlink, self.now());
+        let (_, result) = self.write_at_inode(&mut new_inode, 0, target.as_bytes());
+        self.write_inode_to_disk(new_ino, &new_inode);
+
+        if let Err(e) = result.and_then(|_| self.add_dir_entry(parent_ino, new_ino, link_name)) {
+            self.free_tree(new_ino);
+            return Err(e);
         }
+        Ok(new_ino)
+    }
 
-        println!("Moved '{}' to '{}'", src_path, dst_path);
+    // read_link：读取符号链接保存的目标路径
+    pub fn read_link(&mut self, path: &str) -> Result<String, FsError> {
+        let ino = self.lookup(path, false)?;
+        self.read_link_ino(ino)
+    }
+
+    fn read_link_ino(&mut self, ino: u32) -> Result<String, FsError> {
+        let mut inode = self.read_inode(ino);
+        if inode.mode != InodeType::Symlink {
+            return Err(FsError::InvalidArgument);
+        }
+        let size = inode.size as usize;
+        let target = self.read_inode_range(ino, &mut inode, 0, size)?;
+        Ok(String::from_utf8_lossy(&target).into_owned())
+    }
+
+    // link：为已有文件创建硬链接，目录不能硬链接
+    pub fn link(&mut self, existing: &str, new_path: &str) -> Result<(), FsError> {
+        let ino = self.lookup(existing, false)?;
+        if self.read_inode(ino).mode == InodeType::Directory {
+            return Err(FsError::IsADirectory);
+        }
+        let (parent_ino, name) = self.prepare_new_entry(new_path)?;
+        self.add_dir_entry(parent_ino, ino, name)?;
+        self.adjust_nlinks(ino, 1);
+        Ok(())
+    }
+
+    // 修改属主和属组（chown）
+    pub fn set_owner(&mut self, path: &str, uid: u32, gid: u32) -> Result<(), FsError> {
+        let ino = self.lookup(path, false)?;
+        check_writable(ino)?;
+        let mut inode = self.read_inode(ino);
+        inode.uid = uid;
+        inode.gid = gid;
+        inode.ctime = self.now();
+        self.write_inode_to_disk(ino, &inode);
+        Ok(())
+    }
+
+    // complete：补全路径的最后一个分量，返回以它开头的所有候选路径，目录带 "/" 后缀
+    // 相对路径从当前目录的 Inode 开始，用 find_in_dir 逐级解析前面的分量
+    pub fn complete(&mut self, partial: &str) -> Vec<String> {
+        let (dir_part, prefix) = match partial.rfind('/') {
+            Some(i) => (&partial[..=i], &partial[i + 1..]),
+            None => ("", partial),
+        };
+
+        let mut dir_ino = if partial.starts_with('/') {
+            1
+        } else {
+            self.cwd_ino
+        };
+        for part in dir_part.split('/').filter(|s| !s.is_empty()) {
+            match self.lookup_from(dir_ino, part) {
+                Some(ino) if self.read_inode(ino).mode == InodeType::Directory => dir_ino = ino,
+                _ => return Vec::new(),
+            }
+        }
+
+        let mut candidates: Vec<String> = self
+            .dir_entries(dir_ino)
+            .into_iter()
+            .filter(|e| e.name.starts_with(prefix))
+            // "." 和 ".." 只有在明确输入了 "." 时才作为候选
+            .filter(|e| !(e.name == "." || e.name == "..") || prefix.starts_with('.'))
+            .map(|e| {
+                let is_dir = match self.lookup_from(dir_ino, &e.name) {
+                    Some(ino) => self.read_inode(ino).mode == InodeType::Directory,
+                    None => false,
+                };
+                format!("{}{}{}", dir_part, e.name, if is_dir { "/" } else { "" })
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+
+    // 在目录中查找 name，是符号链接时跟随到目标（"." 和 ".." 也是目录中的条目）
+    fn lookup_from(&mut self, dir_ino: u32, name: &str) -> Option<u32> {
+        let ino = self.find_in_dir(dir_ino, name)?;
+        if self.read_inode(ino).mode != InodeType::Symlink {
+            return Some(ino);
+        }
+        let target = self.read_link_ino(ino).ok()?;
+        if target.starts_with('/') {
+            return self.lookup(&target, true).ok();
+        }
+        // 相对链接从所在目录开始解析
+        let mut current = dir_ino;
+        for part in target.split('/').filter(|s| !s.is_empty() && *s != ".") {
+            current = self.find_in_dir(current, part)?;
+        }
+        Some(current)
+    }
+
+    // read_dir：列出目录中除 "." 和 ".." 以外的条目
+    pub fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let ino = self.resolve_path(path).ok_or(FsError::NotFound)?;
+        if self.read_inode(ino).mode != InodeType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(self.child_entries(ino))
+    }
+
+    // walk：从 root 开始递归遍历目录树，返回每个条目的路径和元数据
+    pub fn walk(&mut self, root: &str) -> Walk<'_> {
+        Walk::new(self, root)
+    }
+
+    // 修改权限位（chmod）
+    pub fn set_permissions(&mut self, path: &str, perm: u32) -> Result<(), FsError> {
+        let ino = self.resolve_path(path).ok_or(FsError::NotFound)?;
+        check_writable(ino)?;
+        let mut inode = self.read_inode(ino);
+        inode.perm = perm & 0o7777;
+        inode.ctime = self.now();
+        self.write_inode_to_disk(ino, &inode);
+        Ok(())
+    }
+
+    // 修改访问时间和修改时间（utimes）
+    pub fn set_times(&mut self, path: &str, atime: u64, mtime: u64) -> Result<(), FsError> {
+        let ino = self.resolve_path(path).ok_or(FsError::NotFound)?;
+        check_writable(ino)?;
+        let mut inode = self.read_inode(ino);
+        inode.atime = atime;
+        inode.mtime = mtime;
+        inode.ctime = self.now();
+        self.write_inode_to_disk(ino, &inode);
+        Ok(())
+    }
+
+    // 快照表中槽位 slot 的记录，空槽位返回 None
+    fn snapshot_record(&mut self, slot: usize) -> Option<SnapshotRecord> {
+        let sb = self.read_superblock();
+        if sb.snapshot_table == 0 || slot >= MAX_SNAPSHOTS {
+            return None;
+        }
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(sb.snapshot_table, &mut buf);
+        let start = slot * SNAPSHOT_RECORD_SIZE;
+        let record = SnapshotRecord::deserialize(&buf[start..start + SNAPSHOT_RECORD_SIZE]);
+        (record.descriptor != 0).then_some(record)
+    }
+
+    // 所有快照，按槽位顺序
+    fn snapshot_records(&mut self) -> Vec<(usize, SnapshotRecord)> {
+        (0..MAX_SNAPSHOTS)
+            .filter_map(|slot| self.snapshot_record(slot).map(|r| (slot, r)))
+            .collect()
+    }
+
+    fn write_snapshot_record(&mut self, slot: usize, record: &[u8; SNAPSHOT_RECORD_SIZE]) {
+        let table = self.read_superblock().snapshot_table;
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(table, &mut buf);
+        let start = slot * SNAPSHOT_RECORD_SIZE;
+        buf[start..start + SNAPSHOT_RECORD_SIZE].copy_from_slice(record);
+        self.device.write_block(table, &buf);
+    }
+
+    // 快照描述块中的块号：Inode 位图副本，然后是 Inode 表副本的各块
+    fn snapshot_blocks(&mut self, descriptor: u32) -> Vec<u32> {
+        let count = 1 + inode_table_blocks(self.read_superblock().inode_count) as usize;
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(descriptor, &mut buf);
+        buf.chunks_exact(4)
+            .take(count)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    fn find_snapshot(&mut self, name: &str) -> Result<(usize, SnapshotRecord), FsError> {
+        self.snapshot_records()
+            .into_iter()
+            .find(|(_, r)| r.name == name)
+            .ok_or(FsError::NotFound)
+    }
+
+    // 实时文件系统（slot 为 None）或某个快照中所有在用的 Inode
+    fn used_inodes(&mut self, slot: Option<usize>) -> Vec<Inode> {
+        let count = self.read_superblock().inode_count;
+        (1..count)
+            .map(|ino| match slot {
+                Some(slot) => self.read_inode(snapshot_ino(slot, ino)),
+                None => self.read_inode(ino),
+            })
+            .filter(|inode| inode.mode != InodeType::Unused)
+            .collect()
+    }
+
+    // Inode 的又一个副本引用了它的直接块和间接块（间接块中的数据块仍然只被间接块引用）
+    fn share_inode_blocks(&mut self, inode: &Inode) {
+        for &b in inode.blocks.iter().chain([&inode.indirect]) {
+            if b != 0 {
+                self.add_ref(b);
+            }
+        }
+    }
+
+    // snapshot create：冻结整棵目录树。复制 Inode 位图和 Inode 表，
+    // 数据块、目录块和间接块不复制，只增加引用计数，之后的修改写时复制
+    pub fn snapshot_create(&mut self, name: &str) -> Result<(), FsError> {
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains('/')
+            || name.len() > MAX_SNAPSHOT_NAME
+        {
+            return Err(FsError::InvalidArgument);
+        }
+        let records = self.snapshot_records();
+        if records.iter().any(|(_, r)| r.name == name) {
+            return Err(FsError::AlreadyExists);
+        }
+        let slot = (0..MAX_SNAPSHOTS)
+            .find(|slot| records.iter().all(|(s, _)| s != slot))
+            .ok_or(FsError::NoSpace)?;
+
+        if self.read_superblock().snapshot_table == 0 {
+            // 根目录中已有真实的 .snapshots 时，快照目录无处可放
+            if self.find_in_dir(1, SNAPSHOT_DIR).is_some() {
+                return Err(FsError::AlreadyExists);
+            }
+            let table = self.alloc_zeroed_block()?;
+            let mut sb = self.read_superblock();
+            sb.snapshot_table = table;
+            self.write_superblock(&sb);
+        }
+        // 描述块、Inode 位图副本、Inode 表副本；空间不足时释放刚建立的表
+        let table_blocks = inode_table_blocks(self.read_superblock().inode_count);
+        let blocks = match self
+            .ensure_refcounts()
+            .and_then(|_| self.alloc_blocks(2 + table_blocks as usize))
+        {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                self.release_unused_refcounts();
+                self.release_unused_snapshot_table();
+                return Err(e);
+            }
+        };
+        let mut buf = [0u8; BLOCK_SIZE];
+        let mut descriptor = [0u8; BLOCK_SIZE];
+        for (i, &b) in blocks[1..].iter().enumerate() {
+            let src = if i == 0 {
+                1
+            } else {
+                INODE_TABLE_START + i as u32 - 1
+            };
+            self.device.read_block(src, &mut buf);
+            self.device.write_block(b, &buf);
+            descriptor[i * 4..i * 4 + 4].copy_from_slice(&b.to_le_bytes());
+        }
+        self.device.write_block(blocks[0], &descriptor);
+
+        for inode in self.used_inodes(None) {
+            self.share_inode_blocks(&inode);
+        }
+        let record = SnapshotRecord {
+            name: name.to_string(),
+            created: self.now(),
+            descriptor: blocks[0],
+        };
+        self.write_snapshot_record(slot, &record.serialize());
+        Ok(())
+    }
+
+    // snapshot list：按创建顺序（槽位顺序）列出所有快照
+    pub fn snapshot_list(&mut self) -> Vec<SnapshotInfo> {
+        self.snapshot_records()
+            .into_iter()
+            .map(|(_, r)| SnapshotInfo {
+                name: r.name,
+                created: r.created,
+            })
+            .collect()
+    }
+
+    // snapshot delete：放弃快照对所有块的引用，只被它引用的块回到空闲位图
+    pub fn snapshot_delete(&mut self, name: &str) -> Result<(), FsError> {
+        let (slot, record) = self.find_snapshot(name)?;
+        if split_ino(self.cwd_ino).0 == Some(slot) {
+            return Err(FsError::Busy);
+        }
+        for mut inode in self.used_inodes(Some(slot)) {
+            let _ = self.free_blocks_from(&mut inode, 0);
+        }
+        for b in self.snapshot_blocks(record.descriptor) {
+            self.free_data_block(b);
+        }
+        self.free_data_block(record.descriptor);
+        self.write_snapshot_record(slot, &[0u8; SNAPSHOT_RECORD_SIZE]);
+        self.release_unused_snapshot_table();
+        Ok(())
+    }
+
+    // 没有快照时释放快照表，超级块中的快照表清零
+    fn release_unused_snapshot_table(&mut self) {
+        let mut sb = self.read_superblock();
+        if sb.snapshot_table == 0 || !self.snapshot_records().is_empty() {
+            return;
+        }
+        let table = std::mem::take(&mut sb.snapshot_table);
+        self.write_superblock(&sb);
+        self.free_data_block(table);
+    }
+
+    // snapshot rollback：把实时文件系统恢复成快照的状态，快照本身保留
+    pub fn snapshot_rollback(&mut self, name: &str) -> Result<(), FsError> {
+        let (slot, record) = self.find_snapshot(name)?;
+        // 快照独占的块引用数为 0，所有块都可能没有共享，引用计数表已被释放
+        self.ensure_refcounts()?;
+        // 先让实时文件系统也引用快照中的块，再放弃当前的块，两边共享的块不会被释放
+        for inode in self.used_inodes(Some(slot)) {
+            self.share_inode_blocks(&inode);
+        }
+        for mut inode in self.used_inodes(None) {
+            let _ = self.free_blocks_from(&mut inode, 0);
+        }
+
+        let mut buf = [0u8; BLOCK_SIZE];
+        for (i, b) in self
+            .snapshot_blocks(record.descriptor)
+            .into_iter()
+            .enumerate()
+        {
+            let dst = if i == 0 {
+                1
+            } else {
+                INODE_TABLE_START + i as u32 - 1
+            };
+            self.device.read_block(b, &mut buf);
+            self.device.write_block(dst, &buf);
+        }
+        let mut sb = self.read_superblock();
+        sb.free_inodes = self.count_free_bits(1, sb.inode_count);
+        self.write_superblock(&sb);
+
+        self.cwd_ino = 1;
+        self.cwd_path = "/".to_string();
+        Ok(())
+    }
+
+    // 所有实时文件（不含快照）的数据块指针及其指向的块；多个文件共享的间接块只列出一次
+    fn file_block_pointers(&mut self) -> Vec<(BlockPointer, u32)> {
+        let count = self.read_superblock().inode_count;
+        let mut seen_indirect = HashSet::new();
+        let mut pointers = Vec::new();
+        let mut buf = [0u8; BLOCK_SIZE];
+        for ino in 1..count {
+            let inode = self.read_inode(ino);
+            if inode.mode != InodeType::File {
+                continue;
+            }
+            for (i, &b) in inode.blocks.iter().enumerate() {
+                if b != 0 {
+                    pointers.push((BlockPointer::Direct(ino, i), b));
+                }
+            }
+            if inode.indirect != 0 && seen_indirect.insert(inode.indirect) {
+                self.device.read_block(inode.indirect, &mut buf);
+                for (i, chunk) in buf.chunks_exact(4).enumerate() {
+                    let b = u32::from_le_bytes(chunk.try_into().unwrap());
+                    if b != 0 {
+                        pointers.push((BlockPointer::Indirect(inode.indirect, i), b));
+                    }
+                }
+            }
+        }
+        pointers
+    }
+
+    // 改写一个数据块指针。共享的间接块也可以直接改写：新旧块内容相同，所有共享者看到的数据不变
+    fn set_block_pointer(&mut self, pointer: BlockPointer, block_idx: u32) {
+        match pointer {
+            BlockPointer::Direct(ino, i) => {
+                let mut inode = self.read_inode(ino);
+                inode.blocks[i] = block_idx;
+                self.write_inode_to_disk(ino, &inode);
+            }
+            BlockPointer::Indirect(indirect, i) => {
+                let mut buf = [0u8; BLOCK_SIZE];
+                self.device.read_block(indirect, &mut buf);
+                buf[i * 4..i * 4 + 4].copy_from_slice(&block_idx.to_le_bytes());
+                self.device.write_block(indirect, &buf);
+            }
+        }
+    }
+
+    // 在索引中找与 block_idx（内容为 data）相同的另一个块；没有时把 block_idx 加入索引
+    fn find_duplicate(
+        &mut self,
+        index: &mut DedupIndex,
+        block_idx: u32,
+        data: &[u8; BLOCK_SIZE],
+    ) -> Option<u32> {
+        let hash = block_hash(data);
+        let mut buf = [0u8; BLOCK_SIZE];
+        for &c in index.blocks.get(&hash).into_iter().flatten() {
+            if c == block_idx {
+                return None;
+            }
+            self.device.read_block(c, &mut buf);
+            if buf == *data {
+                return Some(c);
+            }
+        }
+        index.insert(block_idx, hash);
+        None
+    }
+
+    fn build_dedup_index(&mut self) -> DedupIndex {
+        let mut index = DedupIndex::default();
+        let mut buf = [0u8; BLOCK_SIZE];
+        for (_, b) in self.file_block_pointers() {
+            self.device.read_block(b, &mut buf);
+            index.insert(b, block_hash(&buf));
+        }
+        index
+    }
+
+    // 在线去重：刚写满的块与已有的块内容相同时，改为引用已有的块并放弃新块
+    fn dedup_written_block(
+        &mut self,
+        inode: &mut Inode,
+        file_block: usize,
+        block_idx: u32,
+        data: &[u8; BLOCK_SIZE],
+    ) {
+        let mut index = match self.dedup_index.take() {
+            Some(index) => index,
+            None => self.build_dedup_index(),
+        };
+        let duplicate = self.find_duplicate(&mut index, block_idx, data);
+        // 先放回索引，释放块时才能把它移出
+        self.dedup_index = Some(index);
+        let Some(c) = duplicate else {
+            return;
+        };
+        if self.ensure_refcounts().is_err() {
+            return;
+        }
+        self.add_ref(c);
+        if file_block < DIRECT_BLOCKS {
+            inode.blocks[file_block] = c;
+        } else {
+            let pointer = BlockPointer::Indirect(inode.indirect, file_block - DIRECT_BLOCKS);
+            self.set_block_pointer(pointer, c);
+        }
+        self.free_data_block(block_idx);
+    }
+
+    // dedup：离线去重。检查所有实时文件的数据块，内容相同的块合并为一个并按引用计数共享，
+    // 之后的写入照常写时复制，rm 只减少引用
+    pub fn dedup(&mut self) -> Result<DedupReport, FsError> {
+        let free_before = self.read_superblock().free_blocks;
+        let mut report = DedupReport {
+            scanned: 0,
+            merged: 0,
+            reclaimed: 0,
+        };
+        let mut index = DedupIndex::default();
+        let mut buf = [0u8; BLOCK_SIZE];
+        for (pointer, b) in self.file_block_pointers() {
+            report.scanned += 1;
+            self.device.read_block(b, &mut buf);
+            if let Some(c) = self.find_duplicate(&mut index, b, &buf) {
+                self.ensure_refcounts()?;
+                self.add_ref(c);
+                self.set_block_pointer(pointer, c);
+                self.free_data_block(b);
+                report.merged += 1;
+            }
+        }
+        // 索引中只剩下合并后保留的块，可以直接作为在线去重的索引
+        self.dedup_index = Some(index);
+        report.reclaimed = self
+            .read_superblock()
+            .free_blocks
+            .saturating_sub(free_before);
+        Ok(report)
+    }
+
+    pub fn inline_dedup(&mut self) -> bool {
+        self.read_superblock().flags & FLAG_INLINE_DEDUP != 0
+    }
+
+    // 打开或关闭在线去重，设置保存在超级块中
+    pub fn set_inline_dedup(&mut self, on: bool) {
+        let mut sb = self.read_superblock();
+        if on {
+            sb.flags |= FLAG_INLINE_DEDUP;
+        } else {
+            sb.flags &= !FLAG_INLINE_DEDUP;
+        }
+        self.write_superblock(&sb);
+    }
+
+    // 新建的文件和目录从父目录继承的标志
+    fn inherited_flags(&mut self, parent_ino: u32) -> u32 {
+        self.read_inode(parent_ino).flags & INODE_FLAG_COMPRESSED
+    }
+
+    // 把第 file_block 个逻辑块的指针改为 block_idx，返回原来的块号。
+    // 间接块范围内的指针要求间接块已经可写（见 writable_indirect）
+    fn replace_block(&mut self, inode: &mut Inode, file_block: usize, block_idx: u32) -> u32 {
+        if file_block < DIRECT_BLOCKS {
+            return std::mem::replace(&mut inode.blocks[file_block], block_idx);
+        }
+        if inode.indirect == 0 {
+            return 0;
+        }
+        let i = (file_block - DIRECT_BLOCKS) * 4;
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.device.read_block(inode.indirect, &mut buf);
+        let old = u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        if old != block_idx {
+            buf[i..i + 4].copy_from_slice(&block_idx.to_le_bytes());
+            self.device.write_block(inode.indirect, &buf);
+        }
+        old
+    }
+
+    // 准备改写间接块中的指针：共享的间接块先复制，alloc 为 true 时没有间接块就分配一个
+    fn writable_indirect(&mut self, inode: &mut Inode, alloc: bool) -> Result<(), FsError> {
+        if inode.indirect != 0 {
+            self.unshare_indirect(inode)
+        } else if alloc {
+            inode.indirect = self.alloc_zeroed_block()?;
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+
+    // 读出压缩文件第 c 簇的内容，size 是解释簇布局时使用的文件大小。簇中实际存放的块数：
+    // 0 表示全 0；等于逻辑块数表示原样存放；更少表示压缩存放，前 4 字节是压缩数据的长度
+    fn read_cluster(
+        &mut self,
+        inode: &mut Inode,
+        c: usize,
+        size: usize,
+    ) -> Result<Vec<u8>, FsError> {
+        let len = size.saturating_sub(c * CLUSTER_SIZE).min(CLUSTER_SIZE);
+        let mut data = vec![0u8; len];
+        let mut blocks = Vec::new();
+        for i in 0..len.div_ceil(BLOCK_SIZE) {
+            blocks.push(self.bmap(inode, c * CLUSTER_BLOCKS + i, false)?);
+        }
+        let stored = blocks.iter().take_while(|b| b.is_some()).count();
+        if stored == 0 {
+            return Ok(data);
+        }
+
+        let mut raw = Vec::with_capacity(stored * BLOCK_SIZE);
+        let mut buf = [0u8; BLOCK_SIZE];
+        for b in blocks[..stored].iter().flatten() {
+            self.device.read_block(*b, &mut buf);
+            raw.extend_from_slice(&buf);
+        }
+        if stored == blocks.len() {
+            data.copy_from_slice(&raw[..len]);
+            return Ok(data);
+        }
+        let clen = u32::from_le_bytes(raw[0..4].try_into().unwrap()) as usize;
+        let body = raw.get(4..4 + clen).ok_or(FsError::Corrupted)?;
+        lz::decompress(body, len).ok_or(FsError::Corrupted)
+    }
+
+    // 把压缩文件的第 c 簇写成 data，data 的长度是该簇在写入后的文件大小下的逻辑长度。
+    // 压缩后至少能省下一块时存放压缩数据，否则原样存放，全 0 的簇不占块。
+    // 新块全部分配成功后才替换并释放旧块，空间不足时该簇保持原样
+    fn write_cluster(&mut self, inode: &mut Inode, c: usize, data: &[u8]) -> Result<(), FsError> {
+        self.cluster_cache = None;
+        let logical_blocks = data.len().div_ceil(BLOCK_SIZE);
+        let payload = if data.iter().all(|&b| b == 0) {
+            Vec::new()
+        } else {
+            let compressed = lz::compress(data);
+            if (4 + compressed.len()).div_ceil(BLOCK_SIZE) < logical_blocks {
+                let mut payload = (compressed.len() as u32).to_le_bytes().to_vec();
+                payload.extend_from_slice(&compressed);
+                payload
+            } else {
+                data.to_vec()
+            }
+        };
+
+        let first = c * CLUSTER_BLOCKS;
+        let last = (first + CLUSTER_BLOCKS).min(MAX_FILE_BLOCKS);
+        let count = payload.len().div_ceil(BLOCK_SIZE);
+        if last > DIRECT_BLOCKS {
+            self.writable_indirect(inode, first + count > DIRECT_BLOCKS)?;
+        }
+        let new_blocks = self.alloc_blocks(count)?;
+        for (&b, chunk) in new_blocks.iter().zip(payload.chunks(BLOCK_SIZE)) {
+            let mut buf = [0u8; BLOCK_SIZE];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.device.write_block(b, &buf);
+        }
+        for file_block in first..last {
+            let new = new_blocks.get(file_block - first).copied().unwrap_or(0);
+            let old = self.replace_block(inode, file_block, new);
+            if old != 0 {
+                self.free_data_block(old);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_compressed_range(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: usize,
+        end: usize,
+    ) -> Result<Vec<u8>, FsError> {
+        let size = inode.size as usize;
+        let mut data = Vec::with_capacity(end - offset);
+        for c in offset / CLUSTER_SIZE..end.div_ceil(CLUSTER_SIZE) {
+            let cluster = match self.cluster_cache.take() {
+                Some((cached_ino, cached_c, cluster)) if (cached_ino, cached_c) == (ino, c) => {
+                    cluster
+                }
+                _ => self.read_cluster(inode, c, size)?,
+            };
+            let start = c * CLUSTER_SIZE;
+            let from = offset.max(start) - start;
+            let to = end.min(start + cluster.len()) - start;
+            data.extend_from_slice(&cluster[from..to]);
+            self.cluster_cache = Some((ino, c, cluster));
+        }
+        Ok(data)
+    }
+
+    // 压缩文件的写入：每个涉及的簇读出、修改后重新压缩。文件变大时原来最后一簇的
+    // 逻辑长度也变了，同样要重写；中间跳过的簇没有块，读出为 0
+    fn write_compressed(
+        &mut self,
+        inode: &mut Inode,
+        offset: usize,
+        data: &[u8],
+    ) -> (usize, Result<(), FsError>) {
+        let old_size = inode.size as usize;
+        let Some(end) = offset.checked_add(data.len()) else {
+            return (0, Err(FsError::FileTooLarge));
+        };
+        let new_size = old_size.max(end);
+        let mut clusters: Vec<usize> =
+            (offset / CLUSTER_SIZE..end.div_ceil(CLUSTER_SIZE)).collect();
+        if new_size > old_size && old_size > 0 {
+            let c = (old_size - 1) / CLUSTER_SIZE;
+            if !clusters.contains(&c) {
+                clusters.insert(0, c);
+            }
+        }
+
+        let mut written = 0;
+        let mut result = Ok(());
+        for c in clusters {
+            let start = c * CLUSTER_SIZE;
+            let mut cluster = match self.read_cluster(inode, c, old_size) {
+                Ok(cluster) => cluster,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            cluster.resize(new_size.saturating_sub(start).min(CLUSTER_SIZE), 0);
+            let from = offset.max(start);
+            let to = end.min(start + cluster.len());
+            if from < to {
+                cluster[from - start..to - start]
+                    .copy_from_slice(&data[from - offset..to - offset]);
+            }
+            if let Err(e) = self.write_cluster(inode, c, &cluster) {
+                result = Err(e);
+                break;
+            }
+            // 已经写好的簇按新的大小存放，大小随之增长
+            inode.size = old_size.max(new_size.min(start + CLUSTER_SIZE)) as u32;
+            written = written.max(to.saturating_sub(offset));
+        }
+
+        if written > 0 {
+            inode.mtime = self.now();
+            inode.ctime = inode.mtime;
+        }
+        (written, result)
+    }
+
+    // 压缩文件改变大小：释放新末尾之后的簇，并按新的逻辑长度重写末尾所在的簇
+    fn resize_compressed(&mut self, inode: &mut Inode, size: usize) -> Result<(), FsError> {
+        let old_size = inode.size as usize;
+        let keep = old_size.min(size);
+        if keep == 0 {
+            self.free_blocks_from(inode, 0)?;
+        } else if size != old_size {
+            let c = (keep - 1) / CLUSTER_SIZE;
+            let mut cluster = self.read_cluster(inode, c, old_size)?;
+            cluster.resize(size.saturating_sub(c * CLUSTER_SIZE).min(CLUSTER_SIZE), 0);
+            self.write_cluster(inode, c, &cluster)?;
+            self.free_blocks_from(inode, (c + 1) * CLUSTER_BLOCKS)?;
+        }
+        inode.size = size as u32;
+        inode.mtime = self.now();
+        inode.ctime = inode.mtime;
+        Ok(())
+    }
+
+    // chattr +c / -c：设置或清除压缩标志。文件的现有数据按新的方式重新写入；
+    // 目录只改变标志，影响之后在其中新建的文件和目录
+    pub fn set_compressed(&mut self, path: &str, on: bool) -> Result<(), FsError> {
+        let ino = self.resolve_path(path).ok_or(FsError::NotFound)?;
+        check_writable(ino)?;
+        let mut inode = self.read_inode(ino);
+        let flags = if on {
+            inode.flags | INODE_FLAG_COMPRESSED
+        } else {
+            inode.flags & !INODE_FLAG_COMPRESSED
+        };
+        if flags == inode.flags {
+            return Ok(());
+        }
+
+        if inode.mode == InodeType::File {
+            let size = inode.size as usize;
+            let data = self.read_inode_range(ino, &mut inode, 0, size)?;
+            // 先在一个临时的 Inode 中写好新的布局，空间不足时原文件不受影响
+            let mut converted = Inode::new(InodeType::File, 0);
+            converted.flags = flags;
+            let (_, result) = self.write_at_inode(&mut converted, 0, &data);
+            if let Err(e) = result {
+                let _ = self.free_blocks_from(&mut converted, 0);
+                return Err(e);
+            }
+            self.free_blocks_from(&mut inode, 0)?;
+            inode.blocks = converted.blocks;
+            inode.indirect = converted.indirect;
+        }
+        inode.flags = flags;
+        inode.ctime = self.now();
+        self.write_inode_to_disk(ino, &inode);
+        Ok(())
+    }
+}
+
+// 只读的路径 API，MyFileSystem 和 tar 归档的只读视图都实现了它，
+// 导出、浏览等只读操作可以不关心数据来自哪里
+pub trait ReadFs {
+    fn stat(&mut self, path: &str) -> Result<Metadata, FsError>;
+    fn lstat(&mut self, path: &str) -> Result<Metadata, FsError>;
+    fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError>;
+    fn read_link(&mut self, path: &str) -> Result<String, FsError>;
+    fn read_at(&mut self, ino: u32, offset: usize, len: usize) -> Result<Vec<u8>, FsError>;
+}
+
+impl ReadFs for MyFileSystem {
+    fn stat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        MyFileSystem::stat(self, path)
+    }
+
+    fn lstat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        MyFileSystem::lstat(self, path)
+    }
+
+    fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        MyFileSystem::read_dir(self, path)
+    }
+
+    fn read_link(&mut self, path: &str) -> Result<String, FsError> {
+        MyFileSystem::read_link(self, path)
+    }
+
+    fn read_at(&mut self, ino: u32, offset: usize, len: usize) -> Result<Vec<u8>, FsError> {
+        MyFileSystem::read_at(self, ino, offset, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试使用自己的临时镜像，结束时删除
+    struct TestImage {
+        path: String,
+        fs: MyFileSystem,
+    }
+
+    impl TestImage {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("myfs-test-{}-{}.img", std::process::id(), name))
+                .to_string_lossy()
+                .into_owned();
+            let _ = std::fs::remove_file(&path);
+            let mut fs = MyFileSystem::new(&path).unwrap();
+            fs.format();
+            TestImage { path, fs }
+        }
+    }
+
+    impl Drop for TestImage {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    // 按 seed 生成的伪随机测试数据，任意两块的内容都不相同
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len as u32)
+            .map(|i| ((i ^ ((seed as u32) << 24)).wrapping_mul(2654435761) >> 24) as u8)
+            .collect()
+    }
+
+    // 没有快照、没有共享块时元数据块都已释放
+    fn assert_no_shared_tables(fs: &mut MyFileSystem) {
+        let sb = fs.read_superblock();
+        assert_eq!(sb.refcount_index, 0);
+        assert_eq!(sb.snapshot_table, 0);
+    }
+
+    #[test]
+    fn pwrite_offset_overflow() {
+        let mut img = TestImage::new("pwrite-overflow");
+        let fs = &mut img.fs;
+        fs.create_file("/f").unwrap();
+        for offset in [usize::MAX, usize::MAX - 1, u32::MAX as usize] {
+            assert_eq!(fs.pwrite("/f", offset, b"x"), Err(FsError::FileTooLarge));
+        }
+        fs.set_compressed("/f", true).unwrap();
+        assert_eq!(
+            fs.pwrite("/f", usize::MAX, b"x"),
+            Err(FsError::FileTooLarge)
+        );
+        // 刚好写到最大文件大小是允许的
+        let last = MAX_FILE_BLOCKS * BLOCK_SIZE - 1;
+        assert_eq!(fs.pwrite("/f", last, b"x"), Ok(1));
+        assert_eq!(fs.pwrite("/f", last + 1, b"x"), Err(FsError::FileTooLarge));
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn snapshot_rollback_restores_contents() {
+        let mut img = TestImage::new("snapshot-rollback");
+        let fs = &mut img.fs;
+        fs.create_dir("/d").unwrap();
+        fs.create_file("/a").unwrap();
+        fs.write_bytes("/a", b"one").unwrap();
+        fs.create_file("/d/b").unwrap();
+        fs.write_bytes("/d/b", &pattern(20 * BLOCK_SIZE, 1))
+            .unwrap();
+
+        fs.snapshot_create("s").unwrap();
+        fs.write_bytes("/a", b"two").unwrap();
+        fs.pwrite("/d/b", 15 * BLOCK_SIZE, b"changed").unwrap();
+        fs.create_file("/c").unwrap();
+        assert_eq!(fs.read_bytes("/.snapshots/s/a").unwrap(), b"one");
+        assert_eq!(
+            fs.read_bytes("/.snapshots/s/d/b").unwrap(),
+            pattern(20 * BLOCK_SIZE, 1)
+        );
+        assert_eq!(
+            fs.write_bytes("/.snapshots/s/a", b"x"),
+            Err(FsError::ReadOnly)
+        );
+
+        fs.snapshot_rollback("s").unwrap();
+        assert_eq!(fs.read_bytes("/a").unwrap(), b"one");
+        assert_eq!(fs.read_bytes("/d/b").unwrap(), pattern(20 * BLOCK_SIZE, 1));
+        assert_eq!(fs.stat("/c").err(), Some(FsError::NotFound));
+        // 回滚后的写入仍然写时复制，快照不受影响
+        fs.write_bytes("/a", b"three").unwrap();
+        assert_eq!(fs.read_bytes("/.snapshots/s/a").unwrap(), b"one");
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn snapshot_delete_frees_everything() {
+        let mut img = TestImage::new("snapshot-delete");
+        let fs = &mut img.fs;
+        fs.create_dir("/d").unwrap();
+        fs.create_file("/d/big").unwrap();
+        fs.write_bytes("/d/big", &pattern(30 * BLOCK_SIZE, 2))
+            .unwrap();
+        let free = fs.statfs().free_blocks;
+
+        fs.snapshot_create("s1").unwrap();
+        fs.pwrite("/d/big", 20 * BLOCK_SIZE, b"x").unwrap();
+        fs.snapshot_create("s2").unwrap();
+        fs.write_bytes("/d/big", &pattern(30 * BLOCK_SIZE, 3))
+            .unwrap();
+        fs.snapshot_delete("s1").unwrap();
+        assert_eq!(fs.snapshot_list().len(), 1);
+        fs.snapshot_delete("s2").unwrap();
+
+        assert_eq!(
+            fs.read_bytes("/d/big").unwrap(),
+            pattern(30 * BLOCK_SIZE, 3)
+        );
+        assert_eq!(fs.statfs().free_blocks, free);
+        assert_no_shared_tables(fs);
+        assert!(fs.fsck().is_clean());
+        assert_eq!(fs.stat("/.snapshots").err(), Some(FsError::NotFound));
+    }
+
+    #[test]
+    fn snapshot_rollback_with_reflink() {
+        let mut img = TestImage::new("snapshot-reflink");
+        let fs = &mut img.fs;
+        let free = fs.statfs().free_blocks;
+        fs.create_file("/big").unwrap();
+        fs.write_bytes("/big", &pattern(20 * BLOCK_SIZE, 4))
+            .unwrap();
+        fs.copy("/big", "/r", false, true).unwrap();
+
+        fs.snapshot_create("s").unwrap();
+        fs.pwrite("/r", 14 * BLOCK_SIZE, b"after snapshot").unwrap();
+        fs.remove("/big", false).unwrap();
+        fs.snapshot_rollback("s").unwrap();
+        assert_eq!(fs.read_bytes("/big").unwrap(), pattern(20 * BLOCK_SIZE, 4));
+        assert_eq!(fs.read_bytes("/r").unwrap(), pattern(20 * BLOCK_SIZE, 4));
+
+        // 回滚后两个副本仍然互不影响
+        fs.pwrite("/big", 14 * BLOCK_SIZE, b"big").unwrap();
+        assert_eq!(fs.read_bytes("/r").unwrap(), pattern(20 * BLOCK_SIZE, 4));
+        fs.snapshot_delete("s").unwrap();
+        fs.remove("/big", false).unwrap();
+        assert_eq!(fs.read_bytes("/r").unwrap(), pattern(20 * BLOCK_SIZE, 4));
+        fs.remove("/r", false).unwrap();
+
+        assert_eq!(fs.statfs().free_blocks, free);
+        assert_no_shared_tables(fs);
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn snapshot_dir_name_is_free_without_snapshots() {
+        let mut img = TestImage::new("snapshot-name");
+        let fs = &mut img.fs;
+        fs.create_dir("/.snapshots").unwrap();
+        assert_eq!(fs.snapshot_create("s"), Err(FsError::AlreadyExists));
+        fs.remove("/.snapshots", true).unwrap();
+        fs.snapshot_create("s").unwrap();
+        assert_eq!(
+            fs.create_dir("/.snapshots").err(),
+            Some(FsError::AlreadyExists)
+        );
+        fs.snapshot_delete("s").unwrap();
+        assert_no_shared_tables(fs);
+    }
+
+    #[test]
+    fn reflink_shares_blocks_until_written() {
+        let mut img = TestImage::new("reflink");
+        let fs = &mut img.fs;
+        let free = fs.statfs().free_blocks;
+        let data = pattern(20 * BLOCK_SIZE, 5);
+        fs.create_file("/a").unwrap();
+        fs.write_bytes("/a", &data).unwrap();
+        fs.copy("/a", "/b", false, true).unwrap();
+        // 第一次共享时建立引用计数表，之后的副本不占任何块
+        let before = fs.statfs().free_blocks;
+        fs.copy("/a", "/c", false, true).unwrap();
+        assert_eq!(fs.statfs().free_blocks, before);
+        fs.remove("/c", false).unwrap();
+        assert_eq!(fs.read_bytes("/b").unwrap(), data);
+
+        // 写直接块只复制这一块
+        fs.pwrite("/b", 0, b"direct").unwrap();
+        assert_eq!(fs.statfs().free_blocks, before - 1);
+        // 写间接块范围内的块：先复制间接块，再复制数据块
+        fs.pwrite("/b", 15 * BLOCK_SIZE, b"indirect").unwrap();
+        assert_eq!(fs.statfs().free_blocks, before - 3);
+        let a = fs.resolve_path("/a").unwrap();
+        let b = fs.resolve_path("/b").unwrap();
+        assert_ne!(fs.read_inode(a).indirect, fs.read_inode(b).indirect);
+
+        assert_eq!(fs.read_bytes("/a").unwrap(), data);
+        let mut expected = data.clone();
+        expected[..6].copy_from_slice(b"direct");
+        expected[15 * BLOCK_SIZE..15 * BLOCK_SIZE + 8].copy_from_slice(b"indirect");
+        assert_eq!(fs.read_bytes("/b").unwrap(), expected);
+
+        // 反过来写原文件，副本同样不受影响
+        fs.pwrite("/a", 16 * BLOCK_SIZE, b"source").unwrap();
+        fs.set_len("/a", 13 * BLOCK_SIZE).unwrap();
+        assert_eq!(fs.read_bytes("/b").unwrap(), expected);
+        fs.remove("/b", false).unwrap();
+        assert_eq!(fs.read_bytes("/a").unwrap(), data[..13 * BLOCK_SIZE]);
+
+        fs.remove("/a", false).unwrap();
+        assert_eq!(fs.statfs().free_blocks, free);
+        assert_no_shared_tables(fs);
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn dedup_merges_identical_files() {
+        let mut img = TestImage::new("dedup");
+        let fs = &mut img.fs;
+        let free = fs.statfs().free_blocks;
+        let data = pattern(16 * BLOCK_SIZE, 6);
+        for path in ["/a", "/b"] {
+            fs.create_file(path).unwrap();
+            fs.write_bytes(path, &data).unwrap();
+        }
+        let report = fs.dedup().unwrap();
+        assert_eq!(report.scanned, 32);
+        assert_eq!(report.merged, 16);
+        // 合并了 16 块，其中 2 块用作引用计数表
+        assert_eq!(report.reclaimed, 14);
+        assert_eq!(fs.dedup().unwrap().merged, 0);
+
+        fs.pwrite("/a", 13 * BLOCK_SIZE, b"overwrite").unwrap();
+        fs.remove("/b", false).unwrap();
+        let mut expected = data.clone();
+        expected[13 * BLOCK_SIZE..13 * BLOCK_SIZE + 9].copy_from_slice(b"overwrite");
+        assert_eq!(fs.read_bytes("/a").unwrap(), expected);
+        assert!(fs.fsck().is_clean());
+
+        fs.remove("/a", false).unwrap();
+        assert_eq!(fs.statfs().free_blocks, free);
+        assert_no_shared_tables(fs);
+    }
+
+    #[test]
+    fn inline_dedup_forgets_freed_blocks() {
+        let mut img = TestImage::new("inline-dedup");
+        let fs = &mut img.fs;
+        fs.set_inline_dedup(true);
+        let free = fs.statfs().free_blocks;
+        let data = pattern(14 * BLOCK_SIZE, 7);
+        fs.create_file("/a").unwrap();
+        fs.write_bytes("/a", &data).unwrap();
+        fs.create_file("/b").unwrap();
+        fs.write_bytes("/b", &data).unwrap();
+        // 第二个文件的数据块全部引用第一个文件的块，只多出引用计数表和间接块
+        assert_eq!(fs.statfs().free_blocks, free - 14 - 1 - 2 - 1);
+
+        // 释放的块移出了索引，之后写入相同内容时不会引用已释放的块
+        fs.remove("/a", false).unwrap();
+        fs.remove("/b", false).unwrap();
+        assert_eq!(fs.statfs().free_blocks, free);
+        fs.create_file("/c").unwrap();
+        fs.write_bytes("/c", &data).unwrap();
+        fs.create_file("/d").unwrap();
+        fs.write_bytes("/d", &pattern(14 * BLOCK_SIZE, 8)).unwrap();
+        assert_eq!(fs.read_bytes("/c").unwrap(), data);
+        assert_eq!(fs.read_bytes("/d").unwrap(), pattern(14 * BLOCK_SIZE, 8));
+        assert!(fs.fsck().is_clean());
+    }
+
+    // 容易压缩的文本数据
+    fn text(len: usize, seed: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut line = 0;
+        while data.len() < len {
+            data.extend_from_slice(format!("{} line {} INFO ok\n", seed, line).as_bytes());
+            line += 1;
+        }
+        data.truncate(len);
+        data
+    }
+
+    // 对照用的内存模型：在 offset 处写入 data，必要时用 0 补齐
+    fn model_write(model: &mut Vec<u8>, offset: usize, data: &[u8]) {
+        if model.len() < offset + data.len() {
+            model.resize(offset + data.len(), 0);
+        }
+        model[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    #[test]
+    fn compressed_writes_across_clusters() {
+        let mut img = TestImage::new("compress-write");
+        let fs = &mut img.fs;
+        let free = fs.statfs().free_blocks;
+        fs.create_dir("/c").unwrap();
+        fs.set_compressed("/c", true).unwrap();
+        fs.create_dir("/c/sub").unwrap();
+        fs.create_file("/c/sub/f").unwrap();
+        // 新文件和目录继承父目录的压缩标志
+        assert!(fs.stat("/c/sub").unwrap().compressed);
+        assert!(fs.stat("/c/sub/f").unwrap().compressed);
+
+        let mut model = text(3 * CLUSTER_SIZE + 1000, 1);
+        fs.write_bytes("/c/sub/f", &model).unwrap();
+        let used = free - fs.statfs().free_blocks;
+        assert!((used as usize) < model.len() / BLOCK_SIZE / 2);
+
+        // 跨越簇边界、簇内部分覆盖、追加到下一个簇、越过末尾留下空洞
+        let writes: [(usize, Vec<u8>); 4] = [
+            (CLUSTER_SIZE - 10, vec![b'x'; 20]),
+            (CLUSTER_SIZE + 5000, text(300, 2)),
+            (3 * CLUSTER_SIZE + 900, text(2 * BLOCK_SIZE, 3)),
+            (6 * CLUSTER_SIZE + 7, b"tail".to_vec()),
+        ];
+        for (offset, data) in &writes {
+            assert_eq!(fs.pwrite("/c/sub/f", *offset, data), Ok(data.len()));
+            model_write(&mut model, *offset, data);
+            assert_eq!(fs.read_bytes("/c/sub/f").unwrap(), model);
+        }
+        let ino = fs.resolve_path("/c/sub/f").unwrap();
+        assert_eq!(
+            fs.read_at(ino, 4 * CLUSTER_SIZE, 10).unwrap(),
+            vec![0u8; 10]
+        );
+        assert!(fs.fsck().is_clean());
+
+        fs.remove("/c", true).unwrap();
+        assert_eq!(fs.statfs().free_blocks, free);
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn compressed_set_len() {
+        let mut img = TestImage::new("compress-set-len");
+        let fs = &mut img.fs;
+        let free = fs.statfs().free_blocks;
+        fs.create_file("/f").unwrap();
+        fs.set_compressed("/f", true).unwrap();
+        let mut model = text(2 * CLUSTER_SIZE + 123, 4);
+        fs.write_bytes("/f", &model).unwrap();
+
+        for size in [
+            CLUSTER_SIZE + 77,
+            3 * CLUSTER_SIZE,
+            CLUSTER_SIZE,
+            100,
+            CLUSTER_SIZE + 5,
+            0,
+        ] {
+            fs.set_len("/f", size).unwrap();
+            model.resize(size, 0);
+            assert_eq!(fs.read_bytes("/f").unwrap(), model);
+        }
+        fs.remove("/f", false).unwrap();
+        assert_eq!(fs.statfs().free_blocks, free);
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn set_compressed_converts_existing_data() {
+        let mut img = TestImage::new("compress-convert");
+        let fs = &mut img.fs;
+        let data = text(CLUSTER_SIZE + 5000, 5);
+        fs.create_file("/f").unwrap();
+        fs.write_bytes("/f", &data).unwrap();
+        let plain = fs.statfs().free_blocks;
+
+        fs.set_compressed("/f", true).unwrap();
+        assert!(fs.statfs().free_blocks > plain);
+        assert_eq!(fs.read_bytes("/f").unwrap(), data);
+        fs.set_compressed("/f", false).unwrap();
+        assert_eq!(fs.statfs().free_blocks, plain);
+        assert_eq!(fs.read_bytes("/f").unwrap(), data);
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn cluster_cache_sees_writes() {
+        let mut img = TestImage::new("cluster-cache");
+        let fs = &mut img.fs;
+        fs.create_file("/f").unwrap();
+        fs.set_compressed("/f", true).unwrap();
+        let mut model = text(2 * CLUSTER_SIZE, 6);
+        fs.write_bytes("/f", &model).unwrap();
+        let ino = fs.resolve_path("/f").unwrap();
+
+        // 逐块读取时缓存住当前的簇，写入、截断之后再读必须看到新内容
+        assert_eq!(fs.read_at(ino, 0, BLOCK_SIZE).unwrap(), model[..BLOCK_SIZE]);
+        fs.pwrite("/f", 100, b"fresh").unwrap();
+        model_write(&mut model, 100, b"fresh");
+        assert_eq!(fs.read_at(ino, 0, BLOCK_SIZE).unwrap(), model[..BLOCK_SIZE]);
+
+        assert_eq!(
+            fs.read_at(ino, CLUSTER_SIZE, 10).unwrap(),
+            model[CLUSTER_SIZE..][..10]
+        );
+        fs.set_len("/f", CLUSTER_SIZE + 4).unwrap();
+        assert_eq!(
+            fs.read_at(ino, CLUSTER_SIZE, 10).unwrap(),
+            model[CLUSTER_SIZE..][..4]
+        );
+
+        // 删除后重新创建的文件可能使用同一个 Inode 编号
+        fs.remove("/f", false).unwrap();
+        fs.create_file("/g").unwrap();
+        fs.set_compressed("/g", true).unwrap();
+        let other = text(CLUSTER_SIZE, 7);
+        fs.write_bytes("/g", &other).unwrap();
+        assert_eq!(fs.resolve_path("/g"), Some(ino));
+        assert_eq!(fs.read_at(ino, 0, BLOCK_SIZE).unwrap(), other[..BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn rename_files_and_directories() {
+        let mut img = TestImage::new("rename");
+        let fs = &mut img.fs;
+        fs.create_dir_all("/d/e/f").unwrap();
+        fs.create_dir("/z").unwrap();
+        fs.create_file("/a").unwrap();
+        fs.write_bytes("/a", b"a").unwrap();
+        fs.create_file("/b").unwrap();
+        fs.write_bytes("/b", b"b").unwrap();
+        let free = fs.statfs().free_blocks;
+
+        // 覆盖已存在的文件，旧文件的块被释放
+        fs.rename("/a", "/b").unwrap();
+        assert_eq!(fs.read_bytes("/b").unwrap(), b"a");
+        assert_eq!(fs.stat("/a").err(), Some(FsError::NotFound));
+        assert_eq!(fs.statfs().free_blocks, free + 1);
+
+        // 目标是目录时移动到目录中
+        assert_eq!(fs.mv("/b", "/d").unwrap(), "/d/b");
+        assert_eq!(fs.read_bytes("/d/b").unwrap(), b"a");
+
+        // 移动目录后 ".." 指向新的父目录，新旧父目录的链接数随之变化
+        let z_links = fs.stat("/z").unwrap().nlinks;
+        let d_links = fs.stat("/d").unwrap().nlinks;
+        fs.rename("/d/e", "/z/e").unwrap();
+        assert_eq!(fs.resolve_path("/z/e/f/../.."), fs.resolve_path("/z"));
+        assert_eq!(fs.stat("/z").unwrap().nlinks, z_links + 1);
+        assert_eq!(fs.stat("/d").unwrap().nlinks, d_links - 1);
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn rename_rejects_cycles_and_dot_names() {
+        let mut img = TestImage::new("rename-cycle");
+        let fs = &mut img.fs;
+        fs.create_dir_all("/d/e/f").unwrap();
+        fs.symlink("/d/e", "/l").unwrap();
+
+        for dst in ["/d/x", "/d/e/x", "/d/e/f/x", "/l/x", "/l/f/x"] {
+            assert_eq!(fs.rename("/d", dst), Err(FsError::InvalidArgument));
+        }
+        assert_eq!(fs.rename("/d/e", "/l/f/x"), Err(FsError::InvalidArgument));
+        assert_eq!(
+            fs.copy("/d", "/l/f/x", true, false),
+            Err(FsError::InvalidArgument)
+        );
+        assert_eq!(fs.rename("/d/e/..", "/z"), Err(FsError::InvalidArgument));
+        assert_eq!(fs.rename("/d/.", "/z"), Err(FsError::InvalidArgument));
+        assert_eq!(fs.rename("/d", "/z/.."), Err(FsError::InvalidArgument));
+        assert!(fs.resolve_path("/d/e/f").is_some());
+        assert!(fs.fsck().is_clean());
+    }
+
+    #[test]
+    fn recursive_copy_and_remove_large_directory() {
+        let mut img = TestImage::new("tree");
+        let fs = &mut img.fs;
+        fs.format_with(4096, 512);
+        let before = fs.statfs();
+
+        // 每个目录块放 64 个条目，100 个文件让目录增长到第二块
+        fs.create_dir_all("/src/sub/deep").unwrap();
+        for i in 0..100 {
+            let path = format!("/src/f{}", i);
+            fs.create_file(&path).unwrap();
+            fs.write_bytes(&path, &pattern(i * 100, i as u8)).unwrap();
+        }
+        fs.create_file("/src/sub/deep/big").unwrap();
+        fs.write_bytes("/src/sub/deep/big", &pattern(20 * BLOCK_SIZE, 9))
+            .unwrap();
+        fs.symlink("f1", "/src/link").unwrap();
+        assert!(fs.stat("/src").unwrap().size as usize > BLOCK_SIZE);
+        let filled = fs.statfs();
+
+        fs.copy("/src", "/dst", true, false).unwrap();
+        assert_eq!(
+            fs.read_dir("/dst").unwrap().len(),
+            fs.read_dir("/src").unwrap().len()
+        );
+        for i in [0, 63, 64, 99] {
+            let path = format!("/dst/f{}", i);
+            assert_eq!(fs.read_bytes(&path).unwrap(), pattern(i * 100, i as u8));
+        }
+        assert_eq!(
+            fs.read_bytes("/dst/sub/deep/big").unwrap(),
+            pattern(20 * BLOCK_SIZE, 9)
+        );
+        assert_eq!(fs.read_link("/dst/link").unwrap(), "f1");
+        assert!(fs.fsck().is_clean());
+
+        fs.remove("/dst", true).unwrap();
+        let after = fs.statfs();
+        assert_eq!(after.free_blocks, filled.free_blocks);
+        assert_eq!(after.free_inodes, filled.free_inodes);
+        fs.remove("/src", true).unwrap();
+        let after = fs.statfs();
+        assert_eq!(after.free_blocks, before.free_blocks);
+        assert_eq!(after.free_inodes, before.free_inodes);
+        assert!(fs.fsck().is_clean());
     }
 }
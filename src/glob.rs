@@ -0,0 +1,229 @@
+use crate::fs::MyFileSystem;
+use crate::layout::InodeType;
+
+// 通配符：* 匹配任意多个字符，? 匹配一个字符，[abc] / [a-z] / [!abc] 匹配字符集合，
+// \ 转义下一个字符；路径中单独的 ** 分量匹配零层或多层目录
+// 展开时用 read_dir 逐级遍历镜像中的目录，而不是宿主机
+
+// 分量中是否含有未转义的通配符
+pub fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+// 去掉转义用的反斜杠
+fn unescape(pattern: &str) -> String {
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+// 解析 [...]：返回是否匹配 c 和 ] 之后的位置；没有闭合的 [ 返回 None，按普通字符处理
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut lo = *pattern.get(i)?;
+        if lo == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if lo == '\\' {
+            i += 1;
+            lo = *pattern.get(i)?;
+        }
+        i += 1;
+        let mut hi = lo;
+        if pattern.get(i) == Some(&'-') && pattern.get(i + 1).is_some_and(|&c| c != ']') {
+            hi = pattern[i + 1];
+            if hi == '\\' {
+                hi = *pattern.get(i + 2)?;
+                i += 1;
+            }
+            i += 2;
+        }
+        if lo <= c && c <= hi {
+            matched = true;
+        }
+    }
+}
+
+// 判断文件名是否匹配一个分量的模式（不含 /）
+pub fn fnmatch(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 * 的位置和它当前匹配到的文件名位置，失配时回溯到这里让 * 多吃一个字符
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match match_class(&pattern, p, name[n]) {
+                Some((true, next)) => Some(next),
+                Some((false, _)) => None,
+                None => (name[n] == '[').then_some(p + 1),
+            },
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == name[n]).then_some(p + 2),
+            Some(&c) => (c == name[n]).then_some(p + 1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            }
+            (None, Some((sp, sn))) => {
+                star = Some((sp, sn + 1));
+                p = sp + 1;
+                n = sn + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// 展开路径模式，返回按字典序排列的匹配路径；相对模式从当前目录开始，结果也是相对路径
+// 以 . 开头的文件名只能被以 . 开头的模式匹配；模式以 / 结尾时只匹配目录
+pub fn glob(fs: &mut MyFileSystem, pattern: &str) -> Vec<String> {
+    // 镜像没有格式化时没有任何匹配，单词原样保留，由命令报告错误
+    if !fs.is_formatted() {
+        return Vec::new();
+    }
+    let (base, shown) = if pattern.starts_with('/') {
+        ("/".to_string(), "/".to_string())
+    } else {
+        (fs.cwd_path.clone(), String::new())
+    };
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let mut walker = Walker {
+        fs,
+        dir_only: pattern.ends_with('/') && !components.is_empty(),
+        results: Vec::new(),
+    };
+    walker.walk(&base, &shown, &components);
+    let mut results = walker.results;
+    results.sort();
+    results.dedup();
+    results
+}
+
+struct Walker<'a> {
+    fs: &'a mut MyFileSystem,
+    dir_only: bool,
+    results: Vec<String>,
+}
+
+fn join_shown(shown: &str, name: &str) -> String {
+    if shown.is_empty() {
+        name.to_string()
+    } else if shown.ends_with('/') {
+        format!("{}{}", shown, name)
+    } else {
+        format!("{}/{}", shown, name)
+    }
+}
+
+impl Walker<'_> {
+    fn is_dir(&mut self, path: &str) -> bool {
+        self.fs
+            .stat(path)
+            .is_ok_and(|m| m.kind == InodeType::Directory)
+    }
+
+    // abs 是已经匹配部分的绝对路径，shown 是它在结果中的写法，rest 是剩下的分量
+    fn walk(&mut self, abs: &str, shown: &str, rest: &[&str]) {
+        let Some((&first, rest)) = rest.split_first() else {
+            if self.dir_only {
+                if self.is_dir(abs) {
+                    self.results
+                        .push(format!("{}/", shown.trim_end_matches('/')));
+                }
+            } else if self.fs.lstat(abs).is_ok() {
+                self.results.push(shown.to_string());
+            }
+            return;
+        };
+
+        if first == "**" {
+            // 匹配零层目录，再进入每个子目录继续匹配；不跟随指向目录的符号链接，避免循环
+            // ** 是最后一个分量时匹配其下所有的文件和目录，但不包括起点本身
+            if !rest.is_empty() {
+                self.walk(abs, shown, rest);
+            }
+            let Ok(entries) = self.fs.read_dir(abs) else {
+                return;
+            };
+            for entry in entries {
+                if entry.name.starts_with('.') {
+                    continue;
+                }
+                let child = MyFileSystem::join_path(abs, &entry.name);
+                let child_shown = join_shown(shown, &entry.name);
+                if rest.is_empty() {
+                    self.walk(&child, &child_shown, rest);
+                }
+                if self
+                    .fs
+                    .lstat(&child)
+                    .is_ok_and(|m| m.kind == InodeType::Directory)
+                {
+                    let mut again = vec!["**"];
+                    again.extend_from_slice(rest);
+                    self.walk(&child, &child_shown, &again);
+                }
+            }
+            return;
+        }
+
+        if !has_magic(first) {
+            let name = unescape(first);
+            let child = MyFileSystem::join_path(abs, &name);
+            self.walk(&child, &join_shown(shown, &name), rest);
+            return;
+        }
+
+        let Ok(entries) = self.fs.read_dir(abs) else {
+            return;
+        };
+        for entry in entries {
+            if entry.name.starts_with('.') && !first.starts_with('.') {
+                continue;
+            }
+            if !fnmatch(first, &entry.name) {
+                continue;
+            }
+            // 后面还有分量时只有目录才能继续
+            let child = MyFileSystem::join_path(abs, &entry.name);
+            if !rest.is_empty() && !self.is_dir(&child) {
+                continue;
+            }
+            self.walk(&child, &join_shown(shown, &entry.name), rest);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::FsError;
+use crate::fs::MyFileSystem;
+use crate::layout::{BLOCK_SIZE, InodeType};
+use crate::regex::Regex;
+use crate::{EXIT_USAGE, fail, report, to_absolute_path, usage};
+use std::io::Write;
+
+// grep [-r] [-i] [-n] [-l] [-c] <pattern> [path...]
+// 文件按块读取（read_at 每次一块），不完整的最后一行留到下一块拼接，因此跨越块边界的行也能匹配；
+// 没有给出路径时搜索管道或 heredoc 的输入
+// 退出状态：有匹配的行为 0，没有为 1，出错为 2
+
+const GREP_USAGE: &str = "grep [-r] [-i] [-n] [-l] [-c] <pattern> [path...]";
+
+struct Options {
+    recursive: bool,
+    ignore_case: bool,
+    line_numbers: bool,
+    files_only: bool,
+    count: bool,
+}
+
+struct Grep<'a> {
+    regex: Regex,
+    opts: Options,
+    show_names: bool, // 搜索多个文件时每一行前面加上文件名
+    out: &'a mut Vec<u8>,
+}
+
+impl Grep<'_> {
+    // 逐行匹配一个数据源；next_chunk 每次返回下一段数据，返回空时结束。返回匹配的行数
+    fn search(
+        &mut self,
+        name: &str,
+        mut next_chunk: impl FnMut() -> Result<Vec<u8>, FsError>,
+    ) -> Result<usize, FsError> {
+        let mut pending: Vec<u8> = Vec::new();
+        let mut line_no = 0;
+        let mut matches = 0;
+        let mut eof = false;
+        while !eof {
+            let chunk = next_chunk()?;
+            eof = chunk.is_empty();
+            pending.extend_from_slice(&chunk);
+
+            // 处理所有完整的行；到达末尾时最后一行没有换行也要处理
+            let mut start = 0;
+            loop {
+                let end = match pending[start..].iter().position(|&b| b == b'\n') {
+                    Some(i) => start + i,
+                    None if eof && start < pending.len() => pending.len(),
+                    None => break,
+                };
+                line_no += 1;
+                let line = &pending[start..end];
+                start = (end + 1).min(pending.len());
+                if !self.regex.is_match(&String::from_utf8_lossy(line)) {
+                    continue;
+                }
+                matches += 1;
+                if self.opts.files_only {
+                    // 只需要知道有没有匹配，不必读完整个文件
+                    writeln!(self.out, "{}", name).unwrap();
+                    return Ok(matches);
+                }
+                if self.opts.count {
+                    continue;
+                }
+                if self.show_names {
+                    write!(self.out, "{}:", name).unwrap();
+                }
+                if self.opts.line_numbers {
+                    write!(self.out, "{}:", line_no).unwrap();
+                }
+                self.out.extend_from_slice(line);
+                self.out.push(b'\n');
+            }
+            pending.drain(..start);
+        }
+        if self.opts.count {
+            if self.show_names {
+                write!(self.out, "{}:", name).unwrap();
+            }
+            writeln!(self.out, "{}", matches).unwrap();
+        }
+        Ok(matches)
+    }
+
+    // 按块读取镜像中的一个文件
+    fn search_file(
+        &mut self,
+        fs: &mut MyFileSystem,
+        name: &str,
+        ino: u32,
+    ) -> Result<usize, FsError> {
+        let mut offset = 0;
+        self.search(name, || {
+            let chunk = fs.read_at(ino, offset, BLOCK_SIZE)?;
+            offset += chunk.len();
+            Ok(chunk)
+        })
+    }
+}
+
+pub fn run_grep(
+    fs: &mut MyFileSystem,
+    args: &[&str],
+    input: Option<&[u8]>,
+    out: &mut Vec<u8>,
+) -> i32 {
+    let mut opts = Options {
+        recursive: false,
+        ignore_case: false,
+        line_numbers: false,
+        files_only: false,
+        count: false,
+    };
+    let mut pattern = None;
+    let mut paths = Vec::new();
+    let mut i = 0;
+    let mut options_done = false;
+    while i < args.len() {
+        let arg = args[i];
+        i += 1;
+        if options_done || !arg.starts_with('-') || arg.len() == 1 {
+            if pattern.is_none() {
+                pattern = Some(arg);
+            } else {
+                paths.push(arg);
+            }
+            continue;
+        }
+        if arg == "--" {
+            options_done = true;
+            continue;
+        }
+        // 单字母选项可以合并，例如 -rn；-e 之后的参数是模式
+        for flag in arg[1..].chars() {
+            match flag {
+                'r' | 'R' => opts.recursive = true,
+                'i' => opts.ignore_case = true,
+                'n' => opts.line_numbers = true,
+                'l' => opts.files_only = true,
+                'c' => opts.count = true,
+                'E' => {}
+                'e' if i < args.len() && pattern.is_none() => {
+                    pattern = Some(args[i]);
+                    i += 1;
+                }
+                _ => return usage(GREP_USAGE),
+            }
+        }
+    }
+    let Some(pattern) = pattern else {
+        return usage(GREP_USAGE);
+    };
+    let regex = match Regex::new(pattern, opts.ignore_case) {
+        Ok(regex) => regex,
+        Err(msg) => {
+            report(
+                "grep",
+                None,
+                &format!("invalid pattern '{}': {}", pattern, msg),
+            );
+            return EXIT_USAGE;
+        }
+    };
+
+    let show_names = paths.len() > 1 || opts.recursive;
+    let mut grep = Grep {
+        regex,
+        opts,
+        show_names,
+        out,
+    };
+    let mut matched = false;
+    let mut failed = false;
+
+    if paths.is_empty() {
+        let Some(data) = input else {
+            return usage(GREP_USAGE);
+        };
+        // 输入已经在内存中，同样按块交给 search，和文件走同一条路径
+        let mut chunks = data.chunks(BLOCK_SIZE);
+        let result = grep.search("(standard input)", || {
+            Ok(chunks.next().map(<[u8]>::to_vec).unwrap_or_default())
+        });
+        matched = result.is_ok_and(|n| n > 0);
+    }
+
+    for raw_path in paths {
+        let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+        let meta = match fs.stat(&abs_path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                fail("grep", raw_path, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        // 需要搜索的文件：(输出中的名字, Inode 号)
+        let files: Vec<(String, u32)> = match meta.kind {
+            InodeType::Directory if !grep.opts.recursive => {
+                fail("grep", raw_path, FsError::IsADirectory);
+                failed = true;
+                continue;
+            }
+            InodeType::Directory => {
+                // 递归时不跟随目录中的符号链接
+                let mut files = Vec::new();
+                for entry in fs.walk(&abs_path) {
+                    match entry {
+                        Ok(entry) if entry.meta.kind == InodeType::File => {
+                            let rel = entry.path[abs_path.len()..].trim_start_matches('/');
+                            let name = format!("{}/{}", raw_path.trim_end_matches('/'), rel);
+                            files.push((name, entry.meta.ino));
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            fail("grep", raw_path, e);
+                            failed = true;
+                        }
+                    }
+                }
+                files
+            }
+            _ => vec![(raw_path.to_string(), meta.ino)],
+        };
+
+        for (name, ino) in files {
+            match grep.search_file(fs, &name, ino) {
+                Ok(n) => matched |= n > 0,
+                Err(e) => {
+                    fail("grep", &name, e);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        EXIT_USAGE
+    } else if matched {
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,317 @@
+use crate::error::FsError;
+use crate::fs::{MyFileSystem, StatFs};
+use crate::layout::*;
+use crate::report;
+use std::fs::{self, File, FileTimes};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 宿主机文件系统与镜像之间的导入导出，数据按块大小分段流式传输，不会整体读入内存
+
+fn unix_secs(t: std::io::Result<SystemTime>) -> Option<u64> {
+    t.ok()?.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+#[cfg(unix)]
+fn host_perm(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn host_perm(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn host_symlink(target: &str, path: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+fn host_symlink(_target: &str, path: &Path) -> std::io::Result<()> {
+    report(
+        "export",
+        Some(&path.display().to_string()),
+        "skipping symbolic link",
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_host_perm(path: &Path, perm: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(perm))
+}
+
+#[cfg(not(unix))]
+fn set_host_perm(_path: &Path, _perm: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+// 把宿主机上的权限和时间戳写到镜像中的 Inode
+fn copy_host_metadata(
+    fs: &mut MyFileSystem,
+    meta: &fs::Metadata,
+    fs_path: &str,
+) -> Result<(), FsError> {
+    if let Some(perm) = host_perm(meta) {
+        fs.set_permissions(fs_path, perm)?;
+    }
+    if let Some(mtime) = unix_secs(meta.modified()) {
+        // 读取文件本身就会改变 atime，固定时间时改用 mtime 以保证可重现
+        let atime = match fs.fixed_time() {
+            Some(_) => mtime,
+            None => unix_secs(meta.accessed()).unwrap_or(mtime),
+        };
+        fs.set_times(fs_path, fs.clamp_time(atime), fs.clamp_time(mtime))?;
+    }
+    Ok(())
+}
+
+// import：把宿主机上的文件或目录（递归）导入镜像，返回导入的条目数
+// 镜像中的目标是已存在的目录时，导入到该目录下的同名条目
+pub fn import(fs: &mut MyFileSystem, host_path: &Path, fs_path: &str) -> Result<usize, FsError> {
+    let fs_path = match fs.stat(fs_path) {
+        Ok(meta) if meta.kind == InodeType::Directory => {
+            let name = host_path.file_name().ok_or(FsError::InvalidArgument)?;
+            MyFileSystem::join_path(fs_path, &name.to_string_lossy())
+        }
+        _ => fs_path.to_string(),
+    };
+    import_entry(fs, host_path, &fs_path)
+}
+
+fn import_entry(fs: &mut MyFileSystem, host_path: &Path, fs_path: &str) -> Result<usize, FsError> {
+    let meta = fs::symlink_metadata(host_path)?;
+    let mut count = 1;
+
+    if meta.is_dir() {
+        match fs.stat(fs_path) {
+            Ok(m) if m.kind == InodeType::Directory => {}
+            Ok(_) => return Err(FsError::NotADirectory),
+            Err(_) => {
+                fs.create_dir(fs_path)?;
+            }
+        }
+
+        let mut children: Vec<_> = fs::read_dir(host_path)?.collect::<Result<_, _>>()?;
+        children.sort_by_key(|e| e.file_name());
+        for child in children {
+            let name = child.file_name().to_string_lossy().into_owned();
+            count += import_entry(fs, &child.path(), &MyFileSystem::join_path(fs_path, &name))?;
+        }
+    } else if meta.is_file() {
+        match fs.stat(fs_path) {
+            Ok(m) if m.kind == InodeType::Directory => return Err(FsError::IsADirectory),
+            Ok(_) => fs.set_len(fs_path, 0)?,
+            Err(_) => {
+                fs.create_file(fs_path)?;
+            }
+        }
+
+        let mut file = File::open(host_path)?;
+        let mut buf = [0u8; BLOCK_SIZE];
+        let mut offset = 0;
+        loop {
+            let n = read_full(&mut file, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            fs.pwrite(fs_path, offset, &buf[..n])?;
+            offset += n;
+        }
+    } else if meta.file_type().is_symlink() {
+        let target = fs::read_link(host_path)?;
+        fs.symlink(&target.to_string_lossy(), fs_path)?;
+        // 符号链接本身的权限和时间戳没有意义
+        return Ok(count);
+    } else {
+        // 设备文件等镜像中没有对应的类型，跳过
+        report(
+            "import",
+            Some(&host_path.display().to_string()),
+            "skipping special file",
+        );
+        return Ok(0);
+    }
+
+    copy_host_metadata(fs, &meta, fs_path)?;
+    Ok(count)
+}
+
+// 尽量读满 buf，只有到达文件末尾时才返回不足一块的长度
+fn read_full(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+// export：把镜像中的文件或目录（递归）导出到宿主机，返回导出的条目数
+// 宿主机上的目标是已存在的目录时，导出到该目录下的同名条目
+pub fn export(fs: &mut MyFileSystem, fs_path: &str, host_path: &Path) -> Result<usize, FsError> {
+    let host_path = if host_path.is_dir() {
+        host_path.join(MyFileSystem::split_path(fs_path).1)
+    } else {
+        host_path.to_path_buf()
+    };
+    export_entry(fs, fs_path, &host_path)
+}
+
+fn export_entry(fs: &mut MyFileSystem, fs_path: &str, host_path: &Path) -> Result<usize, FsError> {
+    let meta = fs.lstat(fs_path)?;
+    let mut count = 1;
+
+    if meta.kind == InodeType::Symlink {
+        host_symlink(&fs.read_link(fs_path)?, host_path)?;
+        return Ok(count);
+    } else if meta.kind == InodeType::Directory {
+        if !host_path.is_dir() {
+            fs::create_dir(host_path)?;
+        }
+        for entry in fs.read_dir(fs_path)? {
+            let child_fs = MyFileSystem::join_path(fs_path, &entry.name);
+            count += export_entry(fs, &child_fs, &host_path.join(&entry.name))?;
+        }
+    } else {
+        let mut file = File::create(host_path)?;
+        let size = meta.size as usize;
+        let mut offset = 0;
+        while offset < size {
+            let chunk = fs.read_at(meta.ino, offset, BLOCK_SIZE)?;
+            file.write_all(&chunk)?;
+            offset += chunk.len();
+        }
+    }
+
+    let times = FileTimes::new()
+        .set_accessed(UNIX_EPOCH + Duration::from_secs(meta.atime))
+        .set_modified(UNIX_EPOCH + Duration::from_secs(meta.mtime));
+    File::open(host_path)?.set_times(times)?;
+    set_host_perm(host_path, meta.perm)?;
+    Ok(count)
+}
+
+// 一棵宿主机目录树放进镜像所需的空间
+struct TreeUsage {
+    data_blocks: u32,
+    inodes: u32,
+}
+
+// 统计目录树所需的数据块（含目录块和间接块）与 Inode 数，同时检查文件名长度和文件大小
+fn measure_tree(host_path: &Path) -> Result<TreeUsage, FsError> {
+    let mut children: Vec<_> = fs::read_dir(host_path)?.collect::<Result<_, _>>()?;
+    children.retain(|e| {
+        e.file_type()
+            .is_ok_and(|t| t.is_dir() || t.is_file() || t.is_symlink())
+    });
+
+    let entries_per_block = BLOCK_SIZE / DIR_ENTRY_SIZE;
+    let dir_blocks = (children.len() + 2).div_ceil(entries_per_block);
+    if dir_blocks > DIRECT_BLOCKS {
+        return Err(FsError::NoSpace);
+    }
+    let mut usage = TreeUsage {
+        data_blocks: dir_blocks as u32,
+        inodes: 0,
+    };
+
+    for child in children {
+        if child.file_name().len() > DIR_ENTRY_SIZE - 4 {
+            return Err(FsError::InvalidArgument);
+        }
+        let meta = fs::symlink_metadata(child.path())?;
+        usage.inodes += 1;
+        if meta.file_type().is_symlink() {
+            let target_len = fs::read_link(child.path())?.as_os_str().len();
+            usage.data_blocks += target_len.div_ceil(BLOCK_SIZE) as u32;
+        } else if meta.is_dir() {
+            let sub = measure_tree(&child.path())?;
+            usage.data_blocks += sub.data_blocks;
+            usage.inodes += sub.inodes;
+        } else {
+            let blocks = (meta.len() as usize).div_ceil(BLOCK_SIZE);
+            if blocks > MAX_FILE_BLOCKS {
+                return Err(FsError::FileTooLarge);
+            }
+            let indirect = if blocks > DIRECT_BLOCKS { 1 } else { 0 };
+            usage.data_blocks += (blocks + indirect) as u32;
+        }
+    }
+    Ok(usage)
+}
+
+// build_image 的选项
+#[derive(Default)]
+pub struct BuildOptions {
+    // 镜像的块数和 Inode 数，不指定时按目录树计算
+    pub blocks: Option<u32>,
+    pub inodes: Option<u32>,
+    // 设置后所有时间戳固定为该值，宿主机上更晚的时间戳被截断到该值
+    pub source_date_epoch: Option<u64>,
+    // 设置后 UUID 由种子决定
+    pub uuid_seed: Option<String>,
+}
+
+// build_image：新建一个刚好能放下 host_dir 的镜像并一次性填入其内容（类似 genext2fs）
+// 指定的大小放不下时在写入任何数据之前就返回 NoSpace
+// 目录项按名字排序、分配顺序固定，配合 source_date_epoch 和 uuid_seed 可以得到逐字节相同的镜像
+pub fn build_image(
+    image_path: &str,
+    host_dir: &Path,
+    opts: &BuildOptions,
+) -> Result<StatFs, FsError> {
+    if !fs::metadata(host_dir)?.is_dir() {
+        return Err(FsError::NotADirectory);
+    }
+    let usage = measure_tree(host_dir)?;
+
+    // Inode 0 保留，Inode 1 是根目录；根目录的第一个块由 format 分配
+    let inode_count = opts.inodes.unwrap_or(usage.inodes + 2);
+    let meta_blocks = INODE_TABLE_START + inode_table_blocks(inode_count);
+    let total_blocks = opts.blocks.unwrap_or(meta_blocks + usage.data_blocks);
+    if inode_count < usage.inodes + 2
+        || total_blocks < meta_blocks + usage.data_blocks
+        || inode_count > MAX_BITMAP_BITS
+        || total_blocks > MAX_BITMAP_BITS
+    {
+        return Err(FsError::NoSpace);
+    }
+
+    // 先删除旧文件，避免残留的旧数据进入新镜像
+    if Path::new(image_path).exists() {
+        fs::remove_file(image_path)?;
+    }
+    let mut image = MyFileSystem::new(image_path)?;
+    image.set_fixed_time(opts.source_date_epoch);
+    image.set_uuid_seed(opts.uuid_seed.clone());
+    image.format_with(total_blocks, inode_count);
+
+    // 在全新的镜像上按排序后的顺序依次写入，首次适配分配保证每个文件的数据块是连续的
+    let result = (|| {
+        let mut children: Vec<_> = fs::read_dir(host_dir)?.collect::<Result<_, _>>()?;
+        children.sort_by_key(|e| e.file_name());
+        for child in children {
+            let name = child.file_name().to_string_lossy().into_owned();
+            import_entry(
+                &mut image,
+                &child.path(),
+                &MyFileSystem::join_path("/", &name),
+            )?;
+        }
+        copy_host_metadata(&mut image, &fs::metadata(host_dir)?, "/")?;
+        Ok(image.statfs())
+    })();
+
+    if result.is_err() {
+        drop(image);
+        let _ = fs::remove_file(image_path);
+    }
+    result
+}
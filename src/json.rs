@@ -0,0 +1,202 @@
+use crate::fs::{FsckReport, Metadata, StatFs};
+use crate::layout::InodeType;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// JSON 输出：--json 或 shell 中的 set -o json 打开后，ls / stat / df / du / find / fsck
+// 以及错误信息改为输出一行 JSON，内容直接由文件系统返回的结构构造
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(u64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // 保持字段的插入顺序
+}
+
+impl Json {
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    // 往对象中追加一个字段；不是对象时什么也不做
+    pub fn push(&mut self, key: &str, value: Json) {
+        if let Json::Object(fields) = self {
+            fields.push((key.to_string(), value));
+        }
+    }
+}
+
+macro_rules! from_num {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Json {
+            fn from(n: $t) -> Json {
+                Json::Num(n as u64)
+            }
+        })*
+    };
+}
+from_num!(u32, u64, usize);
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::Str(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::Str(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(v: Option<T>) -> Json {
+        v.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(v: Vec<T>) -> Json {
+        Json::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Num(n) => write!(f, "{}", n),
+            Json::Str(s) => write_str(f, s),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+pub fn kind_name(kind: InodeType) -> &'static str {
+    match kind {
+        InodeType::File => "file",
+        InodeType::Directory => "directory",
+        InodeType::Symlink => "symlink",
+        InodeType::Unused => "unused",
+    }
+}
+
+impl From<&Metadata> for Json {
+    fn from(meta: &Metadata) -> Json {
+        Json::object([
+            ("ino", meta.ino.into()),
+            ("type", kind_name(meta.kind).into()),
+            ("size", meta.size.into()),
+            ("blocks", meta.allocated_blocks().into()),
+            ("compressed", meta.compressed.into()),
+            ("block_list", meta.blocks.clone().into()),
+            (
+                "indirect",
+                (meta.indirect != 0).then_some(meta.indirect).into(),
+            ),
+            ("nlinks", meta.nlinks.into()),
+            ("perm", format!("{:04o}", meta.perm).into()),
+            ("uid", meta.uid.into()),
+            ("gid", meta.gid.into()),
+            ("atime", meta.atime.into()),
+            ("mtime", meta.mtime.into()),
+            ("ctime", meta.ctime.into()),
+        ])
+    }
+}
+
+impl From<&StatFs> for Json {
+    fn from(st: &StatFs) -> Json {
+        Json::object([
+            ("block_size", st.block_size.into()),
+            ("total_blocks", st.total_blocks.into()),
+            ("used_blocks", (st.total_blocks - st.free_blocks).into()),
+            ("free_blocks", st.free_blocks.into()),
+            ("total_inodes", st.total_inodes.into()),
+            ("used_inodes", (st.total_inodes - st.free_inodes).into()),
+            ("free_inodes", st.free_inodes.into()),
+        ])
+    }
+}
+
+impl From<&FsckReport> for Json {
+    fn from(report: &FsckReport) -> Json {
+        Json::object([
+            ("clean", report.is_clean().into()),
+            ("recorded_free_blocks", report.recorded_free_blocks.into()),
+            ("actual_free_blocks", report.actual_free_blocks.into()),
+            ("recorded_free_inodes", report.recorded_free_inodes.into()),
+            ("actual_free_inodes", report.actual_free_inodes.into()),
+        ])
+    }
+}
+
+// 错误信息：{"error":{"command":..,"path":..,"message":..}}，写到标准错误
+pub fn print_error(command: &str, path: Option<&str>, message: &str) {
+    let mut error = Json::object([("command", command.into())]);
+    if let Some(path) = path {
+        error.push("path", path.into());
+    }
+    error.push("message", message.into());
+    eprintln!("{}", Json::object([("error", error)]));
+}
@@ -2,12 +2,43 @@ pub const BLOCK_SIZE: usize = 4096;
 pub const INODE_SIZE: usize = 128;
 pub const DIR_ENTRY_SIZE: usize = 64;
 pub const MAGIC: u32 = 0x12345678;
+pub const INODE_TABLE_START: u32 = 3;
+pub const DEFAULT_TOTAL_BLOCKS: u32 = 1024;
+pub const DEFAULT_INODE_COUNT: u32 = 128;
+// 位图各占一个块，块数和 Inode 数都不能超过一个块的位数
+pub const MAX_BITMAP_BITS: u32 = (BLOCK_SIZE * 8) as u32;
+pub const DIRECT_BLOCKS: usize = 12;
+// 解析一条路径时最多跟随的符号链接数，超过视为循环
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+// 一个间接块可以存放的块号个数
+pub const PTRS_PER_BLOCK: usize = BLOCK_SIZE / 4;
+// 单个文件最多可映射的数据块数：12 个直接块 + 1 个一级间接块
+pub const MAX_FILE_BLOCKS: usize = DIRECT_BLOCKS + PTRS_PER_BLOCK;
+// 快照表中每条记录的大小，快照表占一个块
+pub const SNAPSHOT_RECORD_SIZE: usize = 64;
+pub const MAX_SNAPSHOTS: usize = BLOCK_SIZE / SNAPSHOT_RECORD_SIZE;
+pub const MAX_SNAPSHOT_NAME: usize = 47;
+// 根目录下浏览快照的虚拟目录
+pub const SNAPSHOT_DIR: &str = ".snapshots";
+// 超级块 flags：写入时在线去重
+pub const FLAG_INLINE_DEDUP: u32 = 1;
+// Inode flags：文件数据压缩存放；目录带这个标志时，其中新建的文件和目录继承它
+pub const INODE_FLAG_COMPRESSED: u32 = 1;
+// 压缩文件按簇存放，每簇 CLUSTER_BLOCKS 个逻辑块，各簇独立压缩
+pub const CLUSTER_BLOCKS: usize = 16;
+pub const CLUSTER_SIZE: usize = CLUSTER_BLOCKS * BLOCK_SIZE;
+
+// 存放 inode_count 个 Inode 需要的 Inode 表块数
+pub fn inode_table_blocks(inode_count: u32) -> u32 {
+    (inode_count as usize * INODE_SIZE).div_ceil(BLOCK_SIZE) as u32
+}
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum InodeType {
     Unused = 0,
     File = 1,
     Directory = 2,
+    Symlink = 3,
 }
 
 pub struct Superblock {
@@ -15,6 +46,15 @@ pub struct Superblock {
     pub total_blocks: u32,
     pub inode_count: u32,
     pub data_area_start: u32,
+    pub free_blocks: u32,
+    pub free_inodes: u32,
+    pub uuid: [u8; 16],
+    // 引用计数索引块：其中依次存放引用计数表各块的块号，0 表示还没有共享的块
+    pub refcount_index: u32,
+    // 快照表所在的块，0 表示没有快照
+    pub snapshot_table: u32,
+    // FLAG_* 标志位
+    pub flags: u32,
 }
 
 impl Superblock {
@@ -24,17 +64,70 @@ impl Superblock {
         buf[4..8].copy_from_slice(&self.total_blocks.to_le_bytes());
         buf[8..12].copy_from_slice(&self.inode_count.to_le_bytes());
         buf[12..16].copy_from_slice(&self.data_area_start.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.free_blocks.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.free_inodes.to_le_bytes());
+        buf[24..40].copy_from_slice(&self.uuid);
+        buf[40..44].copy_from_slice(&self.refcount_index.to_le_bytes());
+        buf[44..48].copy_from_slice(&self.snapshot_table.to_le_bytes());
+        buf[48..52].copy_from_slice(&self.flags.to_le_bytes());
         buf
     }
+
+    pub fn deserialize(buf: &[u8]) -> Self {
+        Superblock {
+            magic: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            total_blocks: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+            inode_count: u32::from_le_bytes(buf[8..12].try_into().unwrap()),
+            data_area_start: u32::from_le_bytes(buf[12..16].try_into().unwrap()),
+            free_blocks: u32::from_le_bytes(buf[16..20].try_into().unwrap()),
+            free_inodes: u32::from_le_bytes(buf[20..24].try_into().unwrap()),
+            uuid: buf[24..40].try_into().unwrap(),
+            refcount_index: u32::from_le_bytes(buf[40..44].try_into().unwrap()),
+            snapshot_table: u32::from_le_bytes(buf[44..48].try_into().unwrap()),
+            flags: u32::from_le_bytes(buf[48..52].try_into().unwrap()),
+        }
+    }
 }
 
 pub struct Inode {
     pub mode: InodeType,
     pub size: u32,
     pub blocks: [u32; 12],
+    pub nlinks: u32,
+    pub perm: u32,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub indirect: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub flags: u32, // INODE_FLAG_*
 }
 
 impl Inode {
+    // 新建 Inode 的默认属性：目录 0755 且链接数为 2（自身与 "."），文件 0644、符号链接 0777 且链接数为 1
+    pub fn new(mode: InodeType, now: u64) -> Self {
+        let (perm, nlinks) = match mode {
+            InodeType::Directory => (0o755, 2),
+            InodeType::Symlink => (0o777, 1),
+            _ => (0o644, 1),
+        };
+        Inode {
+            mode,
+            size: 0,
+            blocks: [0; 12],
+            nlinks,
+            perm,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            indirect: 0,
+            uid: 0,
+            gid: 0,
+            flags: 0,
+        }
+    }
+
     pub fn serialize(&self) -> [u8; INODE_SIZE] {
         let mut buf = [0u8; INODE_SIZE];
         buf[0..4].copy_from_slice(&(self.mode as u32).to_le_bytes());
@@ -42,6 +135,15 @@ impl Inode {
         for i in 0..12 {
             buf[8 + i * 4..12 + i * 4].copy_from_slice(&self.blocks[i].to_le_bytes());
         }
+        buf[56..60].copy_from_slice(&self.nlinks.to_le_bytes());
+        buf[60..64].copy_from_slice(&self.perm.to_le_bytes());
+        buf[64..72].copy_from_slice(&self.atime.to_le_bytes());
+        buf[72..80].copy_from_slice(&self.mtime.to_le_bytes());
+        buf[80..88].copy_from_slice(&self.ctime.to_le_bytes());
+        buf[88..92].copy_from_slice(&self.indirect.to_le_bytes());
+        buf[92..96].copy_from_slice(&self.uid.to_le_bytes());
+        buf[96..100].copy_from_slice(&self.gid.to_le_bytes());
+        buf[100..104].copy_from_slice(&self.flags.to_le_bytes());
         buf
     }
 
@@ -50,6 +152,7 @@ impl Inode {
         let mode = match mode_raw {
             1 => InodeType::File,
             2 => InodeType::Directory,
+            3 => InodeType::Symlink,
             _ => InodeType::Unused,
         };
         let size = u32::from_le_bytes(buf[4..8].try_into().unwrap());
@@ -57,7 +160,20 @@ impl Inode {
         for i in 0..12 {
             blocks[i] = u32::from_le_bytes(buf[8 + i * 4..12 + i * 4].try_into().unwrap());
         }
-        Inode { mode, size, blocks }
+        Inode {
+            mode,
+            size,
+            blocks,
+            nlinks: u32::from_le_bytes(buf[56..60].try_into().unwrap()),
+            perm: u32::from_le_bytes(buf[60..64].try_into().unwrap()),
+            atime: u64::from_le_bytes(buf[64..72].try_into().unwrap()),
+            mtime: u64::from_le_bytes(buf[72..80].try_into().unwrap()),
+            ctime: u64::from_le_bytes(buf[80..88].try_into().unwrap()),
+            indirect: u32::from_le_bytes(buf[88..92].try_into().unwrap()),
+            uid: u32::from_le_bytes(buf[92..96].try_into().unwrap()),
+            gid: u32::from_le_bytes(buf[96..100].try_into().unwrap()),
+            flags: u32::from_le_bytes(buf[100..104].try_into().unwrap()),
+        }
     }
 }
 
@@ -82,4 +198,32 @@ impl DirEntry {
         let name = String::from_utf8_lossy(&buf[4..4 + name_end]).into_owned();
         DirEntry { inode_no, name }
     }
-}
\ No newline at end of file
+}
+
+// 快照表中的一条记录；descriptor 指向的块中依次存放 Inode 位图副本和 Inode 表副本的块号
+pub struct SnapshotRecord {
+    pub name: String,
+    pub created: u64,
+    pub descriptor: u32,
+}
+
+impl SnapshotRecord {
+    pub fn serialize(&self) -> [u8; SNAPSHOT_RECORD_SIZE] {
+        let mut buf = [0u8; SNAPSHOT_RECORD_SIZE];
+        let name_bytes = self.name.as_bytes();
+        let len = name_bytes.len().min(MAX_SNAPSHOT_NAME);
+        buf[..len].copy_from_slice(&name_bytes[..len]);
+        buf[48..56].copy_from_slice(&self.created.to_le_bytes());
+        buf[56..60].copy_from_slice(&self.descriptor.to_le_bytes());
+        buf
+    }
+
+    pub fn deserialize(buf: &[u8]) -> Self {
+        let name_end = buf[..48].iter().position(|&b| b == 0).unwrap_or(48);
+        SnapshotRecord {
+            name: String::from_utf8_lossy(&buf[..name_end]).into_owned(),
+            created: u64::from_le_bytes(buf[48..56].try_into().unwrap()),
+            descriptor: u32::from_le_bytes(buf[56..60].try_into().unwrap()),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+// 终端上的行编辑：左右方向键移动光标，上下方向键浏览历史，Tab 补全
+// 只用标准库：通过 stty 把终端切换到非规范模式，自己处理每一个按键
+// 历史记录保存在文件中，跨会话保留
+
+const MAX_HISTORY: usize = 1000;
+
+// 补全结果：光标前从第 start 个字符开始的内容可以替换成 candidates 中的任意一个
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+// 进入非规范、不回显的模式，离开作用域时恢复原来的终端设置
+#[cfg(unix)]
+struct RawMode {
+    saved: String,
+}
+
+#[cfg(unix)]
+fn stty(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> Option<Self> {
+        let saved = stty(&["-g"])?;
+        // -isig：Ctrl-C 交给编辑器处理（取消当前行），而不是结束进程
+        stty(&[
+            "-icanon", "-echo", "-isig", "-ixon", "min", "1", "time", "0",
+        ])?;
+        Some(RawMode { saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+#[cfg(not(unix))]
+struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    fn enable() -> Option<Self> {
+        None
+    }
+}
+
+// 解码后的按键
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Ctrl(u8), // Ctrl 加字母，值为字母的小写形式
+    Unknown,
+}
+
+// 字符在终端上占的列数，CJK 等宽字符占两列
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115f
+        | 0x2e80..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+fn str_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+// 所有候选共同的最长前缀
+fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix: Vec<char> = candidates[0].chars().collect();
+    for c in &candidates[1..] {
+        let len = prefix
+            .iter()
+            .zip(c.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(len);
+    }
+    prefix.into_iter().collect()
+}
+
+impl LineEditor {
+    // history_path 为 None 时历史记录只在本次会话中有效
+    pub fn new(history_path: Option<PathBuf>) -> Self {
+        let history = history_path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        LineEditor {
+            history,
+            history_path,
+        }
+    }
+
+    // 记录一条历史，与上一条相同时不重复记录；同时追加到历史文件，超过上限时重写整个文件
+    pub fn add_history(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.trim().is_empty() || line.contains('\n') {
+            return;
+        }
+        if self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        // 历史文件写不进去不影响使用，忽略错误
+        if self.history.len() > MAX_HISTORY {
+            let excess = self.history.len() - MAX_HISTORY;
+            self.history.drain(..excess);
+            let mut text = self.history.join("\n");
+            text.push('\n');
+            let _ = fs::write(path, text);
+        } else if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    // 读入一行，返回的内容以换行结尾；输入结束（空行上按 Ctrl-D）时返回 None
+    // 终端不支持时退化为普通的按行读取
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: &mut dyn FnMut(&str) -> Completion,
+    ) -> Option<String> {
+        print!("{}", prompt);
+        io::stdout().flush().ok()?;
+
+        let Some(_raw) = RawMode::enable() else {
+            let mut line = String::new();
+            return match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line),
+            };
+        };
+
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // 浏览历史时的位置；history.len() 表示正在编辑的新行，draft 保存它的内容
+        let mut hist_pos = self.history.len();
+        let mut draft: Vec<char> = Vec::new();
+
+        loop {
+            let key = read_key()?;
+            match key {
+                Key::Enter => {
+                    println!();
+                    let mut text: String = line.into_iter().collect();
+                    text.push('\n');
+                    return Some(text);
+                }
+                Key::Ctrl(b'd') if line.is_empty() => {
+                    println!();
+                    return None;
+                }
+                Key::Ctrl(b'c') => {
+                    // 取消当前行
+                    println!("^C");
+                    return Some("\n".to_string());
+                }
+                Key::Char(c) => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                Key::Backspace | Key::Ctrl(b'h') if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Key::Delete | Key::Ctrl(b'd') if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::Left | Key::Ctrl(b'b') if cursor > 0 => cursor -= 1,
+                Key::Right | Key::Ctrl(b'f') if cursor < line.len() => cursor += 1,
+                Key::Home | Key::Ctrl(b'a') => cursor = 0,
+                Key::End | Key::Ctrl(b'e') => cursor = line.len(),
+                Key::Ctrl(b'u') => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                Key::Ctrl(b'k') => line.truncate(cursor),
+                Key::Ctrl(b'w') => {
+                    // 删除光标前的一个单词
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1] == ' ' {
+                        start -= 1;
+                    }
+                    while start > 0 && line[start - 1] != ' ' {
+                        start -= 1;
+                    }
+                    line.drain(start..cursor);
+                    cursor = start;
+                }
+                Key::Up | Key::Ctrl(b'p') if hist_pos > 0 => {
+                    if hist_pos == self.history.len() {
+                        draft = line.clone();
+                    }
+                    hist_pos -= 1;
+                    line = self.history[hist_pos].chars().collect();
+                    cursor = line.len();
+                }
+                Key::Down | Key::Ctrl(b'n') if hist_pos < self.history.len() => {
+                    hist_pos += 1;
+                    line = if hist_pos == self.history.len() {
+                        draft.clone()
+                    } else {
+                        self.history[hist_pos].chars().collect()
+                    };
+                    cursor = line.len();
+                }
+                Key::Tab => {
+                    let before: String = line[..cursor].iter().collect();
+                    let completion = complete(&before);
+                    apply_completion(&mut line, &mut cursor, completion);
+                }
+                Key::Ctrl(b'l') => print!("\x1b[H\x1b[2J"),
+                _ => {}
+            }
+            redraw(prompt, &line, cursor);
+        }
+    }
+}
+
+// 只有一个候选时直接替换；有多个时先补全共同前缀，已经无法再补全时列出所有候选
+fn apply_completion(line: &mut Vec<char>, cursor: &mut usize, completion: Completion) {
+    let Completion { start, candidates } = completion;
+    if candidates.is_empty() || start > *cursor {
+        return;
+    }
+    let current: String = line[start..*cursor].iter().collect();
+    let replacement = if candidates.len() == 1 {
+        let mut only = candidates[0].clone();
+        // 补全的是目录时留在目录里继续补全，否则加一个空格开始下一个参数
+        if !only.ends_with('/') {
+            only.push(' ');
+        }
+        only
+    } else {
+        let prefix = common_prefix(&candidates);
+        if prefix.chars().count() <= current.chars().count() {
+            print_candidates(&candidates);
+            return;
+        }
+        prefix
+    };
+    let new: Vec<char> = replacement.chars().collect();
+    let len = new.len();
+    line.splice(start..*cursor, new);
+    *cursor = start + len;
+}
+
+// 重绘当前行：回到行首，输出提示符和内容，清除行尾，再把光标移回原位
+fn redraw(prompt: &str, line: &[char], cursor: usize) {
+    let text: String = line.iter().collect();
+    let back: usize = line[cursor..].iter().map(|&c| char_width(c)).sum();
+    let mut out = io::stdout();
+    let _ = write!(out, "\r{}{}\x1b[K", prompt, text);
+    if back > 0 {
+        let _ = write!(out, "\x1b[{}D", back);
+    }
+    let _ = out.flush();
+}
+
+// 把候选分列显示在下一行，路径只显示最后一个分量
+fn print_candidates(candidates: &[String]) {
+    let names: Vec<&str> = candidates
+        .iter()
+        .map(|c| {
+            let trimmed = c.strip_suffix('/').unwrap_or(c);
+            match trimmed.rfind('/') {
+                Some(i) => &c[i + 1..],
+                None => c.as_str(),
+            }
+        })
+        .collect();
+    let width = names.iter().map(|n| str_width(n)).max().unwrap_or(0) + 2;
+    let columns = (80 / width).max(1);
+    println!();
+    for (i, name) in names.iter().enumerate() {
+        print!("{}{}", name, " ".repeat(width - str_width(name)));
+        if (i + 1) % columns == 0 || i + 1 == names.len() {
+            println!();
+        }
+    }
+}
+
+fn read_byte() -> Option<u8> {
+    let mut buf = [0u8; 1];
+    match io::stdin().lock().read(&mut buf) {
+        Ok(1) => Some(buf[0]),
+        _ => None,
+    }
+}
+
+// 读入一个按键：解码 UTF-8 多字节字符和 ESC 开头的控制序列
+fn read_key() -> Option<Key> {
+    let b = read_byte()?;
+    let key = match b {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f => Key::Backspace,
+        0x1b => match read_byte()? {
+            b'[' | b'O' => {
+                // CSI 序列：参数是数字和分号，以 0x40..0x7e 之间的字节结束
+                let mut params = String::new();
+                let last = loop {
+                    let c = read_byte()?;
+                    if (0x40..=0x7e).contains(&c) {
+                        break c;
+                    }
+                    params.push(c as char);
+                };
+                match (last, params.as_str()) {
+                    (b'A', _) => Key::Up,
+                    (b'B', _) => Key::Down,
+                    (b'C', _) => Key::Right,
+                    (b'D', _) => Key::Left,
+                    (b'H', _) | (b'~', "1" | "7") => Key::Home,
+                    (b'F', _) | (b'~', "4" | "8") => Key::End,
+                    (b'~', "3") => Key::Delete,
+                    _ => Key::Unknown,
+                }
+            }
+            _ => Key::Unknown,
+        },
+        0x01..=0x1a => Key::Ctrl(b - 1 + b'a'),
+        0x00..=0x1f => Key::Unknown,
+        _ => {
+            // UTF-8 首字节中前导 1 的个数就是字符的总字节数
+            let len = match b {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![b];
+            for _ in 1..len {
+                bytes.push(read_byte()?);
+            }
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+    };
+    Some(key)
+}
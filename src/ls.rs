@@ -0,0 +1,235 @@
+use crate::error::FsError;
+use crate::fs::{Metadata, MyFileSystem};
+use crate::json::{self, Json};
+use crate::layout::InodeType;
+use crate::{EXIT_FAILURE, fail, format_time, mode_string, to_absolute_path, usage};
+use std::io::Write;
+
+// ls [-l] [-a] [-i] [-R] [-S] [-t] [-r] [path...]
+// 每个条目的信息都来自 lstat 返回的 Metadata；默认按目录项的顺序列出，
+// -S 按大小、-t 按修改时间从大到小排序（相同时保持目录项顺序），-r 反转顺序
+// JSON 模式下输出 {"files":[...],"directories":[{"path":..,"entries":[...]}]}
+
+const LS_USAGE: &str = "ls [-l] [-a] [-i] [-R] [-S] [-t] [-r] [path...]";
+
+#[derive(Default)]
+struct Options {
+    long: bool,      // -l 长格式
+    all: bool,       // -a 同时列出 . 和 ..
+    inode: bool,     // -i 显示 Inode 号
+    recursive: bool, // -R 递归列出子目录
+    by_size: bool,   // -S
+    by_time: bool,   // -t
+    reverse: bool,   // -r
+}
+
+// 要显示的一个条目
+struct Entry {
+    name: String,
+    meta: Metadata,
+    target: Option<String>, // 符号链接的目标，只在长格式中显示
+}
+
+struct Lister<'a> {
+    fs: &'a mut MyFileSystem,
+    opts: Options,
+    listings: Vec<Json>, // JSON 模式下收集的目录列表
+    out: &'a mut Vec<u8>,
+}
+
+impl From<&Entry> for Json {
+    fn from(e: &Entry) -> Json {
+        let mut value = Json::object([("name", e.name.as_str().into())]);
+        if let Json::Object(fields) = Json::from(&e.meta) {
+            for (key, field) in fields {
+                value.push(&key, field);
+            }
+        }
+        value.push("target", e.target.clone().into());
+        value
+    }
+}
+
+impl Lister<'_> {
+    fn entry(&mut self, path: &str, name: &str) -> Result<Entry, FsError> {
+        let meta = self.fs.lstat(path)?;
+        let target = if (self.opts.long || json::enabled()) && meta.kind == InodeType::Symlink {
+            self.fs.read_link(path).ok()
+        } else {
+            None
+        };
+        Ok(Entry {
+            name: name.to_string(),
+            meta,
+            target,
+        })
+    }
+
+    fn sort(&self, entries: &mut [Entry]) {
+        if self.opts.by_size {
+            entries.sort_by_key(|e| std::cmp::Reverse(e.meta.size));
+        } else if self.opts.by_time {
+            entries.sort_by_key(|e| std::cmp::Reverse(e.meta.mtime));
+        }
+        if self.opts.reverse {
+            entries.reverse();
+        }
+    }
+
+    // 输出一组条目；长格式下各列按最宽的值对齐
+    fn print(&mut self, entries: &[Entry]) {
+        let width =
+            |f: &dyn Fn(&Entry) -> String| entries.iter().map(|e| f(e).len()).max().unwrap_or(0);
+        let ino_w = width(&|e| e.meta.ino.to_string());
+        let links_w = width(&|e| e.meta.nlinks.to_string());
+        let uid_w = width(&|e| e.meta.uid.to_string());
+        let gid_w = width(&|e| e.meta.gid.to_string());
+        let size_w = width(&|e| e.meta.size.to_string());
+
+        for e in entries {
+            let mut line = String::from("  ");
+            if self.opts.inode {
+                line += &format!("{:>w$} ", e.meta.ino, w = ino_w);
+            }
+            if self.opts.long {
+                line += &format!(
+                    "{} {:>lw$} {:>uw$} {:>gw$} {:>sw$} {} {}",
+                    mode_string(e.meta.kind, e.meta.perm),
+                    e.meta.nlinks,
+                    e.meta.uid,
+                    e.meta.gid,
+                    e.meta.size,
+                    format_time(e.meta.mtime),
+                    e.name,
+                    lw = links_w,
+                    uw = uid_w,
+                    gw = gid_w,
+                    sw = size_w,
+                );
+                if let Some(target) = &e.target {
+                    line += &format!(" -> {}", target);
+                }
+            } else {
+                let suffix = match e.meta.kind {
+                    InodeType::Directory => "/",
+                    InodeType::Symlink => "@",
+                    _ => "",
+                };
+                line += &format!("{}{}", e.name, suffix);
+            }
+            writeln!(self.out, "{}", line).unwrap();
+        }
+    }
+
+    // 列出一个目录；-R 时接着列出每个子目录（不进入 . 和 ..，也不跟随符号链接）
+    fn list_dir(&mut self, abs_path: &str) -> Result<(), FsError> {
+        let mut entries = Vec::new();
+        if self.opts.all {
+            let parent = MyFileSystem::split_path(abs_path).0.to_string();
+            entries.push(self.entry(abs_path, ".")?);
+            entries.push(self.entry(&parent, "..")?);
+        }
+        for dirent in self.fs.read_dir(abs_path)? {
+            let child = MyFileSystem::join_path(abs_path, &dirent.name);
+            entries.push(self.entry(&child, &dirent.name)?);
+        }
+        self.sort(&mut entries);
+
+        if json::enabled() {
+            let entries: Vec<Json> = entries.iter().map(Json::from).collect();
+            self.listings.push(Json::object([
+                ("path", abs_path.into()),
+                ("entries", Json::Array(entries)),
+            ]));
+        } else {
+            writeln!(self.out, "Listing directory: {}", abs_path).unwrap();
+            self.print(&entries);
+        }
+
+        if self.opts.recursive {
+            for e in &entries {
+                if e.meta.kind == InodeType::Directory && e.name != "." && e.name != ".." {
+                    if !json::enabled() {
+                        writeln!(self.out).unwrap();
+                    }
+                    let child = MyFileSystem::join_path(abs_path, &e.name);
+                    self.list_dir(&child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn run_ls(fs: &mut MyFileSystem, args: &[&str], out: &mut Vec<u8>) -> i32 {
+    let mut opts = Options::default();
+    let mut paths = Vec::new();
+    for &arg in args {
+        if !arg.starts_with('-') || arg.len() == 1 {
+            paths.push(arg);
+            continue;
+        }
+        // 单字母选项可以合并，例如 -la
+        for flag in arg[1..].chars() {
+            match flag {
+                'l' => opts.long = true,
+                'a' => opts.all = true,
+                'i' => opts.inode = true,
+                'R' => opts.recursive = true,
+                'S' => opts.by_size = true,
+                't' => opts.by_time = true,
+                'r' => opts.reverse = true,
+                _ => return usage(LS_USAGE),
+            }
+        }
+    }
+    if paths.is_empty() {
+        paths.push(".");
+    }
+
+    let mut lister = Lister {
+        fs,
+        opts,
+        listings: Vec::new(),
+        out,
+    };
+    let mut status = 0;
+    // 先集中列出不是目录的参数，再逐个列出目录
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for raw_path in paths {
+        let abs_path = to_absolute_path(&lister.fs.cwd_path, raw_path);
+        match lister.fs.stat(&abs_path) {
+            Ok(meta) if meta.kind == InodeType::Directory => dirs.push(abs_path),
+            Ok(_) => match lister.entry(&abs_path, raw_path) {
+                Ok(entry) => files.push(entry),
+                Err(e) => status = fail("ls", &abs_path, e),
+            },
+            Err(e) => status = fail("ls", &abs_path, e),
+        }
+    }
+    lister.sort(&mut files);
+    if !json::enabled() {
+        lister.print(&files);
+    }
+    for (i, abs_path) in dirs.iter().enumerate() {
+        if (i > 0 || !files.is_empty()) && !json::enabled() {
+            writeln!(lister.out).unwrap();
+        }
+        if let Err(e) = lister.list_dir(abs_path) {
+            status = fail("ls", abs_path, e);
+        }
+    }
+    if json::enabled() {
+        let files: Vec<Json> = files.iter().map(Json::from).collect();
+        let value = Json::object([
+            ("files", Json::Array(files)),
+            (
+                "directories",
+                Json::Array(std::mem::take(&mut lister.listings)),
+            ),
+        ]);
+        writeln!(lister.out, "{}", value).unwrap();
+    }
+    if status != 0 { EXIT_FAILURE } else { 0 }
+}
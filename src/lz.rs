@@ -0,0 +1,271 @@
+// 自带的 LZ77 压缩编码，格式与 LZ4 块格式相同：压缩数据是一串序列，每个序列为
+//   标记字节（高 4 位字面量长度，低 4 位匹配长度 - 4）、扩展长度、字面量、2 字节偏移、扩展长度
+// 长度为 15 时后面跟若干扩展字节（255 表示继续）；最后一个序列只有字面量，没有偏移
+// 与 LZ4 一样，最后 5 个字节总是字面量，最后一个匹配至少在结尾前 12 个字节开始
+// 压缩时用哈希链记录每个 4 字节序列出现过的位置，贪心地取链上最长的匹配
+
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+const MF_LIMIT: usize = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 14;
+const MAX_CHAIN: usize = 32;
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+// 把位置 pos 加入哈希链：head[h] 是哈希为 h 的最近位置，prev[i] 是位置 i 之前
+// 同一哈希的上一个位置。返回 pos 之前同一哈希的位置
+fn insert(input: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) -> usize {
+    let h = hash(u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap()));
+    prev[pos] = std::mem::replace(&mut head[h], pos);
+    prev[pos]
+}
+
+// 长度超过 4 位能表示的部分：每个 255 表示还有后续字节
+fn write_length(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn read_length(input: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut n = 0;
+    loop {
+        let b = *input.get(*pos)?;
+        *pos += 1;
+        n += b as usize;
+        if b != 255 {
+            return Some(n);
+        }
+    }
+}
+
+// 输出一个序列：literals 之后复制 (偏移, 长度) 的匹配；最后一个序列没有匹配
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8);
+    if lit_len >= 15 {
+        write_length(out, lit_len - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; input.len()];
+
+    let mut anchor = 0; // 还没有输出的字面量从这里开始
+    let mut pos = 0;
+    while pos + MF_LIMIT <= input.len() {
+        // 沿哈希链找最长的匹配，最多检查 MAX_CHAIN 个候选位置
+        let mut best = (0, 0);
+        let mut candidate = insert(input, pos, &mut head, &mut prev);
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate > MAX_OFFSET {
+                break;
+            }
+            let len = input[candidate..]
+                .iter()
+                .zip(&input[pos..input.len() - LAST_LITERALS])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best.1 {
+                best = (pos - candidate, len);
+            }
+            candidate = prev[candidate];
+        }
+        if best.1 < MIN_MATCH {
+            pos += 1;
+            continue;
+        }
+        write_sequence(&mut out, &input[anchor..pos], Some(best));
+        // 匹配覆盖的位置也加入哈希链，后面的数据才能引用它们
+        for p in pos + 1..(pos + best.1).min(input.len() - MIN_MATCH + 1) {
+            insert(input, p, &mut head, &mut prev);
+        }
+        pos += best.1;
+        anchor = pos;
+    }
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+// 解压缩；数据损坏或解出的长度不是 expected_len 时返回 None
+pub fn decompress(input: &[u8], expected_len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(expected_len);
+    let mut pos = 0;
+    loop {
+        let token = *input.get(pos)?;
+        pos += 1;
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len += read_length(input, &mut pos)?;
+        }
+        if out.len() + lit_len > expected_len {
+            return None;
+        }
+        out.extend_from_slice(input.get(pos..pos + lit_len)?);
+        pos += lit_len;
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes(input.get(pos..pos + 2)?.try_into().unwrap()) as usize;
+        pos += 2;
+        let mut match_len = (token & 0x0f) as usize;
+        if match_len == 15 {
+            match_len += read_length(input, &mut pos)?;
+        }
+        match_len += MIN_MATCH;
+        if offset == 0 || offset > out.len() || out.len() + match_len > expected_len {
+            return None;
+        }
+        // 匹配可以和正在输出的部分重叠（offset < match_len），只能逐字节复制
+        let start = out.len() - offset;
+        for i in 0..match_len {
+            out.push(out[start + i]);
+        }
+    }
+    (out.len() == expected_len).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed, data.len()).as_deref(), Some(data));
+        compressed
+    }
+
+    // 逐个序列解析，返回最后一个序列的字面量长度
+    fn last_literals(compressed: &[u8]) -> usize {
+        let mut pos = 0;
+        loop {
+            let token = compressed[pos];
+            pos += 1;
+            let mut lit_len = (token >> 4) as usize;
+            if lit_len == 15 {
+                lit_len += read_length(compressed, &mut pos).unwrap();
+            }
+            pos += lit_len;
+            if pos == compressed.len() {
+                return lit_len;
+            }
+            pos += 2;
+            if token & 0x0f == 15 {
+                read_length(compressed, &mut pos).unwrap();
+            }
+        }
+    }
+
+    // 确定性的伪随机数据，几乎无法压缩
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x = 0x2545f491u32;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(round_trip(&[]), [0]);
+        assert_eq!(decompress(&[], 0), None);
+    }
+
+    #[test]
+    fn short_input_is_all_literals() {
+        let data = b"abcdabcdabc";
+        assert_eq!(round_trip(data).len(), 1 + data.len());
+    }
+
+    #[test]
+    fn incompressible_data() {
+        let data = noise(65536);
+        let compressed = round_trip(&data);
+        // 只多出标记字节和长度字节
+        assert!(compressed.len() <= data.len() + data.len() / 255 + 16);
+    }
+
+    #[test]
+    fn long_runs() {
+        let mut data = vec![0u8; 65536];
+        data.extend(vec![b'x'; 1000]);
+        let compressed = round_trip(&data);
+        assert!(compressed.len() < 600);
+        assert!(last_literals(&compressed) >= LAST_LITERALS);
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        // 偏移小于匹配长度，解压时匹配与正在输出的部分重叠
+        let data: Vec<u8> = b"abc".iter().copied().cycle().take(3000).collect();
+        let compressed = round_trip(&data);
+        assert!(compressed.len() < 40);
+        assert!(last_literals(&compressed) >= LAST_LITERALS);
+    }
+
+    #[test]
+    fn mixed_text() {
+        let line = b"2026-10-18 INFO request served in 12ms\n";
+        let mut data = Vec::new();
+        for i in 0..500 {
+            data.extend_from_slice(line);
+            data.extend_from_slice(&noise(i % 7));
+        }
+        let compressed = round_trip(&data);
+        assert!(compressed.len() < data.len() / 4);
+        assert!(last_literals(&compressed) >= LAST_LITERALS);
+    }
+
+    #[test]
+    fn truncated_input() {
+        let data: Vec<u8> = b"hello world, hello world, hello world!".repeat(20);
+        let compressed = compress(&data);
+        for len in 0..compressed.len() {
+            assert_eq!(decompress(&compressed[..len], data.len()), None);
+        }
+    }
+
+    #[test]
+    fn wrong_length() {
+        let data = vec![7u8; 1000];
+        let compressed = compress(&data);
+        assert_eq!(decompress(&compressed, 999), None);
+        assert_eq!(decompress(&compressed, 1001), None);
+    }
+
+    #[test]
+    fn garbage_input() {
+        // 偏移为 0
+        assert_eq!(decompress(&[0x10, b'a', 0, 0], 5), None);
+        // 偏移超出已输出的数据
+        assert_eq!(decompress(&[0x10, b'a', 2, 0, 0x00], 5), None);
+        // 字面量长度超出输入
+        assert_eq!(decompress(&[0xf0, 10, b'a'], 25), None);
+        // 扩展长度没有结束
+        assert_eq!(decompress(&[0xf0, 255, 255], 1000), None);
+        for seed in 1..200usize {
+            let junk = noise(seed);
+            let _ = decompress(&junk, 4096);
+        }
+    }
+}
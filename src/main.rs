@@ -1,13 +1,56 @@
 mod device;
+mod du;
+mod error;
+mod find;
 mod fs;
+mod glob;
+mod grep;
+mod host;
+mod json;
 mod layout;
+mod lineedit;
+mod ls;
+mod lz;
+mod regex;
+mod script;
+mod shell;
+mod tar;
+mod tree;
+mod walk;
 
-use crate::fs::MyFileSystem;
-use std::io::{self, Write};
+use crate::error::FsError;
+use crate::fs::{Metadata, MyFileSystem, ReadFs, StatFs};
+use crate::host::BuildOptions;
+use crate::json::Json;
+use crate::layout::{BLOCK_SIZE, InodeType};
+use crate::lineedit::LineEditor;
+use crate::script::{Input, Shell, run_lines};
+use crate::tar::TarFs;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// 命令的输出先写入缓冲区，再由调用者决定显示在终端、重定向到镜像中的文件还是交给管道的下一条命令
+// 写入 Vec<u8> 不会失败
+macro_rules! outln {
+    ($out:expr) => {
+        $out.push(b'\n')
+    };
+    ($out:expr, $($arg:tt)*) => {
+        writeln!($out, $($arg)*).unwrap()
+    };
+}
+
+macro_rules! out {
+    ($out:expr, $($arg:tt)*) => {
+        write!($out, $($arg)*).unwrap()
+    };
+}
 
 // 在传入内层函数之前转绝对路径
 fn to_absolute_path(cwd: &str, input: &str) -> String {
-    let mut combined = if input.starts_with('/') {
+    let combined = if input.starts_with('/') {
         input.to_string()
     } else {
         let mut base = cwd.to_string();
@@ -37,92 +80,1193 @@ fn to_absolute_path(cwd: &str, input: &str) -> String {
     if result.is_empty() {
         result = "/".to_string();
     }
-    // print!("{}", result);
     result
 }
 
-fn main() {
-    let mut fs = fs::MyFileSystem::new("disk.img");
-    println!("MyFS CLI started.");
+// 把 "-x" 形式的选项和其余参数分开
+fn split_flags<'a>(args: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+    args.iter().partition(|a| a.starts_with('-') && a.len() > 1)
+}
+
+// 退出状态：0 成功，1 操作失败，2 用法错误，127 未知命令
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_UNKNOWN: i32 = 127;
+
+// 打印用法并返回用法错误的退出状态
+fn usage(text: &str) -> i32 {
+    if json::enabled() {
+        let command = text.split_whitespace().next().unwrap_or(text);
+        json::print_error(command, None, &format!("usage: {}", text));
+        return EXIT_USAGE;
+    }
+    eprintln!("Usage: {}", text);
+    EXIT_USAGE
+}
+
+// 打印 "命令: [路径: ]错误"；JSON 模式下输出 {"error":{...}} 对象
+fn report(cmd: &str, path: Option<&str>, msg: &str) {
+    if json::enabled() {
+        json::print_error(cmd, path, msg);
+    } else if let Some(path) = path {
+        eprintln!("{}: {}: {}", cmd, path, msg);
+    } else {
+        eprintln!("{}: {}", cmd, msg);
+    }
+}
+
+const NOT_FORMATTED: &str = "image is not formatted (run 'format' first)";
+
+// 镜像已经格式化时返回 true，否则报告错误；访问镜像的命令和内建命令都先经过这里
+fn check_formatted(fs: &mut MyFileSystem, cmd: &str) -> bool {
+    if fs.is_formatted() {
+        return true;
+    }
+    report(cmd, None, NOT_FORMATTED);
+    false
+}
+
+// 打印 "命令: 路径: 错误" 并返回失败的退出状态
+fn fail(cmd: &str, path: &str, e: FsError) -> i32 {
+    report(cmd, Some(path), &e.to_string());
+    EXIT_FAILURE
+}
+
+// 以 K/M/G 为单位显示字节数
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
 
-    loop {
-        print!("myfs:{}> ", fs.cwd_path);
-        io::stdout().flush().unwrap();
+fn print_df(out: &mut Vec<u8>, st: &StatFs, human: bool) {
+    let used_blocks = st.total_blocks - st.free_blocks;
+    let used_inodes = st.total_inodes - st.free_inodes;
+    let percent = |used: u32, total: u32| {
+        if total == 0 {
+            0
+        } else {
+            (used as u64 * 100).div_ceil(total as u64)
+        }
+    };
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let cmd_parts: Vec<&str> = input.trim().split_whitespace().collect();
-        if cmd_parts.is_empty() {
+    outln!(
+        out,
+        "{:<8}{:>10}{:>10}{:>10}{:>6}",
+        "",
+        "Total",
+        "Used",
+        "Free",
+        "Use%"
+    );
+    if human {
+        let bs = st.block_size as u64;
+        outln!(
+            out,
+            "{:<8}{:>10}{:>10}{:>10}{:>5}%",
+            "Blocks",
+            human_size(st.total_blocks as u64 * bs),
+            human_size(used_blocks as u64 * bs),
+            human_size(st.free_blocks as u64 * bs),
+            percent(used_blocks, st.total_blocks)
+        );
+    } else {
+        outln!(
+            out,
+            "{:<8}{:>10}{:>10}{:>10}{:>5}%",
+            "Blocks",
+            st.total_blocks,
+            used_blocks,
+            st.free_blocks,
+            percent(used_blocks, st.total_blocks)
+        );
+    }
+    outln!(
+        out,
+        "{:<8}{:>10}{:>10}{:>10}{:>5}%",
+        "Inodes",
+        st.total_inodes,
+        used_inodes,
+        st.free_inodes,
+        percent(used_inodes, st.total_inodes)
+    );
+}
+
+// 把 Unix 秒格式化为 UTC 时间 "YYYY-MM-DD HH:MM:SS"
+fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // 按公历从 1970-01-01 起的天数换算年月日
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// 类似 "drwxr-xr-x" 的类型与权限字符串
+fn mode_string(kind: InodeType, perm: u32) -> String {
+    let mut s = String::new();
+    s.push(match kind {
+        InodeType::Directory => 'd',
+        InodeType::File => '-',
+        InodeType::Symlink => 'l',
+        InodeType::Unused => '?',
+    });
+    for shift in [6, 3, 0] {
+        let bits = (perm >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+// link_target 为符号链接指向的路径
+fn print_stat(out: &mut Vec<u8>, path: &str, meta: &Metadata, link_target: Option<&str>) {
+    let kind = match meta.kind {
+        InodeType::Directory => "directory",
+        InodeType::File => "regular file",
+        InodeType::Symlink => "symbolic link",
+        InodeType::Unused => "unused",
+    };
+    let block_list: Vec<String> = meta.blocks.iter().map(|b| b.to_string()).collect();
+
+    match link_target {
+        Some(target) => outln!(out, "  File: {} -> {}", path, target),
+        None => outln!(out, "  File: {}", path),
+    }
+    outln!(out, "  Type: {}", kind);
+    outln!(out, " Inode: {}", meta.ino);
+    outln!(out, "  Size: {}", meta.size);
+    if meta.compressed {
+        match meta.kind {
+            InodeType::File => outln!(
+                out,
+                "Compressed: {} bytes on disk, {} logical",
+                meta.allocated_blocks() * BLOCK_SIZE,
+                meta.size
+            ),
+            _ => outln!(out, "Compressed: inherited by new entries"),
+        }
+    }
+    outln!(
+        out,
+        "Blocks: {} [{}]",
+        meta.allocated_blocks(),
+        block_list.join(", ")
+    );
+    if meta.indirect != 0 {
+        outln!(out, "Indirect: {}", meta.indirect);
+    }
+    outln!(out, " Links: {}", meta.nlinks);
+    outln!(out, "   Uid: {:<8}Gid: {}", meta.uid, meta.gid);
+    outln!(
+        out,
+        "Access: ({:04o}/{})",
+        meta.perm,
+        mode_string(meta.kind, meta.perm)
+    );
+    outln!(out, "Access: {}", format_time(meta.atime));
+    outln!(out, "Modify: {}", format_time(meta.mtime));
+    outln!(out, "Change: {}", format_time(meta.ctime));
+}
+
+// 解析形如 "48656c6c6f" 的十六进制串
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// 与 hexdump -C 相同的格式：偏移、16 个字节的十六进制、可打印字符，连续重复的行折叠为 "*"
+fn print_hexdump(out: &mut Vec<u8>, data: &[u8]) {
+    let mut prev: Option<&[u8]> = None;
+    let mut folded = false;
+    for (line, chunk) in data.chunks(16).enumerate() {
+        if prev == Some(chunk) && chunk.len() == 16 {
+            if !folded {
+                outln!(out, "*");
+                folded = true;
+            }
             continue;
         }
+        prev = Some(chunk);
+        folded = false;
 
-        match cmd_parts[0] {
-            "format" => fs.format(),
-            "cd" => {
-                let raw_path = if cmd_parts.len() > 1 {
-                    cmd_parts[1]
+        let mut hex = String::new();
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(b) => hex.push_str(&format!("{:02x} ", b)),
+                None => hex.push_str("   "),
+            }
+            if i == 7 {
+                hex.push(' ');
+            }
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
                 } else {
-                    "/"
+                    '.'
+                }
+            })
+            .collect();
+        outln!(out, "{:08x}  {} |{}|", line * 16, hex, ascii);
+    }
+    outln!(out, "{:08x}", data.len());
+}
+
+// write [-x] [-a | --offset N] <path> <content>
+// -x 按十六进制解析内容，-a 追加到文件末尾，--offset 从指定位置覆盖写入
+fn cmd_write(fs: &mut MyFileSystem, args: &[&str], input: Option<&[u8]>, out: &mut Vec<u8>) -> i32 {
+    let mut hex = false;
+    let mut append = false;
+    let mut offset = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "-x" => hex = true,
+            "-a" => append = true,
+            "--offset" => {
+                i += 1;
+                match args.get(i).and_then(|v| v.parse::<usize>().ok()) {
+                    Some(n) => offset = Some(n),
+                    None => {
+                        report("write", None, "--offset requires a number");
+                        return EXIT_USAGE;
+                    }
+                }
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    // 没有给出内容时从管道或 heredoc 读取
+    let content = match (args.len().cmp(&(i + 1)), input) {
+        (std::cmp::Ordering::Greater, _) => args[i + 1..].join(" ").into_bytes(),
+        (std::cmp::Ordering::Equal, Some(data)) => data.to_vec(),
+        _ => return usage("write [-x] [-a | --offset N] <path> [content]"),
+    };
+
+    let abs_path = to_absolute_path(&fs.cwd_path, args[i]);
+    let data = if hex {
+        let text: String = String::from_utf8_lossy(&content)
+            .split_whitespace()
+            .collect();
+        match parse_hex(&text) {
+            Some(data) => data,
+            None => {
+                report("write", None, "invalid hex data");
+                return EXIT_USAGE;
+            }
+        }
+    } else {
+        content
+    };
+
+    let result = if append {
+        fs.append(&abs_path, &data)
+    } else if let Some(offset) = offset {
+        fs.pwrite(&abs_path, offset, &data)
+    } else {
+        fs.write_bytes(&abs_path, &data)
+    };
+    match result {
+        Ok(n) => {
+            outln!(out, "Wrote {} bytes to '{}'.", n, abs_path);
+            0
+        }
+        Err(e) => fail("write", &abs_path, e),
+    }
+}
+
+// 找到路径对应的普通文件，返回 (Inode 编号, 文件大小)
+fn open_for_read(fs: &mut MyFileSystem, cmd: &str, abs_path: &str) -> Option<(u32, usize)> {
+    match fs.stat(abs_path) {
+        Ok(meta) if meta.kind == InodeType::Directory => {
+            fail(cmd, abs_path, FsError::IsADirectory);
+            None
+        }
+        Ok(meta) => Some((meta.ino, meta.size as usize)),
+        Err(e) => {
+            fail(cmd, abs_path, e);
+            None
+        }
+    }
+}
+
+// cat --range START:END <path>，END 省略时读到文件末尾
+fn cmd_cat_range(fs: &mut MyFileSystem, range: &str, raw_path: &str, out: &mut Vec<u8>) -> i32 {
+    let (start, end) = match range.split_once(':') {
+        Some((a, "")) => (a.parse::<usize>().ok(), Some(usize::MAX)),
+        Some((a, b)) => (a.parse::<usize>().ok(), b.parse::<usize>().ok()),
+        None => (None, None),
+    };
+    let (Some(start), Some(end)) = (start, end) else {
+        report(
+            "cat",
+            None,
+            &format!("invalid range '{}', expected START:END", range),
+        );
+        return EXIT_USAGE;
+    };
+
+    let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+    let Some((ino, _)) = open_for_read(fs, "cat", &abs_path) else {
+        return EXIT_FAILURE;
+    };
+    match fs.read_at(ino, start, end.saturating_sub(start)) {
+        Ok(data) => {
+            out.extend_from_slice(&data);
+            0
+        }
+        Err(e) => fail("cat", &abs_path, e),
+    }
+}
+
+// head/tail [-n LINES | -c BYTES] <path>，默认 10 行；按块读取，只读需要的部分
+fn cmd_head_tail(
+    fs: &mut MyFileSystem,
+    args: &[&str],
+    tail: bool,
+    input: Option<&[u8]>,
+    out: &mut Vec<u8>,
+) -> i32 {
+    let cmd = if tail { "tail" } else { "head" };
+    let (count, by_bytes, raw_path) = match args {
+        [flag @ ("-n" | "-c"), n, path] => match n.parse::<usize>() {
+            Ok(n) => (n, *flag == "-c", *path),
+            Err(_) => {
+                report(cmd, None, &format!("invalid count '{}'", n));
+                return EXIT_USAGE;
+            }
+        },
+        [path] => (10, false, *path),
+        // 没有给出路径时处理管道或 heredoc 的输入
+        [flag @ ("-n" | "-c"), n] if input.is_some() => match n.parse::<usize>() {
+            Ok(n) => (n, *flag == "-c", ""),
+            Err(_) => {
+                report(cmd, None, &format!("invalid count '{}'", n));
+                return EXIT_USAGE;
+            }
+        },
+        [] if input.is_some() => (10, false, ""),
+        _ => return usage(&format!("{} [-n LINES | -c BYTES] [path]", cmd)),
+    };
+
+    if let (Some(data), "") = (input, raw_path) {
+        out.extend_from_slice(head_tail_bytes(data, count, by_bytes, tail));
+        return 0;
+    }
+
+    let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+    let Some((ino, size)) = open_for_read(fs, cmd, &abs_path) else {
+        return EXIT_FAILURE;
+    };
+
+    let result = match (tail, by_bytes) {
+        (false, true) => fs.read_at(ino, 0, count),
+        (true, true) => fs.read_at(ino, size.saturating_sub(count), count),
+        (false, false) => head_lines(fs, ino, size, count),
+        (true, false) => tail_lines(fs, ino, size, count),
+    };
+    match result {
+        Ok(data) => {
+            out.extend_from_slice(&data);
+            0
+        }
+        Err(e) => fail(cmd, &abs_path, e),
+    }
+}
+
+// 对内存中的数据取开头或末尾的若干行（或字节）
+fn head_tail_bytes(data: &[u8], count: usize, by_bytes: bool, tail: bool) -> &[u8] {
+    if by_bytes {
+        let n = count.min(data.len());
+        return if tail {
+            &data[data.len() - n..]
+        } else {
+            &data[..n]
+        };
+    }
+    let mut ends = data
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'\n')
+        .map(|(i, _)| i + 1);
+    if !tail {
+        return match ends.nth(count.saturating_sub(1)) {
+            Some(end) if count > 0 => &data[..end],
+            _ if count == 0 => &[],
+            _ => data,
+        };
+    }
+    // 末尾的换行不算作一个新行的开始
+    let body = data.strip_suffix(b"\n").unwrap_or(data);
+    let starts: Vec<usize> = body
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'\n')
+        .map(|(i, _)| i + 1)
+        .collect();
+    match count {
+        0 => &[],
+        n if n > starts.len() => data,
+        n => &data[starts[starts.len() - n]..],
+    }
+}
+
+// 从文件开头逐块读取，直到凑够 lines 行
+fn head_lines(
+    fs: &mut MyFileSystem,
+    ino: u32,
+    size: usize,
+    lines: usize,
+) -> Result<Vec<u8>, FsError> {
+    let mut out = Vec::new();
+    let mut seen = 0;
+    let mut pos = 0;
+    while pos < size && seen < lines {
+        let chunk = fs.read_at(ino, pos, BLOCK_SIZE)?;
+        pos += chunk.len();
+        for (i, &b) in chunk.iter().enumerate() {
+            if b == b'\n' {
+                seen += 1;
+                if seen == lines {
+                    out.extend_from_slice(&chunk[..=i]);
+                    return Ok(out);
+                }
+            }
+        }
+        out.extend_from_slice(&chunk);
+    }
+    Ok(out)
+}
+
+// 从文件末尾向前逐块读取，直到找到倒数 lines 行的起点
+fn tail_lines(
+    fs: &mut MyFileSystem,
+    ino: u32,
+    size: usize,
+    lines: usize,
+) -> Result<Vec<u8>, FsError> {
+    if lines == 0 {
+        return Ok(Vec::new());
+    }
+    let mut acc: Vec<u8> = Vec::new();
+    let mut pos = size;
+    let mut seen = 0;
+    while pos > 0 {
+        let start = (pos - 1) / BLOCK_SIZE * BLOCK_SIZE;
+        let chunk = fs.read_at(ino, start, pos - start)?;
+        // 文件末尾的换行不算作新的一行
+        let scan_end = if pos == size && chunk.ends_with(b"\n") {
+            chunk.len() - 1
+        } else {
+            chunk.len()
+        };
+        for i in (0..scan_end).rev() {
+            if chunk[i] == b'\n' {
+                seen += 1;
+                if seen == lines {
+                    let mut out = chunk[i + 1..].to_vec();
+                    out.extend_from_slice(&acc);
+                    return Ok(out);
+                }
+            }
+        }
+        let mut joined = chunk;
+        joined.extend_from_slice(&acc);
+        acc = joined;
+        pos = start;
+    }
+    Ok(acc)
+}
+
+// 读取 SOURCE_DATE_EPOCH 环境变量
+fn source_date_epoch() -> Option<u64> {
+    std::env::var("SOURCE_DATE_EPOCH").ok()?.parse().ok()
+}
+
+// 标准的 8-4-4-4-12 UUID 格式
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+// myfs mkfs [-b BLOCKS] [-N INODES] [--source-date-epoch SECS] [--uuid-seed SEED] <image> <host_dir>
+// 非交互模式：新建镜像并用宿主机目录树填充，成功返回 0
+// 未指定 --source-date-epoch 时使用 SOURCE_DATE_EPOCH 环境变量，未指定 --uuid-seed 时以该时间作为种子
+fn run_mkfs(args: &[String]) -> i32 {
+    let mut opts = BuildOptions {
+        source_date_epoch: source_date_epoch(),
+        ..Default::default()
+    };
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-b" | "-N" | "--source-date-epoch" => {
+                let Some(n) = iter.next().and_then(|v| v.parse::<u64>().ok()) else {
+                    report("mkfs", None, &format!("{} requires a number", arg));
+                    return EXIT_USAGE;
                 };
-                let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
-                fs.cd(&abs_path);
+                match arg.as_str() {
+                    "-b" => opts.blocks = Some(n as u32),
+                    "-N" => opts.inodes = Some(n as u32),
+                    _ => opts.source_date_epoch = Some(n),
+                }
+            }
+            "--uuid-seed" => {
+                let Some(seed) = iter.next() else {
+                    report("mkfs", None, "--uuid-seed requires a value");
+                    return EXIT_USAGE;
+                };
+                opts.uuid_seed = Some(seed.clone());
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [image, host_dir] = positional[..] else {
+        return usage(
+            "mkfs [-b BLOCKS] [-N INODES] [--source-date-epoch SECS] [--uuid-seed SEED] <image> <host_dir>",
+        );
+    };
+    if opts.uuid_seed.is_none() {
+        opts.uuid_seed = opts.source_date_epoch.map(|t| t.to_string());
+    }
+
+    match host::build_image(image, Path::new(host_dir), &opts) {
+        Ok(st) => {
+            println!(
+                "Built '{}' from '{}': {} blocks ({} free), {} inodes ({} free), UUID {}.",
+                image,
+                host_dir,
+                st.total_blocks,
+                st.free_blocks,
+                st.total_inodes,
+                st.free_inodes,
+                format_uuid(&st.uuid)
+            );
+            0
+        }
+        Err(e) => {
+            report("mkfs", Some(host_dir), &e.to_string());
+            EXIT_FAILURE
+        }
+    }
+}
+
+// 在只读视图上执行 ls / cat / stat
+fn cmd_view(
+    view: &mut impl ReadFs,
+    cmd: &str,
+    path: &str,
+    out: &mut Vec<u8>,
+) -> Result<(), FsError> {
+    match cmd {
+        "ls" => {
+            let meta = view.stat(path)?;
+            if meta.kind != InodeType::Directory {
+                outln!(out, "{}", MyFileSystem::split_path(path).1);
+                return Ok(());
+            }
+            for entry in view.read_dir(path)? {
+                let child = MyFileSystem::join_path(path, &entry.name);
+                match view.lstat(&child)?.kind {
+                    InodeType::Directory => out!(out, "{}/  ", entry.name),
+                    InodeType::Symlink => out!(out, "{}@  ", entry.name),
+                    _ => out!(out, "{}  ", entry.name),
+                }
+            }
+            outln!(out);
+        }
+        "cat" => {
+            let meta = view.stat(path)?;
+            if meta.kind == InodeType::Directory {
+                return Err(FsError::IsADirectory);
+            }
+            let mut offset = 0;
+            while offset < meta.size as usize {
+                let chunk = view.read_at(meta.ino, offset, BLOCK_SIZE)?;
+                if chunk.is_empty() {
+                    break;
+                }
+                out.extend_from_slice(&chunk);
+                offset += chunk.len();
+            }
+        }
+        "stat" => {
+            let meta = view.lstat(path)?;
+            let target = match meta.kind {
+                InodeType::Symlink => Some(view.read_link(path)?),
+                _ => None,
+            };
+            print_stat(out, path, &meta, target.as_deref());
+        }
+        _ => return Err(FsError::InvalidArgument),
+    }
+    Ok(())
+}
+
+fn is_dir(fs: &mut MyFileSystem, abs_path: &str) -> bool {
+    fs.stat(abs_path)
+        .is_ok_and(|m| m.kind == InodeType::Directory)
+}
+
+// 执行一条命令，返回退出状态
+fn run_command(
+    fs: &mut MyFileSystem,
+    cmd_parts: &[&str],
+    input: Option<&[u8]>,
+    out: &mut Vec<u8>,
+) -> i32 {
+    // 除了 format 和不访问镜像的命令，其余命令都要求镜像已经格式化
+    let needs_image = !matches!(cmd_parts[0], "format" | "tar-view");
+    if needs_image && !check_formatted(fs, cmd_parts[0]) {
+        return EXIT_FAILURE;
+    }
+
+    match cmd_parts[0] {
+        "format" => {
+            outln!(out, "Formatting disk...");
+            fs.format();
+            outln!(out, "Done.");
+        }
+        "cd" => {
+            let raw_path = if cmd_parts.len() > 1 {
+                cmd_parts[1]
+            } else {
+                "/"
+            };
+            let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+            if let Err(e) = fs.change_dir(&abs_path) {
+                return fail("cd", &abs_path, e);
+            }
+        }
+        "ls" => return ls::run_ls(fs, &cmd_parts[1..], out),
+        "mkdir" => {
+            let (flags, args) = split_flags(&cmd_parts[1..]);
+            if args.is_empty() {
+                return usage("mkdir [-p] <path>...");
             }
-            "ls" => {
-                let raw_path = if cmd_parts.len() > 1 {
-                    cmd_parts[1]
+            let parents = flags.contains(&"-p");
+            let mut status = 0;
+            for raw_path in args {
+                let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+                let result = if parents {
+                    fs.create_dir_all(&abs_path)
                 } else {
-                    "."
+                    fs.create_dir(&abs_path).map(|_| ())
                 };
+                match result {
+                    Ok(()) => outln!(
+                        out,
+                        "Directory '{}' created.",
+                        MyFileSystem::split_path(&abs_path).1
+                    ),
+                    Err(e) => status = fail("mkdir", &abs_path, e),
+                }
+            }
+            return status;
+        }
+        "touch" => {
+            if cmd_parts.len() < 2 {
+                return usage("touch <path>...");
+            }
+            let mut status = 0;
+            for raw_path in &cmd_parts[1..] {
                 let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
-                fs.list_dir(&abs_path);
+                match fs.touch(&abs_path) {
+                    Ok(true) => outln!(
+                        out,
+                        "File '{}' created.",
+                        MyFileSystem::split_path(&abs_path).1
+                    ),
+                    Ok(false) => {}
+                    Err(e) => status = fail("touch", &abs_path, e),
+                }
+            }
+            return status;
+        }
+        "write" => return cmd_write(fs, &cmd_parts[1..], input, out),
+        "truncate" => {
+            if cmd_parts.len() < 3 {
+                return usage("truncate <path> <size>");
+            }
+            let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
+            let Ok(size) = cmd_parts[2].parse::<usize>() else {
+                report(
+                    "truncate",
+                    None,
+                    &format!("invalid size '{}'", cmd_parts[2]),
+                );
+                return EXIT_USAGE;
+            };
+            if let Err(e) = fs.set_len(&abs_path, size) {
+                return fail("truncate", &abs_path, e);
             }
-            "mkdir" => {
-                if cmd_parts.len() > 1 {
-                    let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    fs.mkdir(&abs_path);
+        }
+        "chattr" => {
+            // chattr +c|-c <path>... 设置或清除压缩标志
+            let on = match cmd_parts.get(1) {
+                Some(&"+c") => true,
+                Some(&"-c") => false,
+                _ => return usage("chattr +c|-c <path>..."),
+            };
+            if cmd_parts.len() < 3 {
+                return usage("chattr +c|-c <path>...");
+            }
+            let mut status = 0;
+            for raw_path in &cmd_parts[2..] {
+                let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+                if let Err(e) = fs.set_compressed(&abs_path, on) {
+                    status = fail("chattr", &abs_path, e);
                 }
             }
-            "touch" => {
-                if cmd_parts.len() > 1 {
-                    let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    fs.touch(&abs_path);
+            return status;
+        }
+        "hexdump" => {
+            let data = match (cmd_parts.get(1), input) {
+                (Some(raw_path), _) => {
+                    let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+                    match fs.read_bytes(&abs_path) {
+                        Ok(data) => data,
+                        Err(e) => return fail("hexdump", &abs_path, e),
+                    }
+                }
+                (None, Some(data)) => data.to_vec(),
+                (None, None) => return usage("hexdump [path]"),
+            };
+            print_hexdump(out, &data);
+        }
+        "cat" => {
+            if cmd_parts.len() > 3 && cmd_parts[1] == "--range" {
+                return cmd_cat_range(fs, cmd_parts[2], cmd_parts[3], out);
+            }
+            // 没有参数时原样输出管道或 heredoc 的输入
+            if cmd_parts.len() < 2 {
+                match input {
+                    Some(data) => out.extend_from_slice(data),
+                    None => return usage("cat [--range START:END] <path>..."),
                 }
             }
-            "write" => {
-                if cmd_parts.len() > 2 {
-                    let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    let content = cmd_parts[2..].join(" ");
-                    fs.write_file(&abs_path, &content);
+            let mut status = 0;
+            for raw_path in &cmd_parts[1..] {
+                let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+                match fs.read_bytes(&abs_path) {
+                    Ok(data) => out.extend_from_slice(&data),
+                    Err(e) => status = fail("cat", &abs_path, e),
                 }
             }
-            "cat" => {
-                if cmd_parts.len() > 1 {
-                    let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    fs.cat(&abs_path);
+            return status;
+        }
+        "grep" => return grep::run_grep(fs, &cmd_parts[1..], input, out),
+        "head" => return cmd_head_tail(fs, &cmd_parts[1..], false, input, out),
+        "tail" => return cmd_head_tail(fs, &cmd_parts[1..], true, input, out),
+        "rm" => {
+            let (flags, args) = split_flags(&cmd_parts[1..]);
+            if args.is_empty() {
+                return usage("rm [-r] <path>...");
+            }
+            let recursive = flags.iter().any(|f| matches!(*f, "-r" | "-R" | "-rf"));
+            let mut status = 0;
+            for raw_path in args {
+                let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+                match fs.remove(&abs_path, recursive) {
+                    Ok(()) => outln!(out, "Removed '{}'", abs_path),
+                    Err(e) => status = fail("rm", &abs_path, e),
                 }
             }
-            "rm" => {
-                if cmd_parts.len() > 1 {
-                    let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    fs.rm(&abs_path);
+            return status;
+        }
+        "cp" => {
+            let (flags, args) = split_flags(&cmd_parts[1..]);
+            if args.len() < 2 {
+                return usage("cp [-r] [--reflink] <src>... <dst>");
+            }
+            let recursive = flags.iter().any(|f| matches!(*f, "-r" | "-R"));
+            let reflink = flags.contains(&"--reflink");
+            let (dst, srcs) = args.split_last().unwrap();
+            let dst_abs = to_absolute_path(&fs.cwd_path, dst);
+            if srcs.len() > 1 && !is_dir(fs, &dst_abs) {
+                return fail("cp", &dst_abs, FsError::NotADirectory);
+            }
+            let mut status = 0;
+            for src in srcs {
+                let src_abs = to_absolute_path(&fs.cwd_path, src);
+                if let Err(e) = fs.copy(&src_abs, &dst_abs, recursive, reflink) {
+                    status = fail("cp", &format!("{} -> {}", src_abs, dst_abs), e);
+                }
+            }
+            return status;
+        }
+        "mv" => {
+            if cmd_parts.len() < 3 {
+                return usage("mv <src>... <dst>");
+            }
+            let (dst, srcs) = cmd_parts[1..].split_last().unwrap();
+            let dst_abs = to_absolute_path(&fs.cwd_path, dst);
+            if srcs.len() > 1 && !is_dir(fs, &dst_abs) {
+                return fail("mv", &dst_abs, FsError::NotADirectory);
+            }
+            let mut status = 0;
+            for src in srcs {
+                let src_abs = to_absolute_path(&fs.cwd_path, src);
+                // 规范化会吃掉末尾的 . 和 ..，按原样检查：与 rename(2) 一样不能移动它们
+                if matches!(
+                    src.trim_end_matches('/').rsplit('/').next(),
+                    Some("." | "..")
+                ) {
+                    status = fail("mv", src, FsError::InvalidArgument);
+                    continue;
                 }
+                match fs.mv(&src_abs, &dst_abs) {
+                    Ok(dst) => outln!(out, "Moved '{}' to '{}'", src_abs, dst),
+                    Err(e) => status = fail("mv", &format!("{} -> {}", src_abs, dst_abs), e),
+                }
+            }
+            return status;
+        }
+        "du" => return du::run_du(fs, &cmd_parts[1..], out),
+        "tree" => return tree::run_tree(fs, &cmd_parts[1..], out),
+        "df" => {
+            let human = cmd_parts[1..].contains(&"-h");
+            let st = fs.statfs();
+            if json::enabled() {
+                outln!(out, "{}", Json::from(&st));
+            } else {
+                print_df(out, &st, human);
+            }
+        }
+        "dedup" => match &cmd_parts[1..] {
+            [] => match fs.dedup() {
+                Ok(report) => outln!(
+                    out,
+                    "dedup: scanned {} blocks, merged {}, reclaimed {} blocks ({})",
+                    report.scanned,
+                    report.merged,
+                    report.reclaimed,
+                    human_size(report.reclaimed as u64 * BLOCK_SIZE as u64)
+                ),
+                Err(e) => return fail("dedup", "/", e),
+            },
+            ["--inline"] => {
+                let state = if fs.inline_dedup() { "on" } else { "off" };
+                outln!(out, "inline dedup: {}", state);
+            }
+            ["--inline", state @ ("on" | "off")] => {
+                fs.set_inline_dedup(*state == "on");
+                outln!(out, "inline dedup: {}", state);
             }
-            "cp" => {
-                if cmd_parts.len() > 2 {
-                    let src_abs = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    let dst_abs = to_absolute_path(&fs.cwd_path, cmd_parts[2]);
-                    fs.cp(&src_abs, &dst_abs);
+            _ => return usage("dedup [--inline [on|off]]"),
+        },
+        "snapshot" => {
+            const SNAPSHOT_USAGE: &str = "snapshot create|delete|rollback <name> | snapshot list";
+            let result = match (cmd_parts.get(1).copied(), cmd_parts.get(2).copied()) {
+                (Some("list"), None) => {
+                    for info in fs.snapshot_list() {
+                        outln!(out, "{}\t{}", info.name, format_time(info.created));
+                    }
+                    return 0;
+                }
+                (Some("create"), Some(name)) if cmd_parts.len() == 3 => {
+                    fs.snapshot_create(name).map(|_| "Created snapshot")
+                }
+                (Some("delete"), Some(name)) if cmd_parts.len() == 3 => {
+                    fs.snapshot_delete(name).map(|_| "Deleted snapshot")
                 }
+                (Some("rollback"), Some(name)) if cmd_parts.len() == 3 => fs
+                    .snapshot_rollback(name)
+                    .map(|_| "Rolled back to snapshot"),
+                _ => return usage(SNAPSHOT_USAGE),
+            };
+            match result {
+                Ok(msg) => outln!(out, "{} '{}'", msg, cmd_parts[2]),
+                Err(e) => return fail("snapshot", cmd_parts[2], e),
             }
-            "mv" => {
-                if cmd_parts.len() > 2 {
-                    let src_abs = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
-                    let dst_abs = to_absolute_path(&fs.cwd_path, cmd_parts[2]);
-                    fs.mv(&src_abs, &dst_abs);
+        }
+        "statfs" => {
+            let st = fs.statfs();
+            outln!(out, "block_size:   {}", st.block_size);
+            outln!(out, "total_blocks: {}", st.total_blocks);
+            outln!(out, "free_blocks:  {}", st.free_blocks);
+            outln!(out, "total_inodes: {}", st.total_inodes);
+            outln!(out, "free_inodes:  {}", st.free_inodes);
+            outln!(out, "uuid:         {}", format_uuid(&st.uuid));
+        }
+        "fsck" => {
+            let report = fs.fsck();
+            if json::enabled() {
+                outln!(out, "{}", Json::from(&report));
+                return if report.is_clean() { 0 } else { EXIT_FAILURE };
+            }
+            if report.is_clean() {
+                outln!(out, "fsck: clean");
+            } else {
+                // 计数不一致时已经修正，但仍以非零状态报告
+                outln!(
+                    out,
+                    "fsck: free blocks {} -> {}, free inodes {} -> {} (fixed)",
+                    report.recorded_free_blocks,
+                    report.actual_free_blocks,
+                    report.recorded_free_inodes,
+                    report.actual_free_inodes
+                );
+                return EXIT_FAILURE;
+            }
+        }
+        "stat" => {
+            // 默认不跟随链接（lstat），-L 跟随（stat）
+            let follow = cmd_parts[1..].contains(&"-L");
+            let paths: Vec<&str> = cmd_parts[1..]
+                .iter()
+                .copied()
+                .filter(|a| *a != "-L")
+                .collect();
+            if paths.is_empty() {
+                return usage("stat [-L] <path>...");
+            }
+            let mut status = 0;
+            let mut results = Vec::new();
+            for raw_path in paths {
+                let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+                let result = if follow {
+                    fs.stat(&abs_path)
+                } else {
+                    fs.lstat(&abs_path)
+                };
+                match result {
+                    Ok(meta) => {
+                        let target = match meta.kind {
+                            InodeType::Symlink => fs.read_link(&abs_path).ok(),
+                            _ => None,
+                        };
+                        if json::enabled() {
+                            let mut value = Json::from(&meta);
+                            value.push("path", abs_path.into());
+                            value.push("target", target.into());
+                            results.push(value);
+                        } else {
+                            print_stat(out, &abs_path, &meta, target.as_deref());
+                        }
+                    }
+                    Err(e) => status = fail("stat", &abs_path, e),
                 }
             }
-            "exit" => break,
-            _ => println!("Unknown command"),
+            if json::enabled() {
+                outln!(out, "{}", Json::Array(results));
+            }
+            return status;
+        }
+        "ln" => {
+            // ln <target> <link> 创建硬链接，ln -s <target> <link> 创建符号链接
+            let (flags, rest) = split_flags(&cmd_parts[1..]);
+            if rest.len() != 2 {
+                return usage("ln [-s] <target> <link>");
+            }
+            let link_abs = to_absolute_path(&fs.cwd_path, rest[1]);
+            let result = if flags.contains(&"-s") {
+                fs.symlink(rest[0], &link_abs).map(|_| ())
+            } else {
+                let target_abs = to_absolute_path(&fs.cwd_path, rest[0]);
+                fs.link(&target_abs, &link_abs)
+            };
+            if let Err(e) = result {
+                return fail("ln", &link_abs, e);
+            }
+        }
+        "readlink" => {
+            if cmd_parts.len() < 2 {
+                return usage("readlink <path>");
+            }
+            let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
+            match fs.read_link(&abs_path) {
+                Ok(target) => outln!(out, "{}", target),
+                Err(e) => return fail("readlink", &abs_path, e),
+            }
+        }
+        "import" => {
+            if cmd_parts.len() < 3 {
+                return usage("import <host_path> <path>");
+            }
+            let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[2]);
+            match host::import(fs, Path::new(cmd_parts[1]), &abs_path) {
+                Ok(n) => outln!(out, "Imported {} entries into '{}'.", n, abs_path),
+                Err(e) => return fail("import", cmd_parts[1], e),
+            }
+        }
+        "export" => {
+            if cmd_parts.len() < 3 {
+                return usage("export <path> <host_path>");
+            }
+            let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
+            match host::export(fs, &abs_path, Path::new(cmd_parts[2])) {
+                Ok(n) => outln!(out, "Exported {} entries to '{}'.", n, cmd_parts[2]),
+                Err(e) => return fail("export", &abs_path, e),
+            }
+        }
+        "tar-import" => {
+            if cmd_parts.len() < 3 {
+                return usage("tar-import <tar> <path>");
+            }
+            let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[2]);
+            match tar::tar_import(fs, Path::new(cmd_parts[1]), &abs_path) {
+                Ok(n) => outln!(out, "Imported {} entries into '{}'.", n, abs_path),
+                Err(e) => return fail("tar-import", cmd_parts[1], e),
+            }
+        }
+        "tar-export" => {
+            if cmd_parts.len() < 3 {
+                return usage("tar-export <path> <tar>");
+            }
+            let abs_path = to_absolute_path(&fs.cwd_path, cmd_parts[1]);
+            match tar::tar_export(fs, &abs_path, Path::new(cmd_parts[2])) {
+                Ok(n) => outln!(out, "Exported {} entries to '{}'.", n, cmd_parts[2]),
+                Err(e) => return fail("tar-export", &abs_path, e),
+            }
+        }
+        "tar-view" => {
+            // tar-view <tar> <ls|cat|stat> [path]：不解包直接浏览归档，路径以归档根为 "/"
+            if cmd_parts.len() < 3 || !matches!(cmd_parts[2], "ls" | "cat" | "stat") {
+                return usage("tar-view <tar> <ls|cat|stat> [path]");
+            }
+            let path = to_absolute_path("/", cmd_parts.get(3).copied().unwrap_or("/"));
+            let result = TarFs::open(Path::new(cmd_parts[1]))
+                .and_then(|mut view| cmd_view(&mut view, cmd_parts[2], &path, out));
+            if let Err(e) = result {
+                return fail("tar-view", cmd_parts[1], e);
+            }
         }
+        _ => {
+            report(cmd_parts[0], None, "command not found");
+            return EXIT_UNKNOWN;
+        }
+    }
+    0
+}
+
+// 历史记录文件：MYFS_HISTORY 环境变量，默认为 ~/.myfs_history
+fn history_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("MYFS_HISTORY") {
+        return Some(PathBuf::from(path));
     }
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".myfs_history"))
+}
+
+const MAIN_USAGE: &str = "myfs [--image PATH] [--json] [--script FILE [args...] | <command> [args...]]\n       myfs mkfs [-b BLOCKS] [-N INODES] [--source-date-epoch SECS] [--uuid-seed SEED] <image> <host_dir>";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("mkfs") {
+        process::exit(run_mkfs(&args[2..]));
+    }
+
+    // 解析全局选项，第一个非选项参数开始是要执行的单条命令或脚本的参数
+    let mut image = "disk.img".to_string();
+    let mut script = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--image" | "--script" if i + 1 >= args.len() => {
+                report("myfs", None, &format!("{} requires a value", args[i]));
+                process::exit(usage(MAIN_USAGE));
+            }
+            "--image" => image = args[i + 1].clone(),
+            "--script" => script = Some(args[i + 1].clone()),
+            "--json" => {
+                json::set_enabled(true);
+                i += 1;
+                continue;
+            }
+            "-h" | "--help" => {
+                println!("Usage: {}", MAIN_USAGE);
+                return;
+            }
+            _ => break,
+        }
+        i += 2;
+    }
+    let command = &args[i..];
+
+    let mut fs = match MyFileSystem::new(&image) {
+        Ok(fs) => fs,
+        Err(e) => {
+            report("myfs", Some(&image), &e.to_string());
+            process::exit(EXIT_FAILURE);
+        }
+    };
+    fs.set_fixed_time(source_date_epoch());
+
+    let status = if let Some(script) = script {
+        // 脚本之后的参数是脚本的位置参数 $1 $2 ...
+        let mut shell = Shell::new(&script, command.to_vec(), true);
+        match File::open(&script) {
+            Ok(file) => run_lines(
+                &mut fs,
+                &mut shell,
+                &mut Input::Script(&mut BufReader::new(file)),
+            ),
+            Err(e) => {
+                report("myfs", Some(&script), &e.to_string());
+                EXIT_FAILURE
+            }
+        }
+    } else if !command.is_empty() {
+        let mut shell = Shell::new("myfs", Vec::new(), false);
+        let status = shell.run_args(&mut fs, command, &mut None);
+        shell.finish_line();
+        status
+    } else if io::stdin().is_terminal() {
+        println!("MyFS CLI started.");
+        run_lines(
+            &mut fs,
+            &mut Shell::new("myfs", Vec::new(), false),
+            &mut Input::Terminal(LineEditor::new(history_path())),
+        )
+    } else {
+        run_lines(
+            &mut fs,
+            &mut Shell::new("myfs", Vec::new(), true),
+            &mut Input::Script(&mut io::stdin().lock()),
+        )
+    };
+    process::exit(status);
 }
@@ -0,0 +1,570 @@
+// 自带的正则表达式引擎，语法与 POSIX 扩展正则表达式（grep -E）相同：
+// . [...] [^...] [[:alpha:]] ^ $ * + ? {m,n} | ( )，以及 \d \w \s \D \W \S \b \B
+// 编译成指令序列后用 Pike VM 模拟 NFA，匹配时间与文本长度成线性，不会因回溯退化
+
+// 重复次数的上限，防止 {m,n} 展开后的程序过大
+const MAX_REPEAT: u32 = 1000;
+// 编译后指令数的上限：嵌套的 {m,n} 会按乘积展开，如 ((a{1000}){1000}){1000}
+const MAX_PROGRAM: usize = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+enum ClassItem {
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+    Alpha,
+    Alnum,
+    Upper,
+    Lower,
+    Punct,
+    Xdigit,
+}
+
+impl ClassItem {
+    fn contains(self, c: char) -> bool {
+        match self {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Word => c.is_alphanumeric() || c == '_',
+            ClassItem::Space => c.is_whitespace(),
+            ClassItem::Alpha => c.is_alphabetic(),
+            ClassItem::Alnum => c.is_alphanumeric(),
+            ClassItem::Upper => c.is_uppercase(),
+            ClassItem::Lower => c.is_lowercase(),
+            ClassItem::Punct => c.is_ascii_punctuation(),
+            ClassItem::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let hit = |c: char| self.items.iter().any(|item| item.contains(c));
+        let found =
+            hit(c) || (ignore_case && (c.to_lowercase().any(hit) || c.to_uppercase().any(hit)));
+        found != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Assert {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assert),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_repeat(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn parse_repeat(&mut self, mut atom: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => match self.parse_bounds()? {
+                    Some(bounds) => bounds,
+                    // 不是合法的 {m,n} 时 { 是普通字符，由下一个 atom 处理
+                    None => return Ok(atom),
+                },
+                Some(c @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    match c {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Ok(atom),
+            };
+            if matches!(atom, Node::Assert(_) | Node::Empty) {
+                return Err("nothing to repeat".to_string());
+            }
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+            };
+        }
+    }
+
+    // 解析 {m}、{m,}、{m,n}，成功时越过右花括号
+    fn parse_bounds(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let Some(end) = rest.find('}') else {
+            return Ok(None);
+        };
+        let body = &rest[..end];
+        let parse = |s: &str| s.parse::<u32>().ok();
+        let bounds = match body.split_once(',') {
+            None => parse(body).map(|n| (n, Some(n))),
+            Some((lo, "")) => parse(lo).map(|n| (n, None)),
+            Some((lo, hi)) => parse(lo).zip(parse(hi)).map(|(a, b)| (a, Some(b))),
+        };
+        let Some((min, max)) = bounds else {
+            return Ok(None);
+        };
+        if max.is_some_and(|max| max < min) || min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(format!("invalid repetition {{{}}}", body));
+        }
+        self.pos += body.chars().count() + 2;
+        Ok(Some((min, max)))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '(' => {
+                let inner = self.parse_alt()?;
+                if self.peek() != Some(')') {
+                    return Err("missing ')'".to_string());
+                }
+                self.pos += 1;
+                inner
+            }
+            '.' => Node::Any,
+            '^' => Node::Assert(Assert::Start),
+            '$' => Node::Assert(Assert::End),
+            '[' => Node::Class(self.parse_class()?),
+            '*' | '+' | '?' => return Err(format!("nothing to repeat before '{}'", c)),
+            '\\' => {
+                let Some(e) = self.peek() else {
+                    return Err("trailing backslash".to_string());
+                };
+                self.pos += 1;
+                let class = |item, negated| {
+                    Node::Class(Class {
+                        negated,
+                        items: vec![item],
+                    })
+                };
+                match e {
+                    'd' => class(ClassItem::Digit, false),
+                    'D' => class(ClassItem::Digit, true),
+                    'w' => class(ClassItem::Word, false),
+                    'W' => class(ClassItem::Word, true),
+                    's' => class(ClassItem::Space, false),
+                    'S' => class(ClassItem::Space, true),
+                    'b' => Node::Assert(Assert::WordBoundary),
+                    'B' => Node::Assert(Assert::NotWordBoundary),
+                    't' => Node::Char('\t'),
+                    'n' => Node::Char('\n'),
+                    _ => Node::Char(e),
+                }
+            }
+            _ => Node::Char(c),
+        })
+    }
+
+    // [ 之后的部分：开头的 ] 是普通字符，支持 a-z 范围和 [:name:] 字符类
+    fn parse_class(&mut self) -> Result<Class, String> {
+        let mut class = Class {
+            negated: false,
+            items: Vec::new(),
+        };
+        if self.peek() == Some('^') {
+            class.negated = true;
+            self.pos += 1;
+        }
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                return Err("missing ']'".to_string());
+            };
+            self.pos += 1;
+            if c == ']' && !first {
+                return Ok(class);
+            }
+            first = false;
+
+            if c == '[' && self.peek() == Some(':') {
+                let rest: String = self.chars[self.pos + 1..].iter().collect();
+                let Some(end) = rest.find(":]") else {
+                    return Err("missing ':]'".to_string());
+                };
+                let item = match &rest[..end] {
+                    "digit" => ClassItem::Digit,
+                    "alpha" => ClassItem::Alpha,
+                    "alnum" => ClassItem::Alnum,
+                    "upper" => ClassItem::Upper,
+                    "lower" => ClassItem::Lower,
+                    "space" => ClassItem::Space,
+                    "punct" => ClassItem::Punct,
+                    "xdigit" => ClassItem::Xdigit,
+                    name => return Err(format!("unknown character class '{}'", name)),
+                };
+                class.items.push(item);
+                self.pos += end + 3;
+                continue;
+            }
+
+            let lo = if c == '\\' {
+                let e = self.peek().ok_or("missing ']'")?;
+                self.pos += 1;
+                match e {
+                    'd' | 'w' | 's' => {
+                        class.items.push(match e {
+                            'd' => ClassItem::Digit,
+                            'w' => ClassItem::Word,
+                            _ => ClassItem::Space,
+                        });
+                        continue;
+                    }
+                    't' => '\t',
+                    _ => e,
+                }
+            } else {
+                c
+            };
+            let hi = match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some('-'), Some(&hi)) if hi != ']' => {
+                    self.pos += 2;
+                    if hi < lo {
+                        return Err(format!("invalid range '{}-{}'", lo, hi));
+                    }
+                    hi
+                }
+                _ => lo,
+            };
+            class.items.push(ClassItem::Range(lo, hi));
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assert),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+// 把语法树编译成指令追加到 prog；指令数超过上限时放弃
+fn compile(node: &Node, prog: &mut Vec<Inst>) -> Result<(), String> {
+    if prog.len() > MAX_PROGRAM {
+        return Err("regular expression too big".to_string());
+    }
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => prog.push(Inst::Char(*c)),
+        Node::Any => prog.push(Inst::Any),
+        Node::Class(class) => prog.push(Inst::Class(class.clone())),
+        Node::Assert(a) => prog.push(Inst::Assert(*a)),
+        Node::Concat(items) => {
+            for n in items {
+                compile(n, prog)?;
+            }
+        }
+        Node::Alt(branches) => {
+            // Split L1, next; L1: 分支; Jmp end; ...
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(branch, prog)?;
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jmp(0));
+                    let next = prog.len();
+                    prog[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, prog)?;
+                }
+            }
+            let end = prog.len();
+            for j in jumps {
+                prog[j] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, prog)?;
+            }
+            match max {
+                None => {
+                    // L: Split body, end; body; Jmp L
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(node, prog)?;
+                    prog.push(Inst::Jmp(split));
+                    let end = prog.len();
+                    prog[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    // 每个可选的重复：Split body, end
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Split(prog.len() + 1, 0));
+                        compile(node, prog)?;
+                    }
+                    let end = prog.len();
+                    for s in splits {
+                        prog[s] = Inst::Split(s + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct Regex {
+    prog: Vec<Inst>,
+    ignore_case: bool,
+}
+
+fn is_word(c: Option<&char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || *c == '_')
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched ')'".to_string());
+        }
+        let mut prog = Vec::new();
+        compile(&node, &mut prog)?;
+        prog.push(Inst::Match);
+        Ok(Regex { prog, ignore_case })
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    // 从 pc 出发沿着 Split / Jmp / 断言加入所有可达的指令；到达 Match 时返回 true
+    fn add_thread(
+        &self,
+        list: &mut Vec<usize>,
+        marks: &mut [usize],
+        generation: usize,
+        pc: usize,
+        text: &[char],
+        pos: usize,
+    ) -> bool {
+        let mut stack = vec![pc];
+        let mut matched = false;
+        while let Some(pc) = stack.pop() {
+            if marks[pc] == generation {
+                continue;
+            }
+            marks[pc] = generation;
+            match &self.prog[pc] {
+                Inst::Jmp(target) => stack.push(*target),
+                Inst::Split(a, b) => {
+                    stack.push(*b);
+                    stack.push(*a);
+                }
+                Inst::Assert(a) => {
+                    let before = pos.checked_sub(1).and_then(|i| text.get(i));
+                    let after = text.get(pos);
+                    let ok = match a {
+                        Assert::Start => pos == 0,
+                        Assert::End => pos == text.len(),
+                        Assert::WordBoundary => is_word(before) != is_word(after),
+                        Assert::NotWordBoundary => is_word(before) == is_word(after),
+                    };
+                    if ok {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::Match => matched = true,
+                _ => list.push(pc),
+            }
+        }
+        matched
+    }
+
+    // 文本中是否有任意一处匹配
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let mut marks = vec![usize::MAX; self.prog.len()];
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        for pos in 0..=text.len() {
+            // 每个位置都可以开始一次新的匹配
+            if self.add_thread(&mut current, &mut marks, pos, 0, &text, pos) {
+                return true;
+            }
+            if pos == text.len() {
+                break;
+            }
+            let c = text[pos];
+            for &pc in &current {
+                let ok = match &self.prog[pc] {
+                    Inst::Char(p) => self.char_eq(*p, c),
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(c, self.ignore_case),
+                    _ => false,
+                };
+                if ok && self.add_thread(&mut next, &mut marks, pos + 1, pc + 1, &text, pos + 1) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern, false).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc]x", "zbx"));
+        assert!(!matches("[abc]x", "zdx"));
+        assert!(matches("[^0-9]", "12a"));
+        assert!(!matches("^[^0-9]+$", "12a"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("^[[:digit:]]+$", "2026"));
+        assert!(!matches("^[[:alpha:]]+$", "ab1"));
+        assert!(matches("[[:upper:]][[:lower:]]", "xAby"));
+        assert!(matches(r"^\d\s\w+$", "1 foo_bar"));
+        assert!(!matches(r"\S", " \t "));
+        assert!(matches(r"[\d.]+", "3.14"));
+        assert!(Regex::new("[z-a]", false).is_err());
+        assert!(Regex::new("[[:nope:]]", false).is_err());
+        assert!(Regex::new("[abc", false).is_err());
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(matches("cat|dog", "hotdog"));
+        assert!(!matches("cat|dog", "cow"));
+        assert!(matches("^(ab|cd)+$", "abcdab"));
+        assert!(!matches("^(ab|cd)+$", "abc"));
+        assert!(matches("^(a|)b$", "b"));
+        assert!(Regex::new("(a|b", false).is_err());
+        assert!(Regex::new("a|b)", false).is_err());
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^abc", "abcdef"));
+        assert!(!matches("^abc", "xabc"));
+        assert!(matches("def$", "abcdef"));
+        assert!(!matches("def$", "defx"));
+        assert!(matches("^$", ""));
+        assert!(!matches("^$", "a"));
+        assert!(matches("^a.c$", "abc"));
+    }
+
+    #[test]
+    fn word_boundary() {
+        assert!(matches(r"\bcat\b", "a cat sat"));
+        assert!(!matches(r"\bcat\b", "concatenate"));
+        assert!(matches(r"\Bcat\B", "concatenate"));
+        assert!(matches(r"\bfoo", "foo_bar"));
+        assert!(!matches(r"bar\b", "foo_bar_"));
+        assert!(Regex::new(r"\b*", false).is_err());
+    }
+
+    #[test]
+    fn ignore_case() {
+        let re = Regex::new("hello [a-z]+", true).unwrap();
+        assert!(re.is_match("HELLO World"));
+        assert!(!Regex::new("hello", false).unwrap().is_match("HELLO"));
+        assert!(Regex::new("[[:upper:]]", true).unwrap().is_match("abc"));
+        assert!(Regex::new("straße", true).unwrap().is_match("STRAßE"));
+    }
+
+    #[test]
+    fn bounds() {
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^a{1,3}$", "aaa"));
+        assert!(!matches("^a{1,3}$", "aaaa"));
+        assert!(matches("^(ab){0,1}c$", "c"));
+        // 不是合法的 {m,n} 时花括号是普通字符
+        assert!(matches("a{x}", "a{x}"));
+        assert!(matches("a{1", "a{1"));
+        assert!(Regex::new("a{3,2}", false).is_err());
+        assert!(Regex::new("a{1001}", false).is_err());
+        assert!(Regex::new("a{1000}", false).is_ok());
+    }
+
+    #[test]
+    fn program_size_limit() {
+        assert!(Regex::new("((a{1000}){1000}){1000}", false).is_err());
+        assert!(Regex::new("(a{1000}){1000}", false).is_err());
+        assert!(Regex::new("(a{100}){100}", false).is_ok());
+        assert!(Regex::new("((a|b){1,1000}){1,1000}", false).is_err());
+    }
+}
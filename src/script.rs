@@ -0,0 +1,1337 @@
+use crate::error::FsError;
+use crate::find;
+use crate::fs::MyFileSystem;
+use crate::glob;
+use crate::json;
+use crate::layout::InodeType;
+use crate::lineedit::{Completion, LineEditor};
+use crate::shell::{
+    self, AndOr, Command, CommandKind, ParseError, Pipeline, Redirect, Word, WordPart,
+};
+use crate::{
+    EXIT_FAILURE, EXIT_USAGE, NOT_FORMATTED, check_formatted, fail, report, run_command,
+    to_absolute_path, usage,
+};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+// 命令语言的解释器：变量、位置参数、$? 、if / for / while、函数、source
+// 文件系统命令交给 run_command 执行，这里只处理控制流和内建命令
+
+// 函数调用和 source 的最大嵌套层数，防止无限递归
+const MAX_DEPTH: usize = 100;
+
+// 执行完一条命令之后的控制流
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    Normal,
+    Break(usize),
+    Continue(usize),
+    Return,
+    Exit, // 退出状态为 status
+}
+
+pub struct Shell {
+    vars: HashMap<String, String>,
+    functions: HashMap<String, Rc<Command>>,
+    positional: Vec<String>,
+    name: String, // $0
+    status: i32,  // $?
+    // 命令失败时立即退出（set -e），批处理模式下默认打开
+    errexit: bool,
+    // 大于 0 时正在求值条件（if / while 的条件、&& || 左侧、! 之后），失败不触发 errexit
+    condition: usize,
+    flow: Flow,
+    loops: usize,
+    depth: usize,
+    // 每层函数调用中 local 变量的原值，函数返回时恢复
+    frames: Vec<Vec<(String, Option<String>)>>,
+    // 捕获输出的缓冲区（管道、重定向、$(...)），为空时直接写到标准输出
+    outputs: Vec<Vec<u8>>,
+    at_line_start: bool,
+    // 最近一次 $(...) 的退出状态，只有赋值的命令以它为退出状态
+    subst_status: Option<i32>,
+}
+
+impl Shell {
+    pub fn new(name: &str, positional: Vec<String>, errexit: bool) -> Self {
+        Shell {
+            vars: HashMap::new(),
+            functions: HashMap::new(),
+            positional,
+            name: name.to_string(),
+            status: 0,
+            errexit,
+            condition: 0,
+            flow: Flow::Normal,
+            loops: 0,
+            depth: 0,
+            frames: Vec::new(),
+            outputs: Vec::new(),
+            at_line_start: true,
+            subst_status: None,
+        }
+    }
+
+    // 执行过 exit 或因 set -e 需要结束
+    pub fn exited(&self) -> bool {
+        self.flow == Flow::Exit
+    }
+
+    fn emit(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        if let Some(buf) = self.outputs.last_mut() {
+            buf.extend_from_slice(data);
+            return;
+        }
+        // 终端或管道已经关闭时没有地方报告错误，直接忽略
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(data);
+        let _ = stdout.flush();
+        self.at_line_start = data.ends_with(b"\n");
+    }
+
+    // 终端上的输出没有以换行结束时补一个，避免和提示符连在一起
+    pub fn finish_line(&mut self) {
+        if !self.at_line_start {
+            println!();
+            self.at_line_start = true;
+        }
+    }
+
+    // 执行 f 并捕获它的输出
+    fn capture(&mut self, f: impl FnOnce(&mut Self) -> i32) -> (i32, Vec<u8>) {
+        self.outputs.push(Vec::new());
+        let status = f(self);
+        let out = self.outputs.pop().unwrap_or_default();
+        (status, out)
+    }
+
+    // 依次执行命令序列，遇到 break / continue / return / exit 时停止；返回最后的退出状态
+    pub fn run_list(
+        &mut self,
+        fs: &mut MyFileSystem,
+        list: &[AndOr],
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        for item in list {
+            self.run_and_or(fs, item, input);
+            if self.flow != Flow::Normal {
+                break;
+            }
+        }
+        self.status
+    }
+
+    fn run_and_or(&mut self, fs: &mut MyFileSystem, item: &AndOr, input: &mut Option<Vec<u8>>) {
+        // 除最后一个以外的管道都相当于条件
+        let mut status = self.run_pipeline(fs, &item.first, input, !item.rest.is_empty());
+        for (i, (and, pipeline)) in item.rest.iter().enumerate() {
+            if self.flow != Flow::Normal {
+                return;
+            }
+            if (status == 0) == *and {
+                let last = i + 1 == item.rest.len();
+                status = self.run_pipeline(fs, pipeline, input, !last);
+            }
+        }
+    }
+
+    // 依次执行管道中的命令，前一条命令的输出作为后一条命令的输入；退出状态是最后一条命令的
+    fn run_pipeline(
+        &mut self,
+        fs: &mut MyFileSystem,
+        pipeline: &Pipeline,
+        input: &mut Option<Vec<u8>>,
+        is_condition: bool,
+    ) -> i32 {
+        let suppress = is_condition || pipeline.negate;
+        if suppress {
+            self.condition += 1;
+        }
+
+        let count = pipeline.commands.len();
+        let mut status = 0;
+        let mut piped = None;
+        for (i, cmd) in pipeline.commands.iter().enumerate() {
+            let stage_input = if i == 0 { &mut *input } else { &mut piped };
+            if i + 1 == count {
+                status = self.run_command(fs, cmd, stage_input);
+            } else {
+                let (s, out) = self.capture(|sh| sh.run_command(fs, cmd, stage_input));
+                status = s;
+                piped = Some(out);
+            }
+            if self.flow != Flow::Normal {
+                break;
+            }
+        }
+
+        if suppress {
+            self.condition -= 1;
+        }
+        if pipeline.negate {
+            status = (status == 0) as i32;
+        }
+        self.status = status;
+        if status != 0
+            && self.errexit
+            && !suppress
+            && self.condition == 0
+            && self.flow == Flow::Normal
+        {
+            self.flow = Flow::Exit;
+        }
+        status
+    }
+
+    // 执行一条命令，处理它的 heredoc 和输出重定向
+    fn run_command(
+        &mut self,
+        fs: &mut MyFileSystem,
+        cmd: &Command,
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        let mut heredoc_input;
+        let input = match &cmd.heredoc {
+            Some(heredoc) => match self.expand_single(fs, &heredoc.body) {
+                Ok(body) => {
+                    heredoc_input = Some(body.into_bytes());
+                    &mut heredoc_input
+                }
+                Err(msg) => return self.expand_failed(&msg),
+            },
+            None => input,
+        };
+
+        let Some(redirect) = &cmd.redirect else {
+            return self.run_kind(fs, &cmd.kind, input);
+        };
+        let (target, append) = match redirect {
+            Redirect::Truncate(word) => (word, false),
+            Redirect::Append(word) => (word, true),
+        };
+        let target = match self.expand_single(fs, target) {
+            Ok(target) => target,
+            Err(msg) => return self.expand_failed(&msg),
+        };
+        let (status, out) = self.capture(|sh| sh.run_kind(fs, &cmd.kind, input));
+        let write_status = write_redirect(fs, &target, append, &out);
+        if status == 0 { write_status } else { status }
+    }
+
+    fn run_kind(
+        &mut self,
+        fs: &mut MyFileSystem,
+        kind: &CommandKind,
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        match kind {
+            CommandKind::Simple { assigns, words } => self.run_simple(fs, assigns, words, input),
+            CommandKind::If {
+                branches,
+                else_body,
+            } => {
+                for (cond, body) in branches {
+                    self.condition += 1;
+                    let status = self.run_list(fs, cond, input);
+                    self.condition -= 1;
+                    if self.flow != Flow::Normal {
+                        return status;
+                    }
+                    if status == 0 {
+                        return self.run_list(fs, body, input);
+                    }
+                }
+                match else_body {
+                    Some(body) => self.run_list(fs, body, input),
+                    None => {
+                        self.status = 0;
+                        0
+                    }
+                }
+            }
+            CommandKind::For { var, items, body } => {
+                let values = match items {
+                    Some(words) => {
+                        let mut values = Vec::new();
+                        for word in words {
+                            match self.expand_word(fs, word) {
+                                Ok(fields) => values.extend(fields),
+                                Err(msg) => return self.expand_failed(&msg),
+                            }
+                        }
+                        values
+                    }
+                    None => self.positional.clone(),
+                };
+                let mut status = 0;
+                self.loops += 1;
+                for value in values {
+                    self.vars.insert(var.clone(), value);
+                    status = self.run_list(fs, body, input);
+                    if self.loop_should_stop() {
+                        break;
+                    }
+                }
+                self.loops -= 1;
+                self.status = status;
+                status
+            }
+            CommandKind::While { until, cond, body } => {
+                let mut status = 0;
+                self.loops += 1;
+                loop {
+                    self.condition += 1;
+                    let cond_status = self.run_list(fs, cond, input);
+                    self.condition -= 1;
+                    if self.loop_should_stop() || (cond_status == 0) == *until {
+                        break;
+                    }
+                    status = self.run_list(fs, body, input);
+                    if self.loop_should_stop() {
+                        break;
+                    }
+                }
+                self.loops -= 1;
+                self.status = status;
+                status
+            }
+            CommandKind::Group(body) => self.run_list(fs, body, input),
+            CommandKind::Function { name, body } => {
+                self.functions.insert(name.clone(), Rc::clone(body));
+                self.status = 0;
+                0
+            }
+        }
+    }
+
+    // 循环体执行完之后处理 break / continue；返回 true 表示结束循环
+    fn loop_should_stop(&mut self) -> bool {
+        match self.flow {
+            Flow::Normal => false,
+            Flow::Break(n) => {
+                self.flow = if n > 1 {
+                    Flow::Break(n - 1)
+                } else {
+                    Flow::Normal
+                };
+                true
+            }
+            Flow::Continue(n) if n > 1 => {
+                self.flow = Flow::Continue(n - 1);
+                true
+            }
+            Flow::Continue(_) => {
+                self.flow = Flow::Normal;
+                false
+            }
+            Flow::Return | Flow::Exit => true,
+        }
+    }
+
+    fn run_simple(
+        &mut self,
+        fs: &mut MyFileSystem,
+        assigns: &[(String, Word)],
+        words: &[Word],
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        self.subst_status = None;
+        for (name, value) in assigns {
+            match self.expand_single(fs, value) {
+                Ok(value) => self.set_var(name, value),
+                Err(msg) => return self.expand_failed(&msg),
+            }
+        }
+        let mut args = Vec::new();
+        for word in words {
+            match self.expand_word(fs, word) {
+                Ok(fields) => args.extend(fields),
+                Err(msg) => return self.expand_failed(&msg),
+            }
+        }
+        if args.is_empty() {
+            return self.subst_status.unwrap_or(0);
+        }
+        self.run_args(fs, &args, input)
+    }
+
+    fn expand_failed(&mut self, msg: &str) -> i32 {
+        report("myfs", None, msg);
+        EXIT_FAILURE
+    }
+
+    fn set_var(&mut self, name: &str, value: String) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    // 执行展开之后的一条命令：函数、内建命令或文件系统命令
+    pub fn run_args(
+        &mut self,
+        fs: &mut MyFileSystem,
+        args: &[String],
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        if let Some(body) = self.functions.get(&args[0]).cloned() {
+            return self.call_function(fs, &body, args, input);
+        }
+        let rest = &args[1..];
+        match args[0].as_str() {
+            ":" | "true" => 0,
+            "false" => 1,
+            "echo" => {
+                let (newline, words) = match rest.first().map(String::as_str) {
+                    Some("-n") => (false, &rest[1..]),
+                    _ => (true, rest),
+                };
+                let mut text = words.join(" ");
+                if newline {
+                    text.push('\n');
+                }
+                self.emit(text.as_bytes());
+                0
+            }
+            "test" | "[" => {
+                let mut operands = rest;
+                if args[0] == "[" {
+                    match operands.split_last() {
+                        Some((last, init)) if last == "]" => operands = init,
+                        _ => {
+                            report("[", None, "missing ']'");
+                            return EXIT_USAGE;
+                        }
+                    }
+                }
+                match eval_test(fs, operands) {
+                    Ok(true) => 0,
+                    Ok(false) => 1,
+                    Err(msg) => {
+                        report(&args[0], None, &msg);
+                        EXIT_USAGE
+                    }
+                }
+            }
+            "set" => self.builtin_set(rest),
+            "shift" => {
+                let n = match rest.first().map(|n| n.parse::<usize>()) {
+                    None => 1,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => return usage("shift [N]"),
+                };
+                if n > self.positional.len() {
+                    report("shift", None, "shift count out of range");
+                    return EXIT_FAILURE;
+                }
+                self.positional.drain(..n);
+                0
+            }
+            "unset" => {
+                for name in rest {
+                    if self.vars.remove(name).is_none() {
+                        self.functions.remove(name);
+                    }
+                }
+                0
+            }
+            "local" => {
+                let Some(frame) = self.frames.last_mut() else {
+                    report("local", None, "can only be used in a function");
+                    return EXIT_FAILURE;
+                };
+                for arg in rest {
+                    let (name, value) = match arg.split_once('=') {
+                        Some((name, value)) => (name, value.to_string()),
+                        None => (arg.as_str(), String::new()),
+                    };
+                    // 同一个函数中多次 local 只保存第一次的原值
+                    if !frame.iter().any(|(n, _)| n == name) {
+                        frame.push((name.to_string(), self.vars.get(name).cloned()));
+                    }
+                    self.vars.insert(name.to_string(), value);
+                }
+                0
+            }
+            "return" => {
+                if self.depth == 0 {
+                    report(
+                        "return",
+                        None,
+                        "can only be used in a function or sourced script",
+                    );
+                    return EXIT_FAILURE;
+                }
+                let status = match rest.first().map(|n| n.parse::<i32>()) {
+                    None => self.status,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => return usage("return [N]"),
+                };
+                self.flow = Flow::Return;
+                status
+            }
+            "break" | "continue" => {
+                let n = match rest.first().map(|n| n.parse::<usize>()) {
+                    None => 1,
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => return usage(&format!("{} [N]", args[0])),
+                };
+                if self.loops == 0 {
+                    report(&args[0], None, "only meaningful in a loop");
+                    return EXIT_FAILURE;
+                }
+                let n = n.min(self.loops);
+                self.flow = if args[0] == "break" {
+                    Flow::Break(n)
+                } else {
+                    Flow::Continue(n)
+                };
+                0
+            }
+            "exit" => {
+                let status = match rest.first().map(|n| n.parse::<i32>()) {
+                    None => self.status,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => usage("exit [N]"),
+                };
+                self.flow = Flow::Exit;
+                status
+            }
+            "source" | "." => self.source(fs, args, input),
+            "find" => {
+                if !check_formatted(fs, "find") {
+                    return EXIT_FAILURE;
+                }
+                // -exec 执行的命令可以是任何命令，包括内建命令和函数
+                let mut out = Vec::new();
+                let status = find::run_find(fs, rest, &mut out, &mut |fs, argv, out| {
+                    let (status, captured) = self.capture(|sh| sh.run_args(fs, argv, &mut None));
+                    // -exec 中的 exit / return 只结束这一条命令
+                    self.flow = Flow::Normal;
+                    out.extend_from_slice(&captured);
+                    status
+                });
+                self.emit(&out);
+                status
+            }
+            _ => {
+                let cmd_parts: Vec<&str> = args.iter().map(String::as_str).collect();
+                let mut out = Vec::new();
+                let status = run_command(fs, &cmd_parts, input.as_deref(), &mut out);
+                self.emit(&out);
+                status
+            }
+        }
+    }
+
+    fn builtin_set(&mut self, args: &[String]) -> i32 {
+        if args.is_empty() {
+            let mut names: Vec<&String> = self.vars.keys().collect();
+            names.sort();
+            let mut text = String::new();
+            for name in names {
+                text.push_str(&format!("{}={}\n", name, self.vars[name]));
+            }
+            self.emit(text.as_bytes());
+            return 0;
+        }
+        const SET_USAGE: &str = "set [-e | +e] [-o | +o errexit|json] [-- args...]";
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "-e" => self.errexit = true,
+                "+e" => self.errexit = false,
+                // -o 打开、+o 关闭一个选项；json 让 ls / stat / df / du / find / fsck 输出 JSON
+                flag @ ("-o" | "+o") => {
+                    let on = flag == "-o";
+                    match args.get(i + 1).map(String::as_str) {
+                        Some("errexit") => self.errexit = on,
+                        Some("json") => json::set_enabled(on),
+                        _ => return usage(SET_USAGE),
+                    }
+                    i += 1;
+                }
+                "--" => {
+                    self.positional = args[i + 1..].to_vec();
+                    break;
+                }
+                _ => return usage(SET_USAGE),
+            }
+            i += 1;
+        }
+        0
+    }
+
+    fn call_function(
+        &mut self,
+        fs: &mut MyFileSystem,
+        body: &Command,
+        args: &[String],
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        if self.depth >= MAX_DEPTH {
+            report(&args[0], None, "maximum nesting depth exceeded");
+            return EXIT_FAILURE;
+        }
+        let saved_args = std::mem::replace(&mut self.positional, args[1..].to_vec());
+        // break / continue 不能跳出函数之外的循环
+        let saved_loops = std::mem::take(&mut self.loops);
+        self.frames.push(Vec::new());
+        self.depth += 1;
+
+        self.status = self.run_command(fs, body, input);
+
+        self.depth -= 1;
+        for (name, old) in self.frames.pop().unwrap_or_default().into_iter().rev() {
+            match old {
+                Some(value) => self.vars.insert(name, value),
+                None => self.vars.remove(&name),
+            };
+        }
+        self.loops = saved_loops;
+        self.positional = saved_args;
+        if self.flow == Flow::Return {
+            self.flow = Flow::Normal;
+        }
+        self.status
+    }
+
+    // source <path> [args...]：在当前环境中执行镜像中的脚本
+    fn source(
+        &mut self,
+        fs: &mut MyFileSystem,
+        args: &[String],
+        input: &mut Option<Vec<u8>>,
+    ) -> i32 {
+        if args.len() < 2 {
+            return usage("source <path> [args...]");
+        }
+        if self.depth >= MAX_DEPTH {
+            report(&args[0], None, "maximum nesting depth exceeded");
+            return EXIT_FAILURE;
+        }
+        if !check_formatted(fs, &args[0]) {
+            return EXIT_FAILURE;
+        }
+        let path = to_absolute_path(&fs.cwd_path, &args[1]);
+        let data = match fs.read_bytes(&path) {
+            Ok(data) => data,
+            Err(e) => return fail(&args[0], &path, e),
+        };
+        let list = match shell::parse_script(&String::from_utf8_lossy(&data)) {
+            Ok(list) => list,
+            Err(e) => {
+                let msg = match e {
+                    ParseError::Incomplete => "unexpected end of file".to_string(),
+                    ParseError::Syntax(msg) => msg,
+                };
+                report(&args[0], Some(&path), &format!("syntax error: {}", msg));
+                return EXIT_USAGE;
+            }
+        };
+
+        let saved_args =
+            (args.len() > 2).then(|| std::mem::replace(&mut self.positional, args[2..].to_vec()));
+        self.depth += 1;
+        let status = self.run_list(fs, &list, input);
+        self.depth -= 1;
+        if let Some(saved) = saved_args {
+            self.positional = saved;
+        }
+        if self.flow == Flow::Return {
+            self.flow = Flow::Normal;
+        }
+        status
+    }
+
+    // 参数的值；未设置的变量为空，shell 变量中没有时查找环境变量
+    fn param(&self, name: &str) -> String {
+        match name {
+            "?" => self.status.to_string(),
+            "#" => self.positional.len().to_string(),
+            "@" | "*" => self.positional.join(" "),
+            "0" => self.name.clone(),
+            _ => {
+                // ${00} 这样带前导 0 的写法与 $0、$1 相同
+                match name.parse::<usize>() {
+                    Ok(0) => return self.name.clone(),
+                    Ok(n) => return self.positional.get(n - 1).cloned().unwrap_or_default(),
+                    Err(_) => {}
+                }
+                self.vars
+                    .get(name)
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok())
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    // $(...)：执行命令并捕获输出，去掉末尾的换行
+    fn command_subst(&mut self, fs: &mut MyFileSystem, source: &str) -> Result<String, String> {
+        let list = match shell::parse_script(source) {
+            Ok(list) => list,
+            Err(ParseError::Incomplete) => return Err("syntax error in $(...)".to_string()),
+            Err(ParseError::Syntax(msg)) => return Err(format!("syntax error: {}", msg)),
+        };
+        let (status, out) = self.capture(|sh| sh.run_list(fs, &list, &mut None));
+        // $(...) 中的 exit 和 set -e 只结束这次替换
+        self.flow = Flow::Normal;
+        self.status = status;
+        self.subst_status = Some(status);
+        let mut text = String::from_utf8_lossy(&out).into_owned();
+        while text.ends_with('\n') {
+            text.pop();
+        }
+        Ok(text)
+    }
+
+    fn expand_part(&mut self, fs: &mut MyFileSystem, part: &WordPart) -> Result<String, String> {
+        match part {
+            WordPart::Lit { text, .. } => Ok(text.clone()),
+            WordPart::Param { name, .. } => Ok(self.param(name)),
+            WordPart::Command { source, .. } => self.command_subst(fs, source),
+            WordPart::Arith { expr, .. } => eval_arith(self, expr).map(|n| n.to_string()),
+        }
+    }
+
+    // 展开成一个字符串，不做字段切分：用于赋值、重定向目标和 heredoc
+    fn expand_single(&mut self, fs: &mut MyFileSystem, word: &Word) -> Result<String, String> {
+        let mut text = String::new();
+        for part in &word.0 {
+            text.push_str(&self.expand_part(fs, part)?);
+        }
+        Ok(text)
+    }
+
+    // 展开成若干字段：没有引号的展开结果按空白切分，"$@" 的每个位置参数是单独的字段，
+    // 含有未加引号的通配符的字段再按镜像中的路径展开，没有匹配时保留原样
+    fn expand_word(&mut self, fs: &mut MyFileSystem, word: &Word) -> Result<Vec<String>, String> {
+        let mut fields = Vec::new();
+        let mut current = Field::default();
+        // 当前字段是否已经存在（即使为空，例如 ""）
+        let mut started = false;
+        for part in &word.0 {
+            match part {
+                WordPart::Lit { text, quoted } => {
+                    current.push(text, *quoted);
+                    started = true;
+                }
+                WordPart::Param { name, quoted: true } if name == "@" => {
+                    for (i, arg) in self.positional.iter().enumerate() {
+                        if i > 0 {
+                            fields.push(std::mem::take(&mut current));
+                        }
+                        current.push(arg, true);
+                        started = true;
+                    }
+                }
+                WordPart::Param { quoted: true, .. }
+                | WordPart::Command { quoted: true, .. }
+                | WordPart::Arith { quoted: true, .. } => {
+                    current.push(&self.expand_part(fs, part)?, true);
+                    started = true;
+                }
+                _ => {
+                    for c in self.expand_part(fs, part)?.chars() {
+                        if c.is_whitespace() {
+                            if started {
+                                fields.push(std::mem::take(&mut current));
+                                started = false;
+                            }
+                        } else {
+                            current.push(c.encode_utf8(&mut [0; 4]), false);
+                            started = true;
+                        }
+                    }
+                }
+            }
+        }
+        if started {
+            fields.push(current);
+        }
+
+        let mut args = Vec::new();
+        for field in fields {
+            if field.glob {
+                let matches = glob::glob(fs, &field.pattern);
+                if !matches.is_empty() {
+                    args.extend(matches);
+                    continue;
+                }
+            }
+            args.push(field.text);
+        }
+        Ok(args)
+    }
+}
+
+// 展开中的一个字段：text 是最终的文本，pattern 是用于路径展开的模式，其中来自引号的通配符已经转义
+#[derive(Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    glob: bool,
+}
+
+impl Field {
+    fn push(&mut self, s: &str, quoted: bool) {
+        for c in s.chars() {
+            self.text.push(c);
+            if c == '\\' || (quoted && matches!(c, '*' | '?' | '[' | ']')) {
+                self.pattern.push('\\');
+            } else if !quoted && matches!(c, '*' | '?' | '[') {
+                self.glob = true;
+            }
+            self.pattern.push(c);
+        }
+    }
+}
+
+// > 覆盖写入、>> 追加到镜像中的文件，文件不存在时创建
+fn write_redirect(fs: &mut MyFileSystem, raw_path: &str, append: bool, data: &[u8]) -> i32 {
+    if !check_formatted(fs, "myfs") {
+        return EXIT_FAILURE;
+    }
+    let abs_path = to_absolute_path(&fs.cwd_path, raw_path);
+    let result = match fs.stat(&abs_path) {
+        Ok(_) => Ok(()),
+        Err(FsError::NotFound) => fs.create_file(&abs_path).map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let result = result.and_then(|()| {
+        if append {
+            fs.append(&abs_path, data)
+        } else {
+            fs.write_bytes(&abs_path, data)
+        }
+    });
+    match result {
+        Ok(_) => 0,
+        Err(e) => fail("myfs", &abs_path, e),
+    }
+}
+
+// test / [ 的条件表达式：字符串比较、整数比较和镜像中的文件测试
+fn eval_test(fs: &mut MyFileSystem, args: &[String]) -> Result<bool, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => Ok(false),
+        [s] => Ok(!s.is_empty()),
+        ["!", rest @ ..] => eval_test_strs(fs, rest).map(|b| !b),
+        [op, operand] => test_unary(fs, op, operand),
+        [a, op, b] => test_binary(a, op, b),
+        _ => Err("too many arguments".to_string()),
+    }
+}
+
+fn eval_test_strs(fs: &mut MyFileSystem, args: &[&str]) -> Result<bool, String> {
+    let owned: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    eval_test(fs, &owned)
+}
+
+fn test_unary(fs: &mut MyFileSystem, op: &str, operand: &str) -> Result<bool, String> {
+    let path = to_absolute_path(&fs.cwd_path, operand);
+    if matches!(op, "-e" | "-f" | "-d" | "-L" | "-h" | "-s") && !fs.is_formatted() {
+        return Err(NOT_FORMATTED.to_string());
+    }
+    let result = match op {
+        "-z" => return Ok(operand.is_empty()),
+        "-n" => return Ok(!operand.is_empty()),
+        "-e" => fs.stat(&path).map(|_| true),
+        "-f" => fs.stat(&path).map(|m| m.kind == InodeType::File),
+        "-d" => fs.stat(&path).map(|m| m.kind == InodeType::Directory),
+        "-L" | "-h" => fs.lstat(&path).map(|m| m.kind == InodeType::Symlink),
+        "-s" => fs.stat(&path).map(|m| m.size > 0),
+        _ => return Err(format!("unknown operator '{}'", op)),
+    };
+    // 路径不存在或无法解析时条件为假
+    Ok(result.unwrap_or(false))
+}
+
+fn test_binary(a: &str, op: &str, b: &str) -> Result<bool, String> {
+    match op {
+        "=" | "==" => return Ok(a == b),
+        "!=" => return Ok(a != b),
+        _ => {}
+    }
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("integer expected: '{}'", s))
+    };
+    let (x, y) = (parse(a)?, parse(b)?);
+    match op {
+        "-eq" => Ok(x == y),
+        "-ne" => Ok(x != y),
+        "-lt" => Ok(x < y),
+        "-le" => Ok(x <= y),
+        "-gt" => Ok(x > y),
+        "-ge" => Ok(x >= y),
+        _ => Err(format!("unknown operator '{}'", op)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArithToken {
+    Num(i64),
+    Op(&'static str),
+}
+
+// 算术运算符及其优先级，数字越大结合越紧
+const ARITH_OPS: &[(&str, u8)] = &[
+    ("||", 1),
+    ("&&", 2),
+    ("==", 3),
+    ("!=", 3),
+    ("<=", 4),
+    (">=", 4),
+    ("<", 4),
+    (">", 4),
+    ("+", 5),
+    ("-", 5),
+    ("*", 6),
+    ("/", 6),
+    ("%", 6),
+    ("!", 0),
+    ("(", 0),
+    (")", 0),
+];
+
+// $((...)) 的整数运算；变量可以写成 name 或 $name，空值当作 0
+fn eval_arith(shell: &Shell, expr: &str) -> Result<i64, String> {
+    let err = || format!("bad arithmetic expression '{}'", expr.trim());
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(ArithToken::Num(text.parse().map_err(|_| err())?));
+        } else if c == '$' || c.is_ascii_alphabetic() || c == '_' {
+            // 变量引用：name、$name、${name}、$1、$#、$?
+            if c == '$' {
+                i += 1;
+            }
+            let braced = chars.get(i) == Some(&'{');
+            if braced {
+                i += 1;
+            }
+            let start = i;
+            if chars.get(i).is_some_and(|c| "?#".contains(*c)) {
+                i += 1;
+            } else {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+            }
+            let name: String = chars[start..i].iter().collect();
+            if braced {
+                if chars.get(i) != Some(&'}') {
+                    return Err(err());
+                }
+                i += 1;
+            }
+            if name.is_empty() {
+                return Err(err());
+            }
+            let value = shell.param(&name);
+            let value = value.trim();
+            let n = if value.is_empty() {
+                0
+            } else {
+                value
+                    .parse()
+                    .map_err(|_| format!("{}: integer expected: '{}'", name, value))?
+            };
+            tokens.push(ArithToken::Num(n));
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let (op, _) = ARITH_OPS
+                .iter()
+                .find(|(op, _)| rest.starts_with(op))
+                .ok_or_else(err)?;
+            tokens.push(ArithToken::Op(op));
+            i += op.len();
+        }
+    }
+
+    let mut pos = 0;
+    let value = arith_expr(&tokens, &mut pos, 1).ok_or_else(err)??;
+    if pos != tokens.len() {
+        return Err(err());
+    }
+    Ok(value)
+}
+
+// 按优先级递归求值；语法错误返回 None，运算错误（除以零）返回 Some(Err)
+fn arith_expr(tokens: &[ArithToken], pos: &mut usize, min_prec: u8) -> Option<Result<i64, String>> {
+    let mut lhs = match arith_unary(tokens, pos)? {
+        Ok(v) => v,
+        Err(e) => return Some(Err(e)),
+    };
+    while let Some(ArithToken::Op(op)) = tokens.get(*pos) {
+        let prec = ARITH_OPS.iter().find(|(o, _)| o == op).map(|(_, p)| *p)?;
+        if prec == 0 || prec < min_prec {
+            break;
+        }
+        *pos += 1;
+        let rhs = match arith_expr(tokens, pos, prec + 1)? {
+            Ok(v) => v,
+            Err(e) => return Some(Err(e)),
+        };
+        lhs = match *op {
+            "||" => (lhs != 0 || rhs != 0) as i64,
+            "&&" => (lhs != 0 && rhs != 0) as i64,
+            "==" => (lhs == rhs) as i64,
+            "!=" => (lhs != rhs) as i64,
+            "<=" => (lhs <= rhs) as i64,
+            ">=" => (lhs >= rhs) as i64,
+            "<" => (lhs < rhs) as i64,
+            ">" => (lhs > rhs) as i64,
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" | "%" if rhs == 0 => return Some(Err("division by zero".to_string())),
+            "/" => lhs.wrapping_div(rhs),
+            _ => lhs.wrapping_rem(rhs),
+        };
+    }
+    Some(Ok(lhs))
+}
+
+fn arith_unary(tokens: &[ArithToken], pos: &mut usize) -> Option<Result<i64, String>> {
+    let token = *tokens.get(*pos)?;
+    *pos += 1;
+    match token {
+        ArithToken::Num(n) => Some(Ok(n)),
+        ArithToken::Op("-") => Some(arith_unary(tokens, pos)?.map(i64::wrapping_neg)),
+        ArithToken::Op("+") => arith_unary(tokens, pos),
+        ArithToken::Op("!") => Some(arith_unary(tokens, pos)?.map(|v| (v == 0) as i64)),
+        ArithToken::Op("(") => {
+            let value = arith_expr(tokens, pos, 1)?;
+            if tokens.get(*pos) != Some(&ArithToken::Op(")")) {
+                return None;
+            }
+            *pos += 1;
+            Some(value)
+        }
+        ArithToken::Op(_) => None,
+    }
+}
+
+// 命令的输入来源：终端上的行编辑器，或者脚本文件、非终端的标准输入
+pub enum Input<'a> {
+    Terminal(LineEditor),
+    Script(&'a mut dyn BufRead),
+}
+
+impl Input<'_> {
+    fn is_interactive(&self) -> bool {
+        matches!(self, Input::Terminal(_))
+    }
+
+    // 读入一行（包括换行符），交互模式下先打印提示符；输入结束或出错时返回 None
+    fn read_line(&mut self, fs: &mut MyFileSystem, prompt: &str) -> Option<String> {
+        match self {
+            Input::Terminal(editor) => {
+                editor.read_line(prompt, &mut |before| complete_line(fs, before))
+            }
+            Input::Script(reader) => {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) => None,
+                    Ok(_) => Some(line),
+                    Err(e) => {
+                        report("myfs", None, &e.to_string());
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if let Input::Terminal(editor) = self {
+            editor.add_history(line);
+        }
+    }
+}
+
+// 可以补全的命令名，包括内建命令
+const COMMANDS: &[&str] = &[
+    "break",
+    "cat",
+    "cd",
+    "chattr",
+    "continue",
+    "cp",
+    "dedup",
+    "df",
+    "du",
+    "echo",
+    "exit",
+    "export",
+    "false",
+    "find",
+    "format",
+    "fsck",
+    "grep",
+    "head",
+    "hexdump",
+    "import",
+    "ln",
+    "local",
+    "ls",
+    "mkdir",
+    "mv",
+    "readlink",
+    "return",
+    "rm",
+    "set",
+    "shift",
+    "snapshot",
+    "source",
+    "stat",
+    "statfs",
+    "tail",
+    "tar-export",
+    "tar-import",
+    "tar-view",
+    "test",
+    "touch",
+    "tree",
+    "true",
+    "truncate",
+    "unset",
+    "write",
+];
+
+// 在参数中需要转义的字符
+fn shell_escape(word: &str) -> String {
+    let mut escaped = String::new();
+    for c in word.chars() {
+        if matches!(
+            c,
+            ' ' | '\t' | '\\' | '\'' | '"' | '|' | '>' | '<' | '#' | '$' | '&' | ';' | '(' | ')'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Tab 补全：before 是光标前的内容。命令的第一个单词补全命令名，其余单词补全镜像中的路径
+fn complete_line(fs: &mut MyFileSystem, before: &str) -> Completion {
+    // 找到光标所在单词的起点，同时去掉其中的引号和转义得到真正的内容
+    let mut start = 0;
+    let mut word = String::new();
+    let mut command_position = true;
+    let mut quote = None;
+    let mut chars = before.chars().enumerate();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some((_, next)) = chars.next() {
+                    word.push(next);
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ' ' | '\t' | '|' | '>' | '<' | ';' | '&' | '(' | ')') => {
+                if !word.is_empty() || c != ' ' {
+                    command_position = matches!(c, '|' | ';' | '&' | '(' | ')');
+                }
+                start = i + 1;
+                word.clear();
+            }
+            (None, c) => word.push(c),
+        }
+    }
+
+    let candidates = if command_position && !word.contains('/') {
+        COMMANDS
+            .iter()
+            .filter(|c| c.starts_with(&word))
+            .map(|c| c.to_string())
+            .collect()
+    } else if fs.is_formatted() {
+        fs.complete(&word).iter().map(|c| shell_escape(c)).collect()
+    } else {
+        Vec::new()
+    };
+    Completion { start, candidates }
+}
+
+// 逐条执行命令。交互模式下打印提示符，遇到失败的命令继续执行；
+// 批处理模式（脚本或非终端的标准输入）下 shell 默认打开 set -e，遇到失败的命令立即停止
+pub fn run_lines(fs: &mut MyFileSystem, shell: &mut Shell, input: &mut Input) -> i32 {
+    let interactive = input.is_interactive();
+    loop {
+        if interactive {
+            shell.finish_line();
+        }
+        let prompt = format!("myfs:{}> ", fs.cwd_path);
+        let Some(mut text) = input.read_line(fs, &prompt) else {
+            break;
+        };
+
+        // 引号、heredoc 或 if / for / while 等没有结束时继续读入下一行
+        let parsed = loop {
+            match shell::parse_script(&text) {
+                Err(ParseError::Incomplete) => match input.read_line(fs, "> ") {
+                    Some(more) => text.push_str(&more),
+                    None => break Err("unexpected end of input".to_string()),
+                },
+                Err(ParseError::Syntax(msg)) => break Err(msg),
+                Ok(list) => break Ok(list),
+            }
+        };
+        input.add_history(&text);
+        match parsed {
+            Ok(list) => {
+                shell.run_list(fs, &list, &mut None);
+                if shell.exited() {
+                    break;
+                }
+            }
+            Err(msg) => {
+                report("myfs", None, &format!("syntax error: {}", msg));
+                shell.status = EXIT_USAGE;
+                if !interactive {
+                    break;
+                }
+            }
+        }
+    }
+    shell.finish_line();
+    shell.status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 在一个临时镜像上执行脚本（不打开 set -e），返回退出状态和输出
+    fn run(name: &str, script: &str) -> (i32, String) {
+        let path = std::env::temp_dir()
+            .join(format!("myfs-script-{}-{}.img", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned();
+        let mut fs = MyFileSystem::new(&path).unwrap();
+        fs.format();
+        let mut shell = Shell::new("test", vec!["p1".to_string(), "p2".to_string()], false);
+        let list = shell::parse_script(script).unwrap();
+        let (status, out) = shell.capture(|sh| sh.run_list(&mut fs, &list, &mut None));
+        let _ = std::fs::remove_file(&path);
+        (status, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn quoting_and_expansion() {
+        let (status, out) = run(
+            "quoting",
+            r#"x='a  b'; echo 'single $x' "double $x" $x e\ f "q\"\\" ${00} ${1} $# "$@""#,
+        );
+        assert_eq!(status, 0);
+        assert_eq!(out, "single $x double a  b a b e f q\"\\ test p1 2 p1 p2\n");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (status, out) = run(
+            "arith",
+            "x=7; echo $((x * 2 + 1)) $(( (1 + 2) * -3 )) $((x / 2)) $((x % 4)) $((x > 3 && x < 5))",
+        );
+        assert_eq!(status, 0);
+        assert_eq!(out, "15 -9 3 3 0\n");
+        assert_eq!(run("arith-zero", "echo $((1 / 0))").0, EXIT_FAILURE);
+    }
+
+    #[test]
+    fn heredocs() {
+        let script = "x=5\ncat <<END\nvalue $x $((x + 1))\nEND\ncat <<'END'\nraw $x\nEND\n";
+        assert_eq!(
+            run("heredoc", script),
+            (0, "value 5 6\nraw $x\n".to_string())
+        );
+    }
+
+    #[test]
+    fn functions_and_return() {
+        let script = r#"
+f() {
+    local v=inner
+    if [ "$1" = stop ]; then return 3; fi
+    echo "f $1 $v"
+}
+v=outer
+f go; echo "status $?"
+f stop; echo "status $? $v"
+g() { f "$@"; echo "g saw $?"; return; }
+g stop; echo "g $?"
+"#;
+        let (status, out) = run("functions", script);
+        assert_eq!(status, 0);
+        assert_eq!(out, "f go inner\nstatus 0\nstatus 3 outer\ng saw 3\ng 0\n");
+    }
+
+    #[test]
+    fn errexit_in_conditions() {
+        let script = r#"
+set -e
+if false; then echo no; fi
+false || echo or
+false && echo and
+! true
+while false; do :; done
+echo survived
+false
+echo not-reached
+"#;
+        let (status, out) = run("errexit", script);
+        assert_eq!(status, 1);
+        assert_eq!(out, "or\nsurvived\n");
+
+        // 函数的最后一条命令失败同样退出
+        let (status, out) = run(
+            "errexit-fn",
+            "set -e\nf() { false; echo in; }\nf\necho after",
+        );
+        assert_eq!((status, out.as_str()), (1, ""));
+    }
+
+    #[test]
+    fn loops_with_break_and_continue() {
+        let script = r#"
+i=0
+while [ $i -lt 10 ]; do
+    i=$((i + 1))
+    if [ $i -eq 2 ]; then continue; fi
+    if [ $i -eq 5 ]; then break; fi
+    echo -n "$i "
+done
+for a in x y; do
+    for b in 1 2 3; do
+        if [ $b = 2 ]; then continue 2; fi
+        echo -n "$a$b "
+    done
+done
+until [ $i -eq 0 ]; do i=$((i - 1)); done
+echo "end $i"
+"#;
+        assert_eq!(run("loops", script), (0, "1 3 4 x1 y1 end 0\n".to_string()));
+        assert_eq!(run("loops-outside", "break").0, EXIT_FAILURE);
+    }
+}
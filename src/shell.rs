@@ -0,0 +1,1013 @@
+use std::rc::Rc;
+
+// 命令语言的词法和语法分析，语法是 sh 的一个子集：
+// 引号和转义、$变量 / ${变量} / $(命令) / $((算术))、管道、&& || !、; 和换行、
+// 输出重定向 > >>、heredoc、if / for / while / until、{ } 命令组和函数定义
+// 只负责把文本解析成语法树，执行在 script.rs 中
+
+// 单词的组成部分；quoted 表示来自引号或转义，展开后不再做字段切分
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    Lit { text: String, quoted: bool },
+    Param { name: String, quoted: bool }, // $name ${name} $1 $? $# $@ $*
+    Command { source: String, quoted: bool }, // $(...)
+    Arith { expr: String, quoted: bool }, // $((...))
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Word(pub Vec<WordPart>);
+
+impl Word {
+    // 没有任何引号和展开时的字面内容，用于识别关键字和赋值
+    pub fn as_plain(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [
+                WordPart::Lit {
+                    text,
+                    quoted: false,
+                },
+            ] => Some(text),
+            _ => None,
+        }
+    }
+}
+
+// 输出重定向的目标是镜像中的文件
+#[derive(Debug, Clone)]
+pub enum Redirect {
+    Truncate(Word), // > path
+    Append(Word),   // >> path
+}
+
+// heredoc 正文；结束标记没有加引号时正文中的 $ 展开，否则整体是一个带引号的字面量
+#[derive(Debug, Clone)]
+pub struct Heredoc {
+    pub body: Word,
+}
+
+#[derive(Debug, Clone)]
+pub enum CommandKind {
+    Simple {
+        assigns: Vec<(String, Word)>,
+        words: Vec<Word>,
+    },
+    If {
+        branches: Vec<(Vec<AndOr>, Vec<AndOr>)>, // (条件, 分支)
+        else_body: Option<Vec<AndOr>>,
+    },
+    For {
+        var: String,
+        items: Option<Vec<Word>>, // 省略 in 时遍历位置参数
+        body: Vec<AndOr>,
+    },
+    While {
+        until: bool,
+        cond: Vec<AndOr>,
+        body: Vec<AndOr>,
+    },
+    Group(Vec<AndOr>),
+    Function {
+        name: String,
+        body: Rc<Command>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub kind: CommandKind,
+    pub redirect: Option<Redirect>,
+    pub heredoc: Option<Heredoc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub negate: bool,
+    pub commands: Vec<Command>,
+}
+
+// 用 && 或 || 连接的管道，rest 中的 bool 为 true 表示 &&
+#[derive(Debug, Clone)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(bool, Pipeline)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    // 引号、$(、heredoc 或复合命令没有结束，需要继续读入下一行
+    Incomplete,
+    Syntax(String),
+}
+
+fn syntax<T>(msg: &str) -> Result<T, ParseError> {
+    Err(ParseError::Syntax(msg.to_string()))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Pipe,
+    Or,
+    And,
+    Semi,
+    LParen,
+    RParen,
+    Great,
+    DGreat,
+    DLess { strip_tabs: bool },
+}
+
+#[derive(Debug)]
+enum Token {
+    Word(Word),
+    Op(Op),
+    Newline,
+    Heredoc(usize), // 在 heredocs 中的下标
+}
+
+const RESERVED: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "for", "in", "while", "until", "do", "done", "{", "}", "!",
+    "function",
+];
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    tokens: Vec<Token>,
+    heredocs: Vec<Heredoc>,
+    // 等待读取正文的 heredoc：(下标, 结束标记, 是否去掉行首的 tab, 是否展开)
+    pending: Vec<(usize, String, bool, bool)>,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn run(mut self) -> Result<(Vec<Token>, Vec<Heredoc>), ParseError> {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '\n' => {
+                    self.pos += 1;
+                    self.tokens.push(Token::Newline);
+                    self.read_heredoc_bodies()?;
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                '\\' if self.peek_at(1) == Some('\n') => self.pos += 2,
+                '|' | '&' | ';' | '(' | ')' | '>' | '<' => self.read_op(c)?,
+                _ => {
+                    let word = self.read_word()?;
+                    self.tokens.push(Token::Word(word));
+                }
+            }
+        }
+        if !self.pending.is_empty() {
+            return Err(ParseError::Incomplete);
+        }
+        Ok((self.tokens, self.heredocs))
+    }
+
+    fn read_op(&mut self, c: char) -> Result<(), ParseError> {
+        let next = self.peek_at(1);
+        let (op, len) = match (c, next) {
+            ('|', Some('|')) => (Op::Or, 2),
+            ('|', _) => (Op::Pipe, 1),
+            ('&', Some('&')) => (Op::And, 2),
+            ('&', _) => return syntax("background jobs ('&') are not supported"),
+            (';', _) => (Op::Semi, 1),
+            ('(', _) => (Op::LParen, 1),
+            (')', _) => (Op::RParen, 1),
+            ('>', Some('>')) => (Op::DGreat, 2),
+            ('>', _) => (Op::Great, 1),
+            ('<', Some('<')) if self.peek_at(2) == Some('-') => (Op::DLess { strip_tabs: true }, 3),
+            ('<', Some('<')) => (Op::DLess { strip_tabs: false }, 2),
+            _ => return syntax("input redirection '<' is not supported"),
+        };
+        self.pos += len;
+
+        if let Op::DLess { strip_tabs } = op {
+            // heredoc 的结束标记：带引号时正文不做展开
+            while matches!(self.peek(), Some(' ' | '\t')) {
+                self.pos += 1;
+            }
+            let word = self.read_word()?;
+            let expand = word
+                .0
+                .iter()
+                .all(|p| matches!(p, WordPart::Lit { quoted: false, .. }));
+            let delim: String = word
+                .0
+                .iter()
+                .map(|p| match p {
+                    WordPart::Lit { text, .. } => text.clone(),
+                    _ => String::new(),
+                })
+                .collect();
+            if delim.is_empty() {
+                return syntax("missing delimiter after '<<'");
+            }
+            let id = self.heredocs.len();
+            self.heredocs.push(Heredoc {
+                body: Word::default(),
+            });
+            self.pending.push((id, delim, strip_tabs, expand));
+            self.tokens.push(Token::Heredoc(id));
+        } else {
+            self.tokens.push(Token::Op(op));
+        }
+        Ok(())
+    }
+
+    // 换行之后依次读取这一行上所有 heredoc 的正文
+    fn read_heredoc_bodies(&mut self) -> Result<(), ParseError> {
+        for (id, delim, strip_tabs, expand) in std::mem::take(&mut self.pending) {
+            let mut body = String::new();
+            loop {
+                if self.pos >= self.chars.len() {
+                    return Err(ParseError::Incomplete);
+                }
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                let mut line: String = self.chars[start..self.pos].iter().collect();
+                let terminated = self.peek() == Some('\n');
+                if terminated {
+                    self.pos += 1;
+                }
+                if strip_tabs {
+                    line = line.trim_start_matches('\t').to_string();
+                }
+                if line.trim_end_matches('\r') == delim {
+                    break;
+                }
+                if !terminated {
+                    // 最后一行还没有换行，结束标记可能还在后面
+                    return Err(ParseError::Incomplete);
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            self.heredocs[id].body = if expand {
+                heredoc_word(&body)?
+            } else {
+                Word(vec![WordPart::Lit {
+                    text: body,
+                    quoted: true,
+                }])
+            };
+        }
+        Ok(())
+    }
+
+    fn read_word(&mut self) -> Result<Word, ParseError> {
+        let mut parts = Vec::new();
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | '|' | '&' | ';' | '(' | ')' | '<' | '>' => break,
+                '\'' => {
+                    self.pos += 1;
+                    let mut text = String::new();
+                    loop {
+                        match self.peek() {
+                            Some('\'') => break,
+                            Some(c) => text.push(c),
+                            None => return Err(ParseError::Incomplete),
+                        }
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                    push_lit(&mut parts, &text, true);
+                }
+                '"' => {
+                    self.pos += 1;
+                    self.read_double_quoted(&mut parts)?;
+                }
+                '\\' => match self.peek_at(1) {
+                    Some('\n') => self.pos += 2,
+                    Some(c) => {
+                        self.pos += 2;
+                        push_lit(&mut parts, &c.to_string(), true);
+                    }
+                    None => return Err(ParseError::Incomplete),
+                },
+                '$' => {
+                    self.pos += 1;
+                    self.read_dollar(&mut parts, false)?;
+                }
+                _ => {
+                    self.pos += 1;
+                    push_lit(&mut parts, &c.to_string(), false);
+                }
+            }
+        }
+        Ok(Word(parts))
+    }
+
+    // 双引号内只有 \" \\ \$ \` 和换行可以转义，$ 展开仍然有效
+    fn read_double_quoted(&mut self, parts: &mut Vec<WordPart>) -> Result<(), ParseError> {
+        let before = parts.len();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(ParseError::Incomplete);
+            };
+            self.pos += 1;
+            match c {
+                '"' => {
+                    // 空的 "" 也是一个单词
+                    if parts.len() == before {
+                        push_lit(parts, "", true);
+                    }
+                    return Ok(());
+                }
+                '\\' => match self.peek() {
+                    Some(c @ ('"' | '\\' | '$' | '`')) => {
+                        self.pos += 1;
+                        push_lit(parts, &c.to_string(), true);
+                    }
+                    Some('\n') => self.pos += 1,
+                    Some(_) => push_lit(parts, "\\", true),
+                    None => return Err(ParseError::Incomplete),
+                },
+                '$' => self.read_dollar(parts, true)?,
+                _ => push_lit(parts, &c.to_string(), true),
+            }
+        }
+    }
+
+    // $ 之后的内容：名字、${名字}、特殊参数、$(命令) 或 $((算术))
+    fn read_dollar(&mut self, parts: &mut Vec<WordPart>, quoted: bool) -> Result<(), ParseError> {
+        match self.peek() {
+            Some('(') if self.peek_at(1) == Some('(') => {
+                self.pos += 2;
+                let expr = self.read_until_close(2)?;
+                parts.push(WordPart::Arith { expr, quoted });
+            }
+            Some('(') => {
+                self.pos += 1;
+                let source = self.read_until_close(1)?;
+                parts.push(WordPart::Command { source, quoted });
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut name = String::new();
+                loop {
+                    match self.peek() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(ParseError::Incomplete),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                let special = name.len() == 1 && "?#@*0".contains(&name);
+                if !special && !is_name(&name) && name.parse::<usize>().is_err() {
+                    return syntax(&format!("bad substitution '${{{}}}'", name));
+                }
+                parts.push(WordPart::Param { name, quoted });
+            }
+            Some(c) if "?#@*".contains(c) || c.is_ascii_digit() => {
+                self.pos += 1;
+                parts.push(WordPart::Param {
+                    name: c.to_string(),
+                    quoted,
+                });
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    name.push(c);
+                    self.pos += 1;
+                }
+                parts.push(WordPart::Param { name, quoted });
+            }
+            // 后面不是合法的名字时 $ 就是普通字符
+            _ => push_lit(parts, "$", quoted),
+        }
+        Ok(())
+    }
+
+    // 读到与开头匹配的 depth 个右括号为止，跳过引号中的括号；返回括号内的原文
+    fn read_until_close(&mut self, depth: usize) -> Result<String, ParseError> {
+        let start = self.pos;
+        let mut level = 0;
+        let mut quote = None;
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(ParseError::Incomplete);
+            };
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some('"'), '\\') => self.pos += 1,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '\\') => self.pos += 1,
+                (None, '(') => level += 1,
+                (None, ')') if level > 0 => level -= 1,
+                (None, ')') => {
+                    let inner: String = self.chars[start..self.pos].iter().collect();
+                    if depth == 2 {
+                        if self.peek_at(1) != Some(')') {
+                            return syntax("expected '))' to end arithmetic expansion");
+                        }
+                        self.pos += 2;
+                    } else {
+                        self.pos += 1;
+                    }
+                    return Ok(inner);
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+// 展开 heredoc 的正文：与双引号内相同，但 " 不是特殊字符
+fn heredoc_word(body: &str) -> Result<Word, ParseError> {
+    let mut lexer = Lexer {
+        chars: body.chars().collect(),
+        pos: 0,
+        tokens: Vec::new(),
+        heredocs: Vec::new(),
+        pending: Vec::new(),
+    };
+    let mut parts = Vec::new();
+    while let Some(c) = lexer.peek() {
+        lexer.pos += 1;
+        match c {
+            '\\' => match lexer.peek() {
+                Some(c @ ('\\' | '$' | '`')) => {
+                    lexer.pos += 1;
+                    push_lit(&mut parts, &c.to_string(), true);
+                }
+                _ => push_lit(&mut parts, "\\", true),
+            },
+            // 正文已经完整，其中未闭合的 $( 是语法错误而不是需要继续读入
+            '$' => lexer.read_dollar(&mut parts, true).map_err(|e| match e {
+                ParseError::Incomplete => {
+                    ParseError::Syntax("unterminated expansion in here-document".to_string())
+                }
+                e => e,
+            })?,
+            _ => push_lit(&mut parts, &c.to_string(), true),
+        }
+    }
+    Ok(Word(parts))
+}
+
+// 追加字面内容，与前一个引号状态相同的字面部分合并
+fn push_lit(parts: &mut Vec<WordPart>, s: &str, quoted: bool) {
+    if let Some(WordPart::Lit { text, quoted: q }) = parts.last_mut()
+        && *q == quoted
+    {
+        text.push_str(s);
+        return;
+    }
+    parts.push(WordPart::Lit {
+        text: s.to_string(),
+        quoted,
+    });
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    heredocs: Vec<Heredoc>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<Op> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    // 当前位置是否是关键字（只有不带引号的单词才可能是关键字）
+    fn peek_keyword(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(w)) => w.as_plain().filter(|s| RESERVED.contains(s)),
+            _ => None,
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek(), Some(Token::Newline)) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> Result<(), ParseError> {
+        self.skip_newlines();
+        match self.peek_keyword() {
+            Some(k) if k == kw => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ if self.peek().is_none() => Err(ParseError::Incomplete),
+            _ => syntax(&format!("expected '{}'", kw)),
+        }
+    }
+
+    // 解析命令序列，直到遇到 terminators 中的关键字或 ")"；
+    // terminators 为空表示顶层，一直解析到文本末尾
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<Vec<AndOr>, ParseError> {
+        let mut list = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None if terminators.is_empty() => return Ok(list),
+                None => return Err(ParseError::Incomplete),
+                Some(Token::Op(Op::Semi)) => return syntax("unexpected ';'"),
+                Some(Token::Op(Op::RParen)) if !terminators.is_empty() => return Ok(list),
+                _ => {}
+            }
+            if let Some(kw) = self.peek_keyword()
+                && terminators.contains(&kw)
+            {
+                return Ok(list);
+            }
+
+            list.push(self.parse_and_or()?);
+            match self.peek() {
+                Some(Token::Op(Op::Semi)) | Some(Token::Newline) => self.pos += 1,
+                None => {}
+                Some(Token::Op(Op::RParen)) if !terminators.is_empty() => {}
+                Some(_)
+                    if self
+                        .peek_keyword()
+                        .is_some_and(|k| terminators.contains(&k)) => {}
+                Some(_) => return syntax("unexpected token"),
+            }
+        }
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let and = match self.peek_op() {
+                Some(Op::And) => true,
+                Some(Op::Or) => false,
+                _ => break,
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            if self.peek().is_none() {
+                return Err(ParseError::Incomplete);
+            }
+            rest.push((and, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negate = self.peek_keyword() == Some("!");
+        if negate {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while self.peek_op() == Some(Op::Pipe) {
+            self.pos += 1;
+            self.skip_newlines();
+            if self.peek().is_none() {
+                return Err(ParseError::Incomplete);
+            }
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { negate, commands })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        let kind = match self.peek_keyword() {
+            Some("if") => self.parse_if()?,
+            Some("for") => self.parse_for()?,
+            Some("while") | Some("until") => self.parse_while()?,
+            Some("{") => {
+                self.pos += 1;
+                let body = self.parse_list(&["}"])?;
+                self.expect_keyword("}")?;
+                CommandKind::Group(body)
+            }
+            Some("function") => {
+                self.pos += 1;
+                let Some(Token::Word(w)) = self.peek() else {
+                    return syntax("expected function name");
+                };
+                let name = w.as_plain().unwrap_or_default().to_string();
+                self.pos += 1;
+                if self.peek_op() == Some(Op::LParen) {
+                    self.pos += 1;
+                    if self.peek_op() != Some(Op::RParen) {
+                        return syntax("expected ')'");
+                    }
+                    self.pos += 1;
+                }
+                self.parse_function_body(name)?
+            }
+            Some(kw) if kw != "!" => return syntax(&format!("unexpected '{}'", kw)),
+            _ => {
+                // name() 形式的函数定义
+                if let Some(Token::Word(w)) = self.peek()
+                    && let Some(name) = w.as_plain().filter(|n| is_name(n))
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::Op(Op::LParen)))
+                {
+                    let name = name.to_string();
+                    if !matches!(self.tokens.get(self.pos + 2), Some(Token::Op(Op::RParen))) {
+                        return syntax("expected ')' after function name");
+                    }
+                    self.pos += 3;
+                    self.parse_function_body(name)?
+                } else {
+                    return self.parse_simple();
+                }
+            }
+        };
+
+        let mut command = Command {
+            kind,
+            redirect: None,
+            heredoc: None,
+        };
+        self.parse_redirects(&mut command)?;
+        Ok(command)
+    }
+
+    fn parse_function_body(&mut self, name: String) -> Result<CommandKind, ParseError> {
+        if !is_name(&name) {
+            return syntax(&format!("invalid function name '{}'", name));
+        }
+        self.skip_newlines();
+        if self.peek().is_none() {
+            return Err(ParseError::Incomplete);
+        }
+        let body = self.parse_command()?;
+        if matches!(body.kind, CommandKind::Simple { .. }) {
+            return syntax("function body must be a compound command");
+        }
+        Ok(CommandKind::Function {
+            name,
+            body: Rc::new(body),
+        })
+    }
+
+    fn parse_if(&mut self) -> Result<CommandKind, ParseError> {
+        self.pos += 1;
+        let mut branches = Vec::new();
+        let mut else_body = None;
+        loop {
+            let cond = self.parse_list(&["then"])?;
+            self.expect_keyword("then")?;
+            let body = self.parse_list(&["elif", "else", "fi"])?;
+            branches.push((cond, body));
+            match self.peek_keyword() {
+                Some("elif") => self.pos += 1,
+                Some("else") => {
+                    self.pos += 1;
+                    else_body = Some(self.parse_list(&["fi"])?);
+                    self.expect_keyword("fi")?;
+                    break;
+                }
+                _ => {
+                    self.expect_keyword("fi")?;
+                    break;
+                }
+            }
+        }
+        Ok(CommandKind::If {
+            branches,
+            else_body,
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<CommandKind, ParseError> {
+        self.pos += 1;
+        let var = match self.peek() {
+            Some(Token::Word(w)) if w.as_plain().is_some_and(is_name) => {
+                w.as_plain().unwrap().to_string()
+            }
+            None => return Err(ParseError::Incomplete),
+            _ => return syntax("expected variable name after 'for'"),
+        };
+        self.pos += 1;
+
+        let mut items = None;
+        self.skip_newlines();
+        if self.peek_keyword() == Some("in") {
+            self.pos += 1;
+            let mut words = Vec::new();
+            while let Some(Token::Word(w)) = self.peek() {
+                words.push(w.clone());
+                self.pos += 1;
+            }
+            items = Some(words);
+        }
+        match self.peek() {
+            Some(Token::Op(Op::Semi)) | Some(Token::Newline) => self.pos += 1,
+            None => return Err(ParseError::Incomplete),
+            _ => {}
+        }
+        self.expect_keyword("do")?;
+        let body = self.parse_list(&["done"])?;
+        self.expect_keyword("done")?;
+        Ok(CommandKind::For { var, items, body })
+    }
+
+    fn parse_while(&mut self) -> Result<CommandKind, ParseError> {
+        let until = self.peek_keyword() == Some("until");
+        self.pos += 1;
+        let cond = self.parse_list(&["do"])?;
+        self.expect_keyword("do")?;
+        let body = self.parse_list(&["done"])?;
+        self.expect_keyword("done")?;
+        Ok(CommandKind::While { until, cond, body })
+    }
+
+    fn parse_simple(&mut self) -> Result<Command, ParseError> {
+        let mut assigns = Vec::new();
+        let mut words = Vec::new();
+        let mut command = Command {
+            kind: CommandKind::Simple {
+                assigns: Vec::new(),
+                words: Vec::new(),
+            },
+            redirect: None,
+            heredoc: None,
+        };
+
+        loop {
+            match self.peek() {
+                Some(Token::Word(w)) => {
+                    // 命令名之前的 NAME=value 是变量赋值
+                    if words.is_empty()
+                        && let Some((name, value)) = split_assignment(w)
+                    {
+                        assigns.push((name, value));
+                    } else {
+                        words.push(w.clone());
+                    }
+                    self.pos += 1;
+                }
+                Some(Token::Op(Op::Great | Op::DGreat)) | Some(Token::Heredoc(_)) => {
+                    self.parse_redirects(&mut command)?;
+                }
+                _ => break,
+            }
+        }
+        if assigns.is_empty() && words.is_empty() {
+            return match self.peek() {
+                None => Err(ParseError::Incomplete),
+                Some(Token::Op(Op::LParen)) => syntax("subshells '( )' are not supported"),
+                _ => syntax("missing command"),
+            };
+        }
+        command.kind = CommandKind::Simple { assigns, words };
+        Ok(command)
+    }
+
+    fn parse_redirects(&mut self, command: &mut Command) -> Result<(), ParseError> {
+        loop {
+            match self.peek() {
+                Some(Token::Op(op @ (Op::Great | Op::DGreat))) => {
+                    let append = *op == Op::DGreat;
+                    self.pos += 1;
+                    let Some(Token::Word(target)) = self.peek() else {
+                        return syntax("missing file name after '>'");
+                    };
+                    let target = target.clone();
+                    self.pos += 1;
+                    command.redirect = Some(if append {
+                        Redirect::Append(target)
+                    } else {
+                        Redirect::Truncate(target)
+                    });
+                }
+                Some(Token::Heredoc(id)) => {
+                    command.heredoc = Some(self.heredocs[*id].clone());
+                    self.pos += 1;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+// "NAME=value" 形式的单词拆成名字和值
+fn split_assignment(word: &Word) -> Option<(String, Word)> {
+    let Some(WordPart::Lit {
+        text,
+        quoted: false,
+    }) = word.0.first()
+    else {
+        return None;
+    };
+    let (name, first_value) = text.split_once('=')?;
+    if !is_name(name) {
+        return None;
+    }
+    let mut value = Vec::new();
+    if !first_value.is_empty() {
+        value.push(WordPart::Lit {
+            text: first_value.to_string(),
+            quoted: false,
+        });
+    }
+    value.extend(word.0[1..].iter().cloned());
+    Some((name.to_string(), Word(value)))
+}
+
+// 把一段文本解析成命令序列；空文本返回空的 Vec
+pub fn parse_script(text: &str) -> Result<Vec<AndOr>, ParseError> {
+    let lexer = Lexer {
+        chars: text.chars().collect(),
+        pos: 0,
+        tokens: Vec::new(),
+        heredocs: Vec::new(),
+        pending: Vec::new(),
+    };
+    let (tokens, heredocs) = lexer.run()?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        heredocs,
+    };
+    let list = parser.parse_list(&[])?;
+    if parser.pos < parser.tokens.len() {
+        return syntax("unexpected token");
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 只有一条简单命令的脚本中的单词
+    fn words(text: &str) -> Vec<Word> {
+        let list = parse_script(text).unwrap();
+        assert_eq!(list.len(), 1);
+        match &list[0].first.commands[0].kind {
+            CommandKind::Simple { words, .. } => words.clone(),
+            kind => panic!("not a simple command: {:?}", kind),
+        }
+    }
+
+    fn lit(text: &str, quoted: bool) -> WordPart {
+        WordPart::Lit {
+            text: text.to_string(),
+            quoted,
+        }
+    }
+
+    #[test]
+    fn quoting_and_escapes() {
+        let w = words(r#"echo 'a $b' "c $d" e\ f "g\"h\\" 'i'j"#);
+        assert_eq!(w[0].as_plain(), Some("echo"));
+        assert_eq!(w[1], Word(vec![lit("a $b", true)]));
+        assert_eq!(
+            w[2],
+            Word(vec![
+                lit("c ", true),
+                WordPart::Param {
+                    name: "d".to_string(),
+                    quoted: true
+                }
+            ])
+        );
+        assert_eq!(
+            w[3],
+            Word(vec![lit("e", false), lit(" ", true), lit("f", false)])
+        );
+        assert_eq!(w[4], Word(vec![lit("g\"h\\", true)]));
+        assert_eq!(w[5], Word(vec![lit("i", true), lit("j", false)]));
+        assert_eq!(w.len(), 6);
+
+        assert_eq!(
+            parse_script("echo 'abc").unwrap_err(),
+            ParseError::Incomplete
+        );
+        assert_eq!(
+            parse_script("echo \"abc").unwrap_err(),
+            ParseError::Incomplete
+        );
+        assert!(parse_script("# comment only\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn expansions() {
+        let w = words("echo ${x}y $(ls /) $((1 + (2 * 3)))");
+        assert_eq!(
+            w[1],
+            Word(vec![
+                WordPart::Param {
+                    name: "x".to_string(),
+                    quoted: false
+                },
+                lit("y", false)
+            ])
+        );
+        assert_eq!(
+            w[2],
+            Word(vec![WordPart::Command {
+                source: "ls /".to_string(),
+                quoted: false
+            }])
+        );
+        assert_eq!(
+            w[3],
+            Word(vec![WordPart::Arith {
+                expr: "1 + (2 * 3)".to_string(),
+                quoted: false
+            }])
+        );
+        assert_eq!(
+            parse_script("echo $((1 + 2").unwrap_err(),
+            ParseError::Incomplete
+        );
+        assert!(matches!(
+            parse_script("echo $((1 + 2) )"),
+            Err(ParseError::Syntax(_))
+        ));
+        assert_eq!(
+            parse_script("echo $(ls").unwrap_err(),
+            ParseError::Incomplete
+        );
+    }
+
+    #[test]
+    fn heredocs() {
+        let list = parse_script("cat <<END\nhello $x\nEND\necho after\n").unwrap();
+        assert_eq!(list.len(), 2);
+        let body = &list[0].first.commands[0].heredoc.as_ref().unwrap().body;
+        assert!(body.0.contains(&WordPart::Param {
+            name: "x".to_string(),
+            quoted: true
+        }));
+
+        // 结束标记带引号时正文不展开；<<- 去掉行首的 tab
+        let list = parse_script("cat <<-'END'\n\traw $x\n\tEND\n").unwrap();
+        let body = &list[0].first.commands[0].heredoc.as_ref().unwrap().body;
+        assert_eq!(body, &Word(vec![lit("raw $x\n", true)]));
+
+        assert_eq!(
+            parse_script("cat <<END\nno end\n").unwrap_err(),
+            ParseError::Incomplete
+        );
+    }
+
+    #[test]
+    fn compound_commands() {
+        let list =
+            parse_script("f() { return 1; }\nif a; then b; elif c; then d; else e; fi").unwrap();
+        assert!(matches!(
+            list[0].first.commands[0].kind,
+            CommandKind::Function { .. }
+        ));
+        match &list[1].first.commands[0].kind {
+            CommandKind::If {
+                branches,
+                else_body,
+            } => {
+                assert_eq!(branches.len(), 2);
+                assert!(else_body.is_some());
+            }
+            kind => panic!("not an if: {:?}", kind),
+        }
+
+        let list = parse_script("! a | b && c || d").unwrap();
+        assert!(list[0].first.negate);
+        assert_eq!(list[0].first.commands.len(), 2);
+        assert_eq!(list[0].rest.len(), 2);
+        assert!(list[0].rest[0].0 && !list[0].rest[1].0);
+
+        assert_eq!(
+            parse_script("while true; do").unwrap_err(),
+            ParseError::Incomplete
+        );
+        assert!(matches!(parse_script("fi"), Err(ParseError::Syntax(_))));
+        assert!(matches!(parse_script("a &"), Err(ParseError::Syntax(_))));
+        assert!(matches!(parse_script("a < b"), Err(ParseError::Syntax(_))));
+    }
+}
@@ -0,0 +1,883 @@
+use crate::error::FsError;
+use crate::fs::{Metadata, MyFileSystem, ReadFs};
+use crate::layout::*;
+use crate::report;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// tar 归档（ustar / pax / GNU 长文件名）与镜像之间的转换，以及不解包直接浏览 tar 的只读视图
+// 归档格式：每个条目是一个 512 字节的头部，后面跟着按 512 字节对齐的数据，归档以两个全零块结束
+
+const TAR_BLOCK: u64 = 512;
+
+// 条目类型，对应头部的 typeflag 字段
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum EntryKind {
+    File,
+    HardLink,
+    Symlink,
+    Directory,
+    Other(u8),
+}
+
+// 解析后的一个归档条目，pax / GNU 扩展头部中的字段已经合并进来
+struct TarEntry {
+    path: String,
+    kind: EntryKind,
+    perm: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    size: u64,
+    link: String,
+    data_offset: u64, // 数据在归档文件中的起始位置
+}
+
+// pax 扩展头部可以覆盖的字段
+#[derive(Default, Clone)]
+struct PaxOverrides {
+    path: Option<String>,
+    link: Option<String>,
+    size: Option<u64>,
+    mtime: Option<u64>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl PaxOverrides {
+    // 解析 "<长度> <key>=<value>\n" 形式的记录，不认识的 key 忽略
+    fn parse(&mut self, data: &[u8]) -> Result<(), FsError> {
+        let mut rest = data;
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or(FsError::InvalidArgument)?;
+            let len: usize = std::str::from_utf8(&rest[..space])
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or(FsError::InvalidArgument)?;
+            if len <= space + 1 || len > rest.len() || rest[len - 1] != b'\n' {
+                return Err(FsError::InvalidArgument);
+            }
+            let record = String::from_utf8_lossy(&rest[space + 1..len - 1]).into_owned();
+            rest = &rest[len..];
+
+            let (key, value) = record.split_once('=').ok_or(FsError::InvalidArgument)?;
+            match key {
+                "path" => self.path = Some(value.to_string()),
+                "linkpath" => self.link = Some(value.to_string()),
+                "size" => self.size = value.parse().ok(),
+                // mtime 可以带小数部分，只取整数秒
+                "mtime" => self.mtime = value.split('.').next().and_then(|s| s.parse().ok()),
+                "uid" => self.uid = value.parse().ok(),
+                "gid" => self.gid = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // 用 other 中出现的字段覆盖自己（局部头部覆盖全局头部）
+    fn merge(&mut self, other: &PaxOverrides) {
+        if other.path.is_some() {
+            self.path = other.path.clone();
+        }
+        if other.link.is_some() {
+            self.link = other.link.clone();
+        }
+        self.size = other.size.or(self.size);
+        self.mtime = other.mtime.or(self.mtime);
+        self.uid = other.uid.or(self.uid);
+        self.gid = other.gid.or(self.gid);
+    }
+}
+
+// 解析八进制数字段；最高位为 1 时是 GNU 的 base-256 编码
+fn parse_number(field: &[u8]) -> Result<u64, FsError> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        let mut value: u64 = (field[0] & 0x7f) as u64;
+        for &b in &field[1..] {
+            value = value.checked_mul(256).ok_or(FsError::InvalidArgument)? | b as u64;
+        }
+        return Ok(value);
+    }
+    let text: String = field
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| FsError::InvalidArgument)
+}
+
+// 读取以 NUL 结尾（或占满整个字段）的字符串字段
+fn parse_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// 校验和：把校验和字段视为 8 个空格后，头部所有字节的无符号和
+fn header_checksum(header: &[u8; 512]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+        .sum()
+}
+
+// 规范化归档中的路径：去掉开头的 "/" 和 "./"、末尾的 "/" 以及 "." 分量
+// 含有 ".." 的路径可能逃出目标目录，返回 None
+fn normalize_path(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+// 顺序读取归档中的条目
+struct TarReader {
+    file: File,
+    pos: u64, // 下一个头部的位置
+    global: PaxOverrides,
+}
+
+impl TarReader {
+    fn open(tar_path: &Path) -> Result<Self, FsError> {
+        Ok(TarReader {
+            file: File::open(tar_path)?,
+            pos: 0,
+            global: PaxOverrides::default(),
+        })
+    }
+
+    // 读取一块头部；文件结束或遇到全零块时返回 None
+    fn read_header(&mut self) -> Result<Option<[u8; 512]>, FsError> {
+        let mut header = [0u8; 512];
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        match self.file.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        if header.iter().all(|&b| b == 0) {
+            return Ok(None);
+        }
+        if parse_number(&header[148..156])? != header_checksum(&header) {
+            return Err(FsError::InvalidArgument);
+        }
+        self.pos += TAR_BLOCK;
+        Ok(Some(header))
+    }
+
+    // 读出扩展头部（pax、GNU 长文件名）的数据部分
+    fn read_data(&mut self, size: u64) -> Result<Vec<u8>, FsError> {
+        // 扩展头部只保存路径等短字符串，过大的多半是损坏的归档
+        if size > 1 << 20 {
+            return Err(FsError::InvalidArgument);
+        }
+        let mut data = vec![0u8; size as usize];
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        self.file.read_exact(&mut data)?;
+        self.pos += size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+        Ok(data)
+    }
+
+    // 返回下一个普通条目，中途遇到的扩展头部合并到该条目上
+    fn next_entry(&mut self) -> Result<Option<TarEntry>, FsError> {
+        let mut local = PaxOverrides::default();
+        loop {
+            let Some(header) = self.read_header()? else {
+                return Ok(None);
+            };
+            let size = parse_number(&header[124..136])?;
+            match header[156] {
+                b'x' => {
+                    let data = self.read_data(size)?;
+                    local.parse(&data)?;
+                    continue;
+                }
+                b'g' => {
+                    let data = self.read_data(size)?;
+                    self.global.parse(&data)?;
+                    continue;
+                }
+                b'L' => {
+                    local.path = Some(parse_string(&self.read_data(size)?));
+                    continue;
+                }
+                b'K' => {
+                    local.link = Some(parse_string(&self.read_data(size)?));
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut fields = self.global.clone();
+            fields.merge(&local);
+
+            let mut path = parse_string(&header[0..100]);
+            // ustar 把长路径拆成 prefix 和 name 两部分（GNU 格式的这块区域另有用途）
+            if &header[257..263] == b"ustar\0" {
+                let prefix = parse_string(&header[345..500]);
+                if !prefix.is_empty() {
+                    path = format!("{}/{}", prefix, path);
+                }
+            }
+            let kind = match header[156] {
+                b'0' | 0 | b'7' => EntryKind::File,
+                b'1' => EntryKind::HardLink,
+                b'2' => EntryKind::Symlink,
+                b'5' => EntryKind::Directory,
+                other => EntryKind::Other(other),
+            };
+            // 老式归档没有目录类型，以 "/" 结尾的普通文件就是目录
+            let kind = if kind == EntryKind::File && path.ends_with('/') {
+                EntryKind::Directory
+            } else {
+                kind
+            };
+
+            let size = fields.size.unwrap_or(size);
+            let entry = TarEntry {
+                path: fields.path.unwrap_or(path),
+                kind,
+                perm: parse_number(&header[100..108])? as u32 & 0o7777,
+                uid: fields
+                    .uid
+                    .unwrap_or(parse_number(&header[108..116])? as u32),
+                gid: fields
+                    .gid
+                    .unwrap_or(parse_number(&header[116..124])? as u32),
+                mtime: fields.mtime.unwrap_or(parse_number(&header[136..148])?),
+                size,
+                link: fields
+                    .link
+                    .unwrap_or_else(|| parse_string(&header[157..257])),
+                data_offset: self.pos,
+            };
+            // 硬链接和目录条目不带数据
+            if matches!(entry.kind, EntryKind::File | EntryKind::Other(_)) {
+                self.pos += size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+            }
+            return Ok(Some(entry));
+        }
+    }
+}
+
+// tar_import：把归档中的条目解包到镜像的 dest 目录下，返回导入的条目数
+// 缺失的父目录自动创建；设备文件等镜像不支持的类型跳过
+// 单个条目失败（如文件名过长）时报告并继续，最后返回第一个错误
+pub fn tar_import(fs: &mut MyFileSystem, tar_path: &Path, dest: &str) -> Result<usize, FsError> {
+    fs.create_dir_all(dest)?;
+    let mut reader = TarReader::open(tar_path)?;
+    let mut count = 0;
+    let mut first_error = None;
+    // 往目录里添加条目会改变目录的 mtime，所以目录的时间戳最后统一设置
+    let mut dir_times = Vec::new();
+
+    while let Some(entry) = reader.next_entry()? {
+        let Some(rel) = normalize_path(&entry.path) else {
+            report("tar-import", Some(&entry.path), "skipping unsafe path");
+            continue;
+        };
+        if rel.is_empty() && entry.kind != EntryKind::Directory {
+            continue;
+        }
+        match import_entry(fs, &mut reader.file, &entry, dest, &rel, &mut dir_times) {
+            Ok(true) => count += 1,
+            Ok(false) => {}
+            Err(e) => {
+                report("tar-import", Some(&entry.path), &e.to_string());
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    for (path, mtime) in dir_times.into_iter().rev() {
+        let mtime = fs.clamp_time(mtime);
+        fs.set_times(&path, mtime, mtime)?;
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(count),
+    }
+}
+
+// 导入一个条目，返回是否真的创建了它（不支持的类型返回 false）
+fn import_entry(
+    fs: &mut MyFileSystem,
+    archive: &mut File,
+    entry: &TarEntry,
+    dest: &str,
+    rel: &str,
+    dir_times: &mut Vec<(String, u64)>,
+) -> Result<bool, FsError> {
+    let target = MyFileSystem::join_path(dest, rel);
+    if !rel.is_empty() {
+        fs.create_dir_all(MyFileSystem::split_path(&target).0)?;
+    }
+
+    // 已存在的同名条目：目录保留，其余的先删除再按归档内容重建
+    let existing = fs.lstat(&target).ok().map(|m| m.kind);
+    match (entry.kind, existing) {
+        (EntryKind::Other(_), _) => {
+            report("tar-import", Some(&entry.path), "skipping special file");
+            return Ok(false);
+        }
+        (EntryKind::Directory, Some(InodeType::Directory)) => {}
+        (_, Some(InodeType::Directory)) => return Err(FsError::IsADirectory),
+        (_, Some(_)) => fs.remove(&target, false)?,
+        _ => {}
+    }
+
+    match entry.kind {
+        EntryKind::Directory => {
+            if existing != Some(InodeType::Directory) {
+                fs.create_dir(&target)?;
+            }
+            dir_times.push((target.clone(), entry.mtime));
+        }
+        EntryKind::File => {
+            fs.create_file(&target)?;
+            archive.seek(SeekFrom::Start(entry.data_offset))?;
+            let mut buf = [0u8; BLOCK_SIZE];
+            let mut offset = 0;
+            while (offset as u64) < entry.size {
+                let n = (entry.size - offset as u64).min(BLOCK_SIZE as u64) as usize;
+                archive.read_exact(&mut buf[..n])?;
+                fs.pwrite(&target, offset, &buf[..n])?;
+                offset += n;
+            }
+        }
+        EntryKind::Symlink => {
+            // 符号链接本身的权限和时间戳没有意义，只记录属主
+            fs.symlink(&entry.link, &target)?;
+            fs.set_owner(&target, entry.uid, entry.gid)?;
+            return Ok(true);
+        }
+        EntryKind::HardLink => {
+            let source = normalize_path(&entry.link).ok_or(FsError::InvalidArgument)?;
+            fs.link(&MyFileSystem::join_path(dest, &source), &target)?;
+            return Ok(true);
+        }
+        EntryKind::Other(_) => unreachable!(),
+    }
+
+    fs.set_permissions(&target, entry.perm)?;
+    fs.set_owner(&target, entry.uid, entry.gid)?;
+    let mtime = fs.clamp_time(entry.mtime);
+    fs.set_times(&target, mtime, mtime)?;
+    Ok(true)
+}
+
+// 写一个 pax 扩展头部，记录 ustar 字段放不下的值
+fn write_pax_header(
+    out: &mut impl Write,
+    name: &str,
+    records: &[(&str, String)],
+) -> Result<(), FsError> {
+    let mut data = Vec::new();
+    for (key, value) in records {
+        // 记录长度包含长度字段本身，长度字段变长时要重新计算
+        let body = format!(" {}={}\n", key, value);
+        let mut len = body.len() + 1;
+        while format!("{}", len).len() + body.len() != len {
+            len = format!("{}", len).len() + body.len();
+        }
+        data.extend_from_slice(format!("{}{}", len, body).as_bytes());
+    }
+
+    let pax_name = format!("PaxHeaders/{}", name.rsplit('/').next().unwrap_or(name));
+    let header = build_header(&pax_name, b'x', 0o644, 0, 0, 0, data.len() as u64, "");
+    out.write_all(&header)?;
+    out.write_all(&data)?;
+    write_padding(out, data.len() as u64)
+}
+
+fn write_padding(out: &mut impl Write, size: u64) -> Result<(), FsError> {
+    let pad = (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK;
+    out.write_all(&vec![0u8; pad as usize])?;
+    Ok(())
+}
+
+// 把数字按八进制写进字段，末尾留一个 NUL
+fn put_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{:0width$o}", value, width = digits);
+    let start = text.len().saturating_sub(digits);
+    field[..digits].copy_from_slice(&text.as_bytes()[start..]);
+    field[digits] = 0;
+}
+
+fn put_string(field: &mut [u8], value: &str) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+}
+
+// 尝试把路径拆成 ustar 的 prefix（最多 155 字节）和 name（最多 100 字节）
+fn split_ustar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_header(
+    path: &str,
+    typeflag: u8,
+    perm: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    size: u64,
+    link: &str,
+) -> [u8; 512] {
+    let mut header = [0u8; 512];
+    let (prefix, name) = split_ustar_path(path).unwrap_or(("", path));
+    put_string(&mut header[0..100], name);
+    put_octal(&mut header[100..108], perm as u64);
+    put_octal(&mut header[108..116], uid.min(0o7777777) as u64);
+    put_octal(&mut header[116..124], gid.min(0o7777777) as u64);
+    put_octal(&mut header[124..136], size);
+    put_octal(&mut header[136..148], mtime.min(0o77777777777));
+    header[156] = typeflag;
+    put_string(&mut header[157..257], link);
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    put_string(&mut header[345..500], prefix);
+
+    let checksum = header_checksum(&header);
+    put_octal(&mut header[148..155], checksum);
+    header[155] = b' ';
+    header
+}
+
+// tar_export：把 view 中的文件或目录（递归）打包成 ustar 归档，返回写入的条目数
+// 路径、链接目标或属主放不进 ustar 头部时加一个 pax 扩展头部
+pub fn tar_export(
+    view: &mut impl ReadFs,
+    fs_path: &str,
+    tar_path: &Path,
+) -> Result<usize, FsError> {
+    let meta = view.lstat(fs_path)?;
+    let mut out = BufWriter::new(File::create(tar_path)?);
+    let mut links = HashMap::new();
+    let mut count = 0;
+
+    // 导出根目录时直接以子条目作为归档的顶层，否则以路径的最后一个分量作为顶层
+    if fs_path == "/" && meta.kind == InodeType::Directory {
+        let mut children = view.read_dir(fs_path)?;
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        for child in children {
+            let child_path = MyFileSystem::join_path(fs_path, &child.name);
+            count += export_entry(view, &child_path, &child.name, &mut out, &mut links)?;
+        }
+    } else {
+        let name = MyFileSystem::split_path(fs_path).1.to_string();
+        count += export_entry(view, fs_path, &name, &mut out, &mut links)?;
+    }
+
+    out.write_all(&[0u8; 2 * TAR_BLOCK as usize])?;
+    out.flush()?;
+    Ok(count)
+}
+
+fn export_entry(
+    view: &mut impl ReadFs,
+    fs_path: &str,
+    name: &str,
+    out: &mut impl Write,
+    links: &mut HashMap<u32, String>,
+) -> Result<usize, FsError> {
+    let meta = view.lstat(fs_path)?;
+    let mut count = 1;
+
+    // 有多个链接的文件只在第一次出现时写数据，之后写成指向它的硬链接
+    let (typeflag, link, size) = match meta.kind {
+        InodeType::Directory => (b'5', String::new(), 0),
+        InodeType::Symlink => (b'2', view.read_link(fs_path)?, 0),
+        _ => match links.get(&meta.ino) {
+            Some(first) => (b'1', first.clone(), 0),
+            None => {
+                if meta.nlinks > 1 {
+                    links.insert(meta.ino, name.to_string());
+                }
+                (b'0', String::new(), meta.size as u64)
+            }
+        },
+    };
+    let archive_name = if meta.kind == InodeType::Directory {
+        format!("{}/", name)
+    } else {
+        name.to_string()
+    };
+
+    let mut records = Vec::new();
+    if split_ustar_path(&archive_name).is_none() || archive_name.len() > 255 {
+        records.push(("path", archive_name.clone()));
+    }
+    if link.len() > 100 {
+        records.push(("linkpath", link.clone()));
+    }
+    if meta.uid > 0o7777777 {
+        records.push(("uid", meta.uid.to_string()));
+    }
+    if meta.gid > 0o7777777 {
+        records.push(("gid", meta.gid.to_string()));
+    }
+    if !records.is_empty() {
+        write_pax_header(out, &archive_name, &records)?;
+    }
+
+    let header = build_header(
+        &archive_name,
+        typeflag,
+        meta.perm,
+        meta.uid,
+        meta.gid,
+        meta.mtime,
+        size,
+        &link,
+    );
+    out.write_all(&header)?;
+
+    if typeflag == b'0' {
+        let mut offset = 0;
+        while (offset as u64) < size {
+            let chunk = view.read_at(meta.ino, offset, BLOCK_SIZE)?;
+            if chunk.is_empty() {
+                return Err(FsError::Io(std::io::ErrorKind::UnexpectedEof));
+            }
+            out.write_all(&chunk)?;
+            offset += chunk.len();
+        }
+        write_padding(out, size)?;
+    }
+
+    if meta.kind == InodeType::Directory {
+        let mut children = view.read_dir(fs_path)?;
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        for child in children {
+            let child_path = MyFileSystem::join_path(fs_path, &child.name);
+            let child_name = format!("{}/{}", name, child.name);
+            count += export_entry(view, &child_path, &child_name, out, links)?;
+        }
+    }
+    Ok(count)
+}
+
+// 只读视图中的一个节点，编号即在 nodes 中的下标，1 号是根目录
+struct TarNode {
+    kind: InodeType,
+    perm: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    size: u64,
+    nlinks: u32,
+    data_offset: u64,
+    link: String,
+    children: Vec<DirEntry>,
+}
+
+impl TarNode {
+    fn new(kind: InodeType) -> Self {
+        TarNode {
+            kind,
+            perm: if kind == InodeType::Directory {
+                0o755
+            } else {
+                0o644
+            },
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+            size: 0,
+            nlinks: 1,
+            data_offset: 0,
+            link: String::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+// TarFs：不解包，直接通过 ReadFs 的路径 API 浏览 tar 归档
+// 打开时只扫描一遍头部建立目录树，文件数据在读取时才从归档中取出
+pub struct TarFs {
+    file: File,
+    nodes: Vec<TarNode>,
+}
+
+impl TarFs {
+    pub fn open(tar_path: &Path) -> Result<Self, FsError> {
+        let mut reader = TarReader::open(tar_path)?;
+        let mut view = TarFs {
+            file: File::open(tar_path)?,
+            nodes: vec![
+                TarNode::new(InodeType::Unused),
+                TarNode::new(InodeType::Directory),
+            ],
+        };
+
+        while let Some(entry) = reader.next_entry()? {
+            let Some(rel) = normalize_path(&entry.path) else {
+                continue;
+            };
+            let kind = match entry.kind {
+                EntryKind::File => InodeType::File,
+                EntryKind::Directory => InodeType::Directory,
+                EntryKind::Symlink => InodeType::Symlink,
+                EntryKind::HardLink => {
+                    let source = normalize_path(&entry.link).ok_or(FsError::InvalidArgument)?;
+                    let ino = view.lookup_rel(&source, false)?;
+                    if view.nodes[ino as usize].kind == InodeType::Directory {
+                        return Err(FsError::IsADirectory);
+                    }
+                    let parent = view.make_parents(&rel)?;
+                    view.insert_child(parent, MyFileSystem::split_path(&rel).1, ino);
+                    view.nodes[ino as usize].nlinks += 1;
+                    continue;
+                }
+                EntryKind::Other(_) => continue,
+            };
+            if entry.size > u32::MAX as u64 {
+                return Err(FsError::FileTooLarge);
+            }
+
+            let ino = if rel.is_empty() {
+                1
+            } else {
+                let parent = view.make_parents(&rel)?;
+                let name = MyFileSystem::split_path(&rel).1;
+                // 同名条目后出现的覆盖先出现的，目录保留已有的子条目
+                match view.child(parent, name) {
+                    Some(ino)
+                        if view.nodes[ino as usize].kind == InodeType::Directory
+                            && kind == InodeType::Directory =>
+                    {
+                        ino
+                    }
+                    _ => {
+                        view.nodes.push(TarNode::new(kind));
+                        let ino = (view.nodes.len() - 1) as u32;
+                        view.insert_child(parent, name, ino);
+                        ino
+                    }
+                }
+            };
+
+            let node = &mut view.nodes[ino as usize];
+            node.perm = entry.perm;
+            node.uid = entry.uid;
+            node.gid = entry.gid;
+            node.mtime = entry.mtime;
+            node.link = entry.link;
+            if kind == InodeType::File {
+                node.size = entry.size;
+                node.data_offset = entry.data_offset;
+            } else if kind == InodeType::Symlink {
+                node.size = node.link.len() as u64;
+            }
+        }
+        Ok(view)
+    }
+
+    fn child(&self, dir: u32, name: &str) -> Option<u32> {
+        self.nodes[dir as usize]
+            .children
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| e.inode_no)
+    }
+
+    // 把 name 指向 ino，已有同名条目时替换
+    fn insert_child(&mut self, dir: u32, name: &str, ino: u32) {
+        let children = &mut self.nodes[dir as usize].children;
+        match children.iter_mut().find(|e| e.name == name) {
+            Some(e) => e.inode_no = ino,
+            None => children.push(DirEntry {
+                inode_no: ino,
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    // 确保 rel 的各级父目录存在（归档中可能没有单独的目录条目），返回直接父目录
+    fn make_parents(&mut self, rel: &str) -> Result<u32, FsError> {
+        let mut dir = 1;
+        let parts: Vec<&str> = rel.split('/').collect();
+        for part in &parts[..parts.len() - 1] {
+            dir = match self.child(dir, part) {
+                Some(ino) if self.nodes[ino as usize].kind == InodeType::Directory => ino,
+                Some(_) => return Err(FsError::NotADirectory),
+                None => {
+                    self.nodes.push(TarNode::new(InodeType::Directory));
+                    let ino = (self.nodes.len() - 1) as u32;
+                    self.insert_child(dir, part, ino);
+                    ino
+                }
+            };
+        }
+        Ok(dir)
+    }
+
+    fn lookup_rel(&self, rel: &str, follow_last: bool) -> Result<u32, FsError> {
+        self.lookup(&format!("/{}", rel), follow_last)
+    }
+
+    // 与镜像中的路径解析规则相同：跟随符号链接，".." 回到上一级
+    fn lookup(&self, path: &str, follow_last: bool) -> Result<u32, FsError> {
+        let mut pending: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .rev()
+            .map(String::from)
+            .collect();
+        let mut stack = vec![1u32];
+        let mut links = 0;
+
+        while let Some(part) = pending.pop() {
+            let current = *stack.last().unwrap();
+            match part.as_str() {
+                "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            if self.nodes[current as usize].kind != InodeType::Directory {
+                return Err(FsError::NotADirectory);
+            }
+            let next = self.child(current, &part).ok_or(FsError::NotFound)?;
+            let node = &self.nodes[next as usize];
+            if node.kind == InodeType::Symlink && (follow_last || !pending.is_empty()) {
+                links += 1;
+                if links > MAX_SYMLINK_DEPTH {
+                    return Err(FsError::TooManyLinks);
+                }
+                if node.link.starts_with('/') {
+                    stack.truncate(1);
+                }
+                pending.extend(
+                    node.link
+                        .split('/')
+                        .filter(|s| !s.is_empty())
+                        .rev()
+                        .map(String::from),
+                );
+            } else {
+                stack.push(next);
+            }
+        }
+        Ok(*stack.last().unwrap())
+    }
+
+    fn metadata_of(&self, ino: u32) -> Metadata {
+        let node = &self.nodes[ino as usize];
+        let nlinks = match node.kind {
+            InodeType::Directory => {
+                let subdirs = node
+                    .children
+                    .iter()
+                    .filter(|e| self.nodes[e.inode_no as usize].kind == InodeType::Directory)
+                    .count();
+                2 + subdirs as u32
+            }
+            _ => node.nlinks,
+        };
+        Metadata {
+            ino,
+            kind: node.kind,
+            size: node.size as u32,
+            blocks: Vec::new(),
+            indirect: 0,
+            nlinks,
+            perm: node.perm,
+            uid: node.uid,
+            gid: node.gid,
+            atime: node.mtime,
+            mtime: node.mtime,
+            ctime: node.mtime,
+            compressed: false,
+        }
+    }
+}
+
+impl ReadFs for TarFs {
+    fn stat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        let ino = self.lookup(path, true)?;
+        Ok(self.metadata_of(ino))
+    }
+
+    fn lstat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        let ino = self.lookup(path, false)?;
+        Ok(self.metadata_of(ino))
+    }
+
+    fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let ino = self.lookup(path, true)?;
+        let node = &self.nodes[ino as usize];
+        if node.kind != InodeType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(node
+            .children
+            .iter()
+            .map(|e| DirEntry {
+                inode_no: e.inode_no,
+                name: e.name.clone(),
+            })
+            .collect())
+    }
+
+    fn read_link(&mut self, path: &str) -> Result<String, FsError> {
+        let ino = self.lookup(path, false)?;
+        let node = &self.nodes[ino as usize];
+        if node.kind != InodeType::Symlink {
+            return Err(FsError::InvalidArgument);
+        }
+        Ok(node.link.clone())
+    }
+
+    fn read_at(&mut self, ino: u32, offset: usize, len: usize) -> Result<Vec<u8>, FsError> {
+        let node = self.nodes.get(ino as usize).ok_or(FsError::NotFound)?;
+        match node.kind {
+            InodeType::Directory => return Err(FsError::IsADirectory),
+            InodeType::Unused => return Err(FsError::NotFound),
+            _ => {}
+        }
+        let end = (offset.saturating_add(len) as u64).min(node.size);
+        if (offset as u64) >= end {
+            return Ok(Vec::new());
+        }
+        match node.kind {
+            InodeType::Symlink => Ok(node.link.as_bytes()[offset..end as usize].to_vec()),
+            InodeType::File => {
+                let mut data = vec![0u8; (end - offset as u64) as usize];
+                self.file
+                    .seek(SeekFrom::Start(node.data_offset + offset as u64))?;
+                self.file.read_exact(&mut data)?;
+                Ok(data)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::error::FsError;
+use crate::fs::{Metadata, MyFileSystem};
+use crate::layout::InodeType;
+use crate::{EXIT_FAILURE, fail, human_size, to_absolute_path, usage};
+use std::io::Write;
+
+// tree [-h] [-L DEPTH] [path...]
+// 以树形显示目录层次，每个条目前面标出类型和逻辑大小；不跟随符号链接，只显示它指向哪里
+
+const TREE_USAGE: &str = "tree [-h] [-L DEPTH] [path...]";
+
+#[derive(Default)]
+struct Counts {
+    dirs: usize,
+    files: usize,
+    links: usize,
+}
+
+struct Tree<'a> {
+    fs: &'a mut MyFileSystem,
+    human: bool,
+    max_depth: Option<usize>,
+    counts: Counts,
+    out: &'a mut Vec<u8>,
+}
+
+impl Tree<'_> {
+    // 条目前面的 [类型 大小]
+    fn label(&self, meta: &Metadata) -> String {
+        let kind = match meta.kind {
+            InodeType::Directory => "dir",
+            InodeType::File => "file",
+            InodeType::Symlink => "link",
+            InodeType::Unused => "?",
+        };
+        let size = if self.human {
+            human_size(meta.size as u64)
+        } else {
+            meta.size.to_string()
+        };
+        format!("[{:<4} {:>8}]", kind, size)
+    }
+
+    // 条目名字，目录加 /，符号链接加上目标
+    fn name(&mut self, path: &str, name: &str, meta: &Metadata) -> String {
+        match meta.kind {
+            InodeType::Directory => {
+                self.counts.dirs += 1;
+                format!("{}/", name.trim_end_matches('/'))
+            }
+            InodeType::Symlink => {
+                self.counts.links += 1;
+                let target = self.fs.read_link(path).unwrap_or_default();
+                format!("{} -> {}", name, target)
+            }
+            _ => {
+                self.counts.files += 1;
+                name.to_string()
+            }
+        }
+    }
+
+    // 输出 dir 的子条目；prefix 是上层留下的竖线和空白
+    fn children(&mut self, dir: &str, prefix: &str, depth: usize) -> Result<(), FsError> {
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return Ok(());
+        }
+        let entries = self.fs.read_dir(dir)?;
+        for (i, entry) in entries.iter().enumerate() {
+            let last = i + 1 == entries.len();
+            let path = MyFileSystem::join_path(dir, &entry.name);
+            let meta = self.fs.lstat(&path)?;
+            let label = self.label(&meta);
+            let name = self.name(&path, &entry.name, &meta);
+            let branch = if last { "└── " } else { "├── " };
+            writeln!(self.out, "{}{}{} {}", prefix, branch, label, name).unwrap();
+            if meta.kind == InodeType::Directory {
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                self.children(&path, &prefix, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn run_tree(fs: &mut MyFileSystem, args: &[&str], out: &mut Vec<u8>) -> i32 {
+    let mut human = false;
+    let mut max_depth = None;
+    let mut paths = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "-h" => human = true,
+            "-L" => {
+                match args.get(i + 1).and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if n > 0 => max_depth = Some(n),
+                    _ => return usage(TREE_USAGE),
+                }
+                i += 1;
+            }
+            arg if arg.starts_with('-') && arg.len() > 1 => return usage(TREE_USAGE),
+            arg => paths.push(arg),
+        }
+        i += 1;
+    }
+    if paths.is_empty() {
+        paths.push(".");
+    }
+
+    let mut tree = Tree {
+        fs,
+        human,
+        max_depth,
+        counts: Counts::default(),
+        out,
+    };
+    let mut status = 0;
+    for raw_path in paths {
+        let abs_path = to_absolute_path(&tree.fs.cwd_path, raw_path);
+        let meta = match tree.fs.lstat(&abs_path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                status = fail("tree", raw_path, e);
+                continue;
+            }
+        };
+        let label = tree.label(&meta);
+        let name = tree.name(&abs_path, raw_path, &meta);
+        writeln!(tree.out, "{} {}", label, name).unwrap();
+        if meta.kind == InodeType::Directory
+            && let Err(e) = tree.children(&abs_path, "", 1)
+        {
+            status = fail("tree", raw_path, e);
+        }
+    }
+
+    let Counts { dirs, files, links } = tree.counts;
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    writeln!(
+        tree.out,
+        "\n{}, {}, {}",
+        plural(dirs, "directory", "directories"),
+        plural(files, "file", "files"),
+        plural(links, "symlink", "symlinks")
+    )
+    .unwrap();
+    if status != 0 { EXIT_FAILURE } else { 0 }
+}
@@ -0,0 +1,92 @@
+use crate::error::FsError;
+use crate::fs::{Metadata, MyFileSystem};
+use crate::layout::InodeType;
+
+// 递归遍历目录树：逐个读取目录的 DirEntry，深度优先，同一目录中按目录项的顺序
+// 不跟随符号链接；遍历过程中不能修改目录树，需要修改时先收集条目再处理
+
+pub struct WalkEntry {
+    pub path: String,
+    pub meta: Metadata,
+}
+
+pub struct Walk<'a> {
+    fs: &'a mut MyFileSystem,
+    // 待访问的条目：(路径, 深度, 子条目是否已经展开)
+    stack: Vec<(String, usize, bool)>,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    contents_first: bool,
+}
+
+impl<'a> Walk<'a> {
+    pub fn new(fs: &'a mut MyFileSystem, root: &str) -> Self {
+        Walk {
+            fs,
+            stack: vec![(root.to_string(), 0, false)],
+            min_depth: 0,
+            max_depth: None,
+            contents_first: false,
+        }
+    }
+
+    // 只返回深度不小于 depth 的条目，起点的深度为 0
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    // 不进入深度超过 depth 的条目
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    // 先返回目录中的条目，再返回目录本身（后序），删除整棵树时需要这种顺序
+    pub fn contents_first(mut self, yes: bool) -> Self {
+        self.contents_first = yes;
+        self
+    }
+
+    // 把目录的子条目压栈，逆序压入使它们按目录项的顺序出栈
+    fn push_children(&mut self, path: &str, depth: usize) -> Result<(), FsError> {
+        let entries = self.fs.read_dir(path)?;
+        for entry in entries.iter().rev() {
+            let child = MyFileSystem::join_path(path, &entry.name);
+            self.stack.push((child, depth + 1, false));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Result<WalkEntry, FsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, depth, expanded) = self.stack.pop()?;
+            let meta = match self.fs.lstat(&path) {
+                Ok(meta) => meta,
+                Err(e) => return Some(Err(e)),
+            };
+            let descend = meta.kind == InodeType::Directory
+                && !expanded
+                && self.max_depth.is_none_or(|max| depth < max);
+            if descend {
+                if self.contents_first {
+                    self.stack.push((path.clone(), depth, true));
+                }
+                if let Err(e) = self.push_children(&path, depth) {
+                    return Some(Err(e));
+                }
+                if self.contents_first {
+                    continue;
+                }
+            }
+            if depth < self.min_depth {
+                continue;
+            }
+            return Some(Ok(WalkEntry { path, meta }));
+        }
+    }
+}